@@ -0,0 +1,317 @@
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
+use libp2p::Multiaddr;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Kind of network an address belongs to, computed offline from the address itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressClass {
+    /// Globally routable IP address
+    Public,
+    /// Address of a private network (RFC 1918, shared address space, unique local, docker
+    /// bridges) or unspecified address, unreachable from the internet
+    Private,
+    Loopback,
+    LinkLocal,
+    /// Address reached through a circuit relay
+    RelayCircuit,
+    /// Host name resolved at dial time
+    Dns,
+    /// Address without an IP address or host name (e.g. `/p2p`, `/unix`, `/memory` or
+    /// `/onion3`), left out of the address warnings
+    Other,
+}
+
+impl AddressClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Private => "private",
+            Self::Loopback => "loopback",
+            Self::LinkLocal => "link-local",
+            Self::RelayCircuit => "relay-circuit",
+            Self::Dns => "dns",
+            Self::Other => "other",
+        }
+    }
+
+    /// Whether peers on the internet may be able to dial the address
+    pub fn is_dialable_remotely(&self) -> bool {
+        matches!(self, Self::Public | Self::RelayCircuit | Self::Dns)
+    }
+
+    /// Whether the address tells about the network the node runs in
+    pub fn reveals_internal_network(&self) -> bool {
+        matches!(self, Self::Private | Self::LinkLocal)
+    }
+}
+
+impl Display for AddressClass {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl Serialize for AddressClass {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+fn classify_ipv4(ip: Ipv4Addr) -> AddressClass {
+    let is_shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0b1100_0000) == 64;
+
+    if ip.is_loopback() {
+        AddressClass::Loopback
+    } else if ip.is_link_local() {
+        AddressClass::LinkLocal
+    } else if ip.is_private() || is_shared || ip.is_unspecified() {
+        AddressClass::Private
+    } else {
+        AddressClass::Public
+    }
+}
+
+fn classify_ipv6(ip: Ipv6Addr) -> AddressClass {
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return classify_ipv4(ipv4);
+    }
+
+    let first_segment = ip.segments()[0];
+
+    if ip.is_loopback() {
+        AddressClass::Loopback
+    } else if (first_segment & 0xffc0) == 0xfe80 {
+        AddressClass::LinkLocal
+    } else if (first_segment & 0xfe00) == 0xfc00 || ip.is_unspecified() {
+        AddressClass::Private
+    } else {
+        AddressClass::Public
+    }
+}
+
+/// Classifies an address by its relay circuit (if any), else its first host protocol
+pub fn classify_address(address: &Multiaddr) -> AddressClass {
+    if address
+        .iter()
+        .any(|protocol| matches!(protocol, MultiaddrProtocol::P2pCircuit))
+    {
+        return AddressClass::RelayCircuit;
+    }
+
+    match address.iter().next() {
+        Some(MultiaddrProtocol::Ip4(ip)) => classify_ipv4(ip),
+        Some(MultiaddrProtocol::Ip6(ip)) => classify_ipv6(ip),
+        Some(
+            MultiaddrProtocol::Dns(name)
+            | MultiaddrProtocol::Dns4(name)
+            | MultiaddrProtocol::Dns6(name)
+            | MultiaddrProtocol::Dnsaddr(name),
+        ) if name == "localhost" || name.ends_with(".localhost") => AddressClass::Loopback,
+        Some(
+            MultiaddrProtocol::Dns(_)
+            | MultiaddrProtocol::Dns4(_)
+            | MultiaddrProtocol::Dns6(_)
+            | MultiaddrProtocol::Dnsaddr(_),
+        ) => AddressClass::Dns,
+        _ => AddressClass::Other,
+    }
+}
+
+/// Misconfiguration spotted in the addresses a node advertises
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressWarning {
+    /// None of the addresses can be dialed from the internet
+    NoPublicAddress,
+    /// Private or link-local addresses are advertised, revealing the node's internal network
+    InternalNetworkLeak(Vec<Multiaddr>),
+}
+
+impl AddressWarning {
+    /// Short machine-readable name of the warning
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NoPublicAddress => "no_public_address",
+            Self::InternalNetworkLeak(_) => "internal_network_leak",
+        }
+    }
+}
+
+impl Display for AddressWarning {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::NoPublicAddress => formatter
+                .write_str("Only non-public addresses advertised, unreachable from the internet"),
+            Self::InternalNetworkLeak(addresses) => write!(
+                formatter,
+                "{} private or link-local address(es) advertised, leaking internal topology",
+                addresses.len()
+            ),
+        }
+    }
+}
+
+impl Serialize for AddressWarning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let addresses = match self {
+            Self::NoPublicAddress => Vec::new(),
+            Self::InternalNetworkLeak(addresses) => {
+                addresses.iter().map(ToString::to_string).collect()
+            }
+        };
+        let mut state = serializer.serialize_struct("AddressWarning", 3)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("addresses", &addresses)?;
+
+        state.end()
+    }
+}
+
+/// Warnings about a set of advertised addresses, none if there are no classifiable addresses at
+/// all
+pub fn address_warnings(addresses: &[Multiaddr]) -> Vec<AddressWarning> {
+    let mut warnings = Vec::new();
+    let classified_addresses = addresses
+        .iter()
+        .map(|address| (address, classify_address(address)))
+        .filter(|(_, class)| *class != AddressClass::Other)
+        .collect::<Vec<_>>();
+
+    if classified_addresses.is_empty() {
+        return warnings;
+    }
+
+    if !classified_addresses
+        .iter()
+        .any(|(_, class)| class.is_dialable_remotely())
+    {
+        warnings.push(AddressWarning::NoPublicAddress);
+    }
+
+    let internal_addresses = classified_addresses
+        .iter()
+        .filter(|(_, class)| class.reveals_internal_network())
+        .map(|(address, _)| (*address).clone())
+        .collect::<Vec<_>>();
+
+    if !internal_addresses.is_empty() {
+        warnings.push(AddressWarning::InternalNetworkLeak(internal_addresses));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(address: &str) -> AddressClass {
+        classify_address(&address.parse().unwrap())
+    }
+
+    fn warnings(addresses: &[&str]) -> Vec<AddressWarning> {
+        address_warnings(
+            &addresses
+                .iter()
+                .map(|address| address.parse().unwrap())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn classifies_ipv4_addresses() {
+        assert_eq!(classify("/ip4/8.8.8.8/tcp/30333"), AddressClass::Public);
+        assert_eq!(classify("/ip4/10.0.0.1/tcp/30333"), AddressClass::Private);
+        assert_eq!(
+            classify("/ip4/192.168.1.1/tcp/30333"),
+            AddressClass::Private
+        );
+        // Docker bridge
+        assert_eq!(classify("/ip4/172.17.0.1/tcp/30333"), AddressClass::Private);
+        // Shared address space (100.64.0.0/10)
+        assert_eq!(classify("/ip4/100.64.0.1/tcp/30333"), AddressClass::Private);
+        assert_eq!(
+            classify("/ip4/100.127.255.254/tcp/30333"),
+            AddressClass::Private
+        );
+        assert_eq!(classify("/ip4/100.128.0.1/tcp/30333"), AddressClass::Public);
+        assert_eq!(classify("/ip4/0.0.0.0/tcp/30333"), AddressClass::Private);
+        assert_eq!(classify("/ip4/127.0.0.1/tcp/30333"), AddressClass::Loopback);
+        assert_eq!(
+            classify("/ip4/169.254.1.1/tcp/30333"),
+            AddressClass::LinkLocal
+        );
+    }
+
+    #[test]
+    fn classifies_ipv6_addresses() {
+        assert_eq!(
+            classify("/ip6/2001:4860:4860::8888/tcp/30333"),
+            AddressClass::Public
+        );
+        assert_eq!(classify("/ip6/fd00::1/tcp/30333"), AddressClass::Private);
+        assert_eq!(classify("/ip6/::/tcp/30333"), AddressClass::Private);
+        assert_eq!(classify("/ip6/::1/tcp/30333"), AddressClass::Loopback);
+        assert_eq!(classify("/ip6/fe80::1/tcp/30333"), AddressClass::LinkLocal);
+        assert_eq!(
+            classify("/ip6/::ffff:127.0.0.1/tcp/30333"),
+            AddressClass::Loopback
+        );
+        assert_eq!(
+            classify("/ip6/::ffff:192.168.1.1/tcp/30333"),
+            AddressClass::Private
+        );
+    }
+
+    #[test]
+    fn classifies_other_addresses() {
+        assert_eq!(
+            classify("/ip4/8.8.8.8/tcp/30333/p2p/12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2/p2p-circuit"),
+            AddressClass::RelayCircuit
+        );
+        assert_eq!(
+            classify("/dns4/boot.example.com/tcp/443/wss"),
+            AddressClass::Dns
+        );
+        assert_eq!(classify("/dns/localhost/tcp/30333"), AddressClass::Loopback);
+        assert_eq!(
+            classify("/p2p/12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"),
+            AddressClass::Other
+        );
+        assert_eq!(classify("/unix/tmp%2Fnode.sock"), AddressClass::Other);
+        assert_eq!(classify("/memory/1234"), AddressClass::Other);
+        assert_eq!(
+            classify("/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234"),
+            AddressClass::Other
+        );
+    }
+
+    #[test]
+    fn warns_about_advertised_addresses() {
+        assert!(warnings(&[]).is_empty());
+        assert!(warnings(&["/ip4/8.8.8.8/tcp/30333", "/memory/1234"]).is_empty());
+        assert!(warnings(&["/memory/1234", "/unix/tmp%2Fnode.sock"]).is_empty());
+        assert_eq!(
+            warnings(&["/ip4/127.0.0.1/tcp/30333", "/memory/1234"]),
+            vec![AddressWarning::NoPublicAddress]
+        );
+        assert_eq!(
+            warnings(&["/ip4/8.8.8.8/tcp/30333", "/ip4/172.17.0.1/tcp/30333"]),
+            vec![AddressWarning::InternalNetworkLeak(vec![
+                "/ip4/172.17.0.1/tcp/30333".parse().unwrap()
+            ])]
+        );
+        assert_eq!(
+            warnings(&["/ip4/10.0.0.1/tcp/30333", "/ip6/fe80::1/tcp/30333"]),
+            vec![
+                AddressWarning::NoPublicAddress,
+                AddressWarning::InternalNetworkLeak(vec![
+                    "/ip4/10.0.0.1/tcp/30333".parse().unwrap(),
+                    "/ip6/fe80::1/tcp/30333".parse().unwrap(),
+                ]),
+            ]
+        );
+    }
+}
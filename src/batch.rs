@@ -0,0 +1,674 @@
+use crate::keys::parse_peer_id;
+use crate::logging::log_debug;
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{LookupTimeouts, PeerInfo, PeerLookupError, PeerLookupResult};
+use futures::StreamExt;
+use libp2p::core::upgrade::UpgradeError;
+use libp2p::core::ConnectedPoint;
+use libp2p::identify::{Event as IdentityEvent, Info as IdentityInfo};
+use libp2p::kad::{
+    GetClosestPeersError as KademliaGetClosestPeersError,
+    GetClosestPeersOk as KademliaGetClosestPeersOk, KademliaEvent, QueryId as KademliaQueryId,
+    QueryResult as KademliaQueryResult,
+};
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::{ConnectionHandlerUpgrErr, DialError as SwarmDialError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Debug, Display, Formatter, Result as FormatterResult};
+use std::io::BufRead;
+use std::str::FromStr;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+/// Single target of a batch lookup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchTarget {
+    /// Dial the peer on a known address
+    Address(Multiaddr),
+    /// Find the peer by its ID on the DHT network of the batch
+    PeerId(PeerId),
+}
+
+#[derive(Deserialize)]
+struct JsonBatchTarget {
+    address: Option<String>,
+    peer_id: Option<String>,
+}
+
+impl FromStr for BatchTarget {
+    type Err = String;
+
+    /// Parses either a multi address, a peer ID, or a JSON object holding one of them as
+    /// `"address"` or `"peer_id"`
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let source = source.trim();
+
+        if source.starts_with('{') {
+            let json_target = serde_json::from_str::<JsonBatchTarget>(source)
+                .map_err(|error| format!("Bad JSON target \"{source}\": {error}"))?;
+
+            return match (json_target.address, json_target.peer_id) {
+                (Some(address), _) => address.parse(),
+                (None, Some(peer_id)) => peer_id.parse(),
+                (None, None) => {
+                    Err("JSON target needs either \"address\" or \"peer_id\".".to_owned())
+                }
+            };
+        }
+
+        if source.starts_with('/') {
+            source
+                .parse()
+                .map(Self::Address)
+                .map_err(|error| format!("Bad multi address \"{source}\": {error}"))
+        } else {
+            parse_peer_id(source).map(Self::PeerId)
+        }
+    }
+}
+
+impl Display for BatchTarget {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Address(address) => write!(formatter, "{address}"),
+            Self::PeerId(peer_id) => write!(formatter, "{peer_id}"),
+        }
+    }
+}
+
+impl BatchTarget {
+    /// Peer ID of the target, if known before connecting to it
+    pub fn peer_id(&self) -> Option<PeerId> {
+        match self {
+            Self::Address(address) => match address.iter().last() {
+                Some(MultiaddrProtocol::P2p(multihash)) => PeerId::from_multihash(multihash).ok(),
+                _ => None,
+            },
+            Self::PeerId(peer_id) => Some(*peer_id),
+        }
+    }
+}
+
+/// Parses batch targets, one per line, skipping blank lines and `#` comments
+pub fn parse_batch_targets(reader: impl BufRead) -> Result<Vec<BatchTarget>, String> {
+    let mut targets = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.map_err(|error| format!("Can't read targets: {error}"))?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        targets.push(line.parse()?);
+    }
+
+    Ok(targets)
+}
+
+/// Outcome of the lookup of a single batch target
+pub struct BatchLookupRecord {
+    pub target: BatchTarget,
+    pub result: PeerLookupResult,
+}
+
+enum LookupPhase {
+    WalkingDht(KademliaQueryId),
+    Dialing,
+    Identifying {
+        peer_id: PeerId,
+        established_in: Duration,
+    },
+}
+
+struct InFlightLookup {
+    target: BatchTarget,
+    phase: LookupPhase,
+    started_at: Instant,
+    phase_started_at: Instant,
+}
+
+impl InFlightLookup {
+    fn new(target: BatchTarget, phase: LookupPhase) -> Self {
+        let started_at = Instant::now();
+
+        Self {
+            target,
+            phase,
+            started_at,
+            phase_started_at: started_at,
+        }
+    }
+
+    fn enter(&mut self, phase: LookupPhase) {
+        self.phase = phase;
+        self.phase_started_at = Instant::now();
+    }
+
+    fn is_dialed_by(&self, peer_id: &PeerId, endpoint: &ConnectedPoint) -> bool {
+        match (&self.phase, &self.target) {
+            (LookupPhase::Dialing, BatchTarget::Address(address)) => match self.target.peer_id() {
+                Some(target_peer_id) => target_peer_id == *peer_id,
+                None => {
+                    matches!(endpoint, ConnectedPoint::Dialer { address: dialed, .. } if dialed == address)
+                }
+            },
+            (
+                LookupPhase::Dialing | LookupPhase::WalkingDht(_),
+                BatchTarget::PeerId(target_peer_id),
+            ) => target_peer_id == peer_id,
+            _ => false,
+        }
+    }
+
+    fn is_failed_by(&self, peer_id: Option<&PeerId>, error: &SwarmDialError) -> bool {
+        if !matches!(self.phase, LookupPhase::Dialing) {
+            return false;
+        }
+
+        match (peer_id, &self.target) {
+            (Some(peer_id), _) => self.target.peer_id().as_ref() == Some(peer_id),
+            (None, BatchTarget::Address(address)) => match error {
+                SwarmDialError::Transport(errors) => errors
+                    .iter()
+                    .any(|(failed_address, _)| failed_address == address),
+                _ => false,
+            },
+            (None, BatchTarget::PeerId(_)) => false,
+        }
+    }
+
+    /// Peer ID of the target, once known
+    fn target_peer_id(&self) -> Option<PeerId> {
+        match self.phase {
+            LookupPhase::Identifying { peer_id, .. } => Some(peer_id),
+            _ => self.target.peer_id(),
+        }
+    }
+
+    fn is_identifying(&self, peer: &PeerId) -> bool {
+        matches!(&self.phase, LookupPhase::Identifying { peer_id, .. } if peer_id == peer)
+    }
+
+    fn expiry_error(&self, timeouts: &LookupTimeouts) -> Option<PeerLookupError> {
+        if let Some(total_timeout) = timeouts.total {
+            if self.started_at.elapsed() >= total_timeout {
+                return Some(PeerLookupError::LookupTimeout(total_timeout));
+            }
+        }
+
+        let phase_elapsed = self.phase_started_at.elapsed();
+
+        match self.phase {
+            LookupPhase::WalkingDht(_) if phase_elapsed >= timeouts.dht_query => {
+                Some(PeerLookupError::DhtQueryTimeout(timeouts.dht_query))
+            }
+            LookupPhase::Dialing if phase_elapsed >= timeouts.dial => {
+                Some(PeerLookupError::DialTimeout(timeouts.dial))
+            }
+            LookupPhase::Identifying { .. } if phase_elapsed >= timeouts.identify => {
+                Some(PeerLookupError::IdentifyTimeout(timeouts.identify))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Looks up many peers concurrently over a single swarm
+pub struct BatchLookupClient {
+    swarm: Swarm<PeerLookupBehaviour>,
+    pending: VecDeque<BatchTarget>,
+    in_flight: Vec<InFlightLookup>,
+    identified: HashMap<PeerId, IdentityInfo>,
+    /// Bootnodes of the DHT network, kept connected to route the walks
+    bootnodes: HashSet<PeerId>,
+    /// Targets released while walks were running, disconnected once they're done
+    pending_releases: Vec<PeerId>,
+    parallelism: usize,
+    timeouts: LookupTimeouts,
+}
+
+impl BatchLookupClient {
+    /// Starts configuring a batch lookup client for the given targets
+    pub fn builder(targets: Vec<BatchTarget>) -> BatchLookupClientBuilder {
+        BatchLookupClientBuilder::new(targets)
+    }
+
+    fn finish(
+        &mut self,
+        mut outcomes: Vec<(usize, PeerLookupResult)>,
+        on_record: &mut impl FnMut(BatchLookupRecord),
+    ) {
+        // Removing from the highest index keeps the lower indices valid
+        outcomes.sort_by(|(left, _), (right, _)| right.cmp(left));
+
+        for (index, result) in outcomes {
+            let lookup = self.in_flight.swap_remove(index);
+
+            // Targets are released as they are recorded
+            if let Some(peer_id) = lookup.target_peer_id() {
+                self.release(peer_id);
+            }
+
+            on_record(BatchLookupRecord {
+                target: lookup.target,
+                result,
+            });
+        }
+    }
+
+    /// Whether a lookup is walking the DHT, possibly through any connected peer
+    fn is_walking_dht(&self) -> bool {
+        self.in_flight
+            .iter()
+            .any(|lookup| matches!(lookup.phase, LookupPhase::WalkingDht(_)))
+    }
+
+    /// Disconnects from a recorded target, unless it's a bootnode or another lookup still needs
+    /// it, and only once no walk may be querying it
+    fn release(&mut self, peer_id: PeerId) {
+        if self.bootnodes.contains(&peer_id)
+            || self
+                .in_flight
+                .iter()
+                .any(|lookup| lookup.target_peer_id() == Some(peer_id))
+        {
+            return;
+        }
+
+        if self.is_walking_dht() {
+            self.pending_releases.push(peer_id);
+
+            return;
+        }
+
+        if self.swarm.disconnect_peer_id(peer_id).is_ok() {
+            log_debug!("Disconnected from \"{peer_id}\"");
+        }
+    }
+
+    fn release_pending(&mut self) {
+        if self.is_walking_dht() {
+            return;
+        }
+
+        for peer_id in std::mem::take(&mut self.pending_releases) {
+            self.release(peer_id);
+        }
+    }
+
+    fn start_pending(&mut self, on_record: &mut impl FnMut(BatchLookupRecord)) {
+        while self.in_flight.len() < self.parallelism {
+            let Some(target) = self.pending.pop_front() else {
+                return;
+            };
+
+            match &target {
+                BatchTarget::Address(address) => {
+                    let dial_opts = match target.peer_id() {
+                        Some(peer_id) => DialOpts::peer_id(peer_id)
+                            .addresses(vec![address.clone()])
+                            .condition(PeerCondition::Always)
+                            .build(),
+                        None => DialOpts::unknown_peer_id().address(address.clone()).build(),
+                    };
+
+                    match self.swarm.dial(dial_opts) {
+                        Ok(()) => self
+                            .in_flight
+                            .push(InFlightLookup::new(target, LookupPhase::Dialing)),
+                        Err(error) => on_record(BatchLookupRecord {
+                            target,
+                            result: Err(error.into()),
+                        }),
+                    }
+                }
+                BatchTarget::PeerId(peer_id) => {
+                    let query_id = self.swarm.behaviour_mut().dht.get_closest_peers(*peer_id);
+                    self.in_flight.push(InFlightLookup::new(
+                        target,
+                        LookupPhase::WalkingDht(query_id),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn on_dht_walked(
+        &mut self,
+        index: usize,
+        query_result: Result<KademliaGetClosestPeersOk, KademliaGetClosestPeersError>,
+    ) -> Option<PeerLookupResult> {
+        let target_peer_id = self.in_flight[index]
+            .target
+            .peer_id()
+            .expect("Programmatic error: DHT lookup without peer ID!");
+        let (peers, timed_out) = match query_result {
+            Ok(KademliaGetClosestPeersOk { peers, .. }) => (peers, false),
+            Err(KademliaGetClosestPeersError::Timeout { peers, .. }) => (peers, true),
+        };
+
+        if !peers.contains(&target_peer_id) {
+            return Some(Err(if timed_out {
+                PeerLookupError::DhtQueryTimeout(self.timeouts.dht_query)
+            } else {
+                PeerLookupError::FailedToFindPeerOnDht
+            }));
+        }
+
+        if self.swarm.is_connected(&target_peer_id) {
+            if let Some(info) = self.identified.get(&target_peer_id) {
+                return Some(Ok(PeerInfo::new(
+                    info.clone(),
+                    Duration::ZERO,
+                    Duration::ZERO,
+                )));
+            }
+
+            self.in_flight[index].enter(LookupPhase::Identifying {
+                peer_id: target_peer_id,
+                established_in: Duration::ZERO,
+            });
+
+            return None;
+        }
+
+        if let Err(error) = self.swarm.dial(target_peer_id) {
+            return Some(Err(error.into()));
+        }
+
+        self.in_flight[index].enter(LookupPhase::Dialing);
+
+        None
+    }
+
+    fn handle_event<THandlerErr: Debug>(
+        &mut self,
+        event: SwarmEvent<PeerLookupBehaviourEvent, THandlerErr>,
+        on_record: &mut impl FnMut(BatchLookupRecord),
+    ) {
+        match event {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                established_in,
+                ..
+            } => {
+                for lookup in &mut self.in_flight {
+                    if lookup.is_dialed_by(&peer_id, &endpoint) {
+                        lookup.enter(LookupPhase::Identifying {
+                            peer_id,
+                            established_in,
+                        });
+                    }
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error } => {
+                if let Some(index) = self
+                    .in_flight
+                    .iter()
+                    .position(|lookup| lookup.is_failed_by(peer_id.as_ref(), &error))
+                {
+                    self.finish(vec![(index, Err(error.into()))], on_record);
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: KademliaQueryResult::GetClosestPeers(query_result),
+                    ..
+                },
+            )) => {
+                if let Some(index) = self.in_flight.iter().position(
+                    |lookup| matches!(lookup.phase, LookupPhase::WalkingDht(query_id) if query_id == id),
+                ) {
+                    if let Some(result) = self.on_dht_walked(index, query_result) {
+                        self.finish(vec![(index, result)], on_record);
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(
+                IdentityEvent::Received { peer_id, info },
+            )) => {
+                let outcomes = self
+                    .in_flight
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, lookup)| match lookup.phase {
+                        LookupPhase::Identifying {
+                            peer_id: identified_peer_id,
+                            established_in,
+                        } if identified_peer_id == peer_id => Some((
+                            index,
+                            Ok(PeerInfo::new(
+                                info.clone(),
+                                established_in,
+                                lookup.phase_started_at.elapsed(),
+                            )),
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                self.identified.insert(peer_id, info);
+                self.finish(outcomes, on_record);
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(
+                IdentityEvent::Error {
+                    peer_id,
+                    error: ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(error)),
+                },
+            )) => {
+                let outcomes = self
+                    .in_flight
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, lookup)| lookup.is_identifying(&peer_id))
+                    .map(|(index, _)| {
+                        (
+                            index,
+                            Err(PeerLookupError::ProtocolMismatch(format!(
+                                "identify protocol can't be negotiated ({error})"
+                            ))),
+                        )
+                    })
+                    .collect();
+                self.finish(outcomes, on_record);
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+
+    fn expire_lookups(&mut self, on_record: &mut impl FnMut(BatchLookupRecord)) {
+        let outcomes = self
+            .in_flight
+            .iter()
+            .enumerate()
+            .filter_map(|(index, lookup)| {
+                lookup
+                    .expiry_error(&self.timeouts)
+                    .map(|error| (index, Err(error)))
+            })
+            .collect();
+        self.finish(outcomes, on_record);
+    }
+
+    /// Runs every lookup, reporting each outcome through `on_record` as soon as it's known
+    pub async fn do_lookup(mut self, mut on_record: impl FnMut(BatchLookupRecord)) {
+        let mut deadline_check = interval(Duration::from_millis(250));
+        deadline_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            self.start_pending(&mut on_record);
+            self.release_pending();
+
+            if self.in_flight.is_empty() {
+                return;
+            }
+
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_event(event, &mut on_record),
+                _ = deadline_check.tick() => self.expire_lookups(&mut on_record),
+            }
+        }
+    }
+}
+
+/// Explicit configuration of a [`BatchLookupClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct BatchLookupClientBuilder {
+    targets: Vec<BatchTarget>,
+    dht_network: Option<DHTNetwork>,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    parallelism: usize,
+}
+
+impl BatchLookupClientBuilder {
+    pub fn new(targets: Vec<BatchTarget>) -> Self {
+        Self {
+            targets,
+            dht_network: None,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+            parallelism: 16,
+        }
+    }
+
+    /// DHT network used to find the targets given by peer ID
+    pub fn dht_network(mut self, dht_network: DHTNetwork) -> Self {
+        self.dht_network = Some(dht_network);
+
+        self
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    /// Maximum number of lookups running at the same time
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+
+        self
+    }
+
+    pub fn build(self) -> Result<BatchLookupClient, PeerLookupError> {
+        let bootnodes = self
+            .dht_network
+            .iter()
+            .flat_map(DHTNetwork::bootnodes)
+            .map(|(_, peer_id)| *peer_id)
+            .collect();
+
+        Ok(BatchLookupClient {
+            swarm: create_swarm(
+                &self.node_config,
+                self.dht_network.as_ref(),
+                self.timeouts.dht_query,
+            )?,
+            pending: self.targets.into(),
+            in_flight: Vec::new(),
+            identified: HashMap::new(),
+            bootnodes,
+            pending_releases: Vec::new(),
+            parallelism: self.parallelism,
+            timeouts: self.timeouts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER_ID: &str = "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2";
+    const ADDRESS: &str = "/ip4/108.136.33.203/tcp/30333";
+
+    fn peer_id() -> PeerId {
+        PEER_ID.parse().unwrap()
+    }
+
+    fn address() -> Multiaddr {
+        format!("{ADDRESS}/p2p/{PEER_ID}").parse().unwrap()
+    }
+
+    #[test]
+    fn parses_plain_targets() {
+        assert_eq!(
+            format!("{ADDRESS}/p2p/{PEER_ID}").parse::<BatchTarget>(),
+            Ok(BatchTarget::Address(address()))
+        );
+        assert_eq!(
+            format!("  {PEER_ID}\t").parse::<BatchTarget>(),
+            Ok(BatchTarget::PeerId(peer_id()))
+        );
+        assert_eq!(ADDRESS.parse::<BatchTarget>().unwrap().peer_id(), None);
+        assert_eq!(
+            address()
+                .to_string()
+                .parse::<BatchTarget>()
+                .unwrap()
+                .peer_id(),
+            Some(peer_id())
+        );
+        assert!("/ip4/108.136.33.203/tcp".parse::<BatchTarget>().is_err());
+        assert!("not-a-peer".parse::<BatchTarget>().is_err());
+    }
+
+    #[test]
+    fn parses_json_targets() {
+        assert_eq!(
+            format!(r#"{{"address": "{ADDRESS}/p2p/{PEER_ID}"}}"#).parse::<BatchTarget>(),
+            Ok(BatchTarget::Address(address()))
+        );
+        assert_eq!(
+            format!(r#"{{"peer_id": "{PEER_ID}", "name": "validator-1"}}"#).parse::<BatchTarget>(),
+            Ok(BatchTarget::PeerId(peer_id()))
+        );
+        // The address wins over the peer ID
+        assert_eq!(
+            format!(r#"{{"peer_id": "{PEER_ID}", "address": "{ADDRESS}"}}"#).parse::<BatchTarget>(),
+            Ok(BatchTarget::Address(ADDRESS.parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json_targets() {
+        assert_eq!(
+            "{}".parse::<BatchTarget>(),
+            Err("JSON target needs either \"address\" or \"peer_id\".".to_owned())
+        );
+        assert!(r#"{"peer_id": "#.parse::<BatchTarget>().is_err());
+        assert!(r#"{"peer_id": 42}"#.parse::<BatchTarget>().is_err());
+        assert!(r#"{"address": "not-an-address"}"#.parse::<BatchTarget>().is_err());
+    }
+
+    #[test]
+    fn parses_target_lines() {
+        let lines = format!(
+            "# Bootnodes\n\n{ADDRESS}/p2p/{PEER_ID}\n   \n  # {PEER_ID}\n{{\"peer_id\": \"{PEER_ID}\"}}\n"
+        );
+
+        assert_eq!(
+            parse_batch_targets(lines.as_bytes()),
+            Ok(vec![
+                BatchTarget::Address(address()),
+                BatchTarget::PeerId(peer_id())
+            ])
+        );
+        assert_eq!(parse_batch_targets("\n# None\n".as_bytes()), Ok(Vec::new()));
+        assert!(parse_batch_targets(format!("{PEER_ID}\n{{\n").as_bytes()).is_err());
+    }
+}
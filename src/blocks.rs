@@ -0,0 +1,795 @@
+use crate::chain::{chain_protocol_names, hex_hash, BlockHash};
+use crate::logging::{log_debug, log_info};
+use crate::node::{PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::protobuf::{put_bytes_field, put_varint_field, FieldReader, FieldValue};
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite, StreamExt};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::request_response::{
+    Codec as RequestResponseCodec, Event as RequestResponseEvent,
+    Message as RequestResponseMessage, OutboundFailure, ProtocolName,
+};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use std::str::FromStr;
+use thiserror::Error;
+use tokio::time::{timeout, Duration, Instant};
+
+const BLOCK_REQUEST_PROTOCOL_SUFFIX: &str = "/sync/2";
+/// Maximum size of a block response accepted by Substrate
+const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+/// Header, body and justification bits of Substrate's `BlockAttributes`, sent as the most
+/// significant byte of the `fields` of the request
+const BLOCK_ATTRIBUTES: u64 = 0b0001_0011 << 24;
+const BLOCK_REQUEST_FIELDS_FIELD: u32 = 1;
+const BLOCK_REQUEST_HASH_FIELD: u32 = 2;
+const BLOCK_REQUEST_NUMBER_FIELD: u32 = 3;
+const BLOCK_REQUEST_MAX_BLOCKS_FIELD: u32 = 6;
+const BLOCK_REQUEST_MULTIPLE_JUSTIFICATIONS_FIELD: u32 = 7;
+const BLOCK_RESPONSE_BLOCKS_FIELD: u32 = 1;
+const BLOCK_DATA_HASH_FIELD: u32 = 1;
+const BLOCK_DATA_HEADER_FIELD: u32 = 2;
+const BLOCK_DATA_BODY_FIELD: u32 = 3;
+const BLOCK_DATA_JUSTIFICATION_FIELD: u32 = 6;
+const BLOCK_DATA_EMPTY_JUSTIFICATION_FIELD: u32 = 7;
+const BLOCK_DATA_JUSTIFICATIONS_FIELD: u32 = 8;
+
+/// Block to be fetched, by number or hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockId {
+    /// Block number, encoded as a `u32` like GoRo's
+    Number(u32),
+    Hash(BlockHash),
+}
+
+impl FromStr for BlockId {
+    type Err = String;
+
+    /// Parses a decimal block number or a `0x`-prefixed hex block hash
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.strip_prefix("0x") {
+            Some(hex_hash) => {
+                let hash_bytes = hex::decode(hex_hash).map_err(|error| error.to_string())?;
+                let hash = hash_bytes.try_into().map_err(|hash_bytes: Vec<u8>| {
+                    format!(
+                        "Block hash must be 32 bytes long, got {} bytes",
+                        hash_bytes.len()
+                    )
+                })?;
+
+                Ok(Self::Hash(hash))
+            }
+            None => source.parse().map(Self::Number).map_err(|_| {
+                format!("Expected a block number or a 0x-prefixed block hash, got \"{source}\"")
+            }),
+        }
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Number(number) => write!(formatter, "#{number}"),
+            Self::Hash(hash) => formatter.write_str(&hex_hash(hash)),
+        }
+    }
+}
+
+/// Reads SCALE-encoded values off the front of a message
+struct ScaleReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> ScaleReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.input.len() < length {
+            return Err(format!(
+                "Expected {length} bytes of SCALE data, got {}",
+                self.input.len()
+            ));
+        }
+
+        let (bytes, rest) = self.input.split_at(length);
+        self.input = rest;
+
+        Ok(bytes)
+    }
+
+    fn take_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take_hash(&mut self) -> Result<BlockHash, String> {
+        Ok(self
+            .take(32)?
+            .try_into()
+            .expect("Programmatic error: bad slice!"))
+    }
+
+    fn take_engine_id(&mut self) -> Result<[u8; 4], String> {
+        Ok(self
+            .take(4)?
+            .try_into()
+            .expect("Programmatic error: bad slice!"))
+    }
+
+    fn take_compact(&mut self) -> Result<u64, String> {
+        let first_byte = self.take_u8()?;
+        let mut bytes = [0; 8];
+
+        match first_byte & 0b11 {
+            0b00 => return Ok(u64::from(first_byte >> 2)),
+            0b01 => bytes[..2].copy_from_slice(&[first_byte, self.take_u8()?]),
+            0b10 => {
+                bytes[0] = first_byte;
+                bytes[1..4].copy_from_slice(self.take(3)?);
+            }
+            _ => {
+                let length = usize::from(first_byte >> 2) + 4;
+
+                if length > 8 {
+                    return Err(format!("Compact integer of {length} bytes overflows"));
+                }
+
+                bytes[..length].copy_from_slice(self.take(length)?);
+
+                return Ok(u64::from_le_bytes(bytes));
+            }
+        }
+
+        Ok(u64::from_le_bytes(bytes) >> 2)
+    }
+
+    /// Skips a length-prefixed byte vector
+    fn skip_bytes(&mut self) -> Result<(), String> {
+        let length = self.take_compact()?;
+        self.take(usize::try_from(length).map_err(|error| error.to_string())?)?;
+
+        Ok(())
+    }
+}
+
+/// Item of a header digest, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestItem {
+    Other,
+    Consensus([u8; 4]),
+    Seal([u8; 4]),
+    PreRuntime([u8; 4]),
+    RuntimeEnvironmentUpdated,
+}
+
+impl DigestItem {
+    fn decode(reader: &mut ScaleReader) -> Result<Self, String> {
+        let item = match reader.take_u8()? {
+            0 => Self::Other,
+            4 => Self::Consensus(reader.take_engine_id()?),
+            5 => Self::Seal(reader.take_engine_id()?),
+            6 => Self::PreRuntime(reader.take_engine_id()?),
+            8 => return Ok(Self::RuntimeEnvironmentUpdated),
+            tag => return Err(format!("Unknown digest item {tag}")),
+        };
+        reader.skip_bytes()?;
+
+        Ok(item)
+    }
+}
+
+impl Display for DigestItem {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        let (kind, engine_id) = match self {
+            Self::Other => return formatter.write_str("other"),
+            Self::RuntimeEnvironmentUpdated => {
+                return formatter.write_str("runtime_environment_updated")
+            }
+            Self::Consensus(engine_id) => ("consensus", engine_id),
+            Self::Seal(engine_id) => ("seal", engine_id),
+            Self::PreRuntime(engine_id) => ("pre_runtime", engine_id),
+        };
+
+        write!(formatter, "{kind}({})", String::from_utf8_lossy(engine_id))
+    }
+}
+
+/// Header of a Substrate block, with `u32` or `u64` block numbers
+#[derive(Debug, Clone)]
+pub struct BlockHeader {
+    pub parent_hash: BlockHash,
+    pub number: u64,
+    pub state_root: BlockHash,
+    pub extrinsics_root: BlockHash,
+    pub digest: Vec<DigestItem>,
+}
+
+impl BlockHeader {
+    fn decode(message: &[u8]) -> Result<Self, String> {
+        let mut reader = ScaleReader { input: message };
+        let parent_hash = reader.take_hash()?;
+        let number = reader.take_compact()?;
+        let state_root = reader.take_hash()?;
+        let extrinsics_root = reader.take_hash()?;
+        let digest = (0..reader.take_compact()?)
+            .map(|_| DigestItem::decode(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !reader.input.is_empty() {
+            return Err(format!(
+                "{} trailing bytes after header",
+                reader.input.len()
+            ));
+        }
+
+        Ok(Self {
+            parent_hash,
+            number,
+            state_root,
+            extrinsics_root,
+            digest,
+        })
+    }
+}
+
+/// Block of a block response, its header left encoded
+#[derive(Debug, Clone, Default)]
+pub struct BlockData {
+    pub hash: Vec<u8>,
+    /// SCALE-encoded header, empty if not returned
+    pub header: Vec<u8>,
+    pub extrinsics: usize,
+    pub has_justification: bool,
+}
+
+fn decode_block_response(message: &[u8]) -> Result<Vec<BlockData>, String> {
+    let mut blocks = Vec::new();
+
+    for field in FieldReader::new(message) {
+        let (BLOCK_RESPONSE_BLOCKS_FIELD, FieldValue::Bytes(block)) = field? else {
+            continue;
+        };
+        let mut block_data = BlockData::default();
+
+        for block_field in FieldReader::new(block) {
+            match block_field? {
+                (BLOCK_DATA_HASH_FIELD, FieldValue::Bytes(hash)) => block_data.hash = hash.to_vec(),
+                (BLOCK_DATA_HEADER_FIELD, FieldValue::Bytes(header)) => {
+                    block_data.header = header.to_vec()
+                }
+                (BLOCK_DATA_BODY_FIELD, FieldValue::Bytes(_)) => block_data.extrinsics += 1,
+                (
+                    BLOCK_DATA_JUSTIFICATION_FIELD | BLOCK_DATA_JUSTIFICATIONS_FIELD,
+                    FieldValue::Bytes(justification),
+                ) if !justification.is_empty() => block_data.has_justification = true,
+                (BLOCK_DATA_EMPTY_JUSTIFICATION_FIELD, FieldValue::Varint(is_empty)) => {
+                    block_data.has_justification |= is_empty != 0
+                }
+                _ => {}
+            }
+        }
+
+        blocks.push(block_data);
+    }
+
+    Ok(blocks)
+}
+
+/// Encodes a `BlockRequest` of the header, body and justification of a single block
+fn encode_block_request(block_id: BlockId) -> Vec<u8> {
+    let mut message = Vec::new();
+    put_varint_field(&mut message, BLOCK_REQUEST_FIELDS_FIELD, BLOCK_ATTRIBUTES);
+
+    match block_id {
+        BlockId::Number(number) => put_bytes_field(
+            &mut message,
+            BLOCK_REQUEST_NUMBER_FIELD,
+            &number.to_le_bytes(),
+        ),
+        BlockId::Hash(hash) => put_bytes_field(&mut message, BLOCK_REQUEST_HASH_FIELD, &hash),
+    }
+
+    put_varint_field(&mut message, BLOCK_REQUEST_MAX_BLOCKS_FIELD, 1);
+    put_varint_field(&mut message, BLOCK_REQUEST_MULTIPLE_JUSTIFICATIONS_FIELD, 1);
+
+    message
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockRequestProtocol(pub(crate) String);
+
+impl ProtocolName for BlockRequestProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Requests a single block (header, body and justification) from Substrate's block request
+/// protocol, answering inbound requests isn't supported
+#[derive(Debug, Clone, Default)]
+pub struct BlockRequestCodec;
+
+#[async_trait]
+impl RequestResponseCodec for BlockRequestCodec {
+    type Protocol = BlockRequestProtocol;
+    type Request = BlockId;
+    /// Returned blocks, none if the peer doesn't know the requested one
+    type Response = Vec<BlockData>;
+
+    async fn read_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        _io: &mut T,
+    ) -> std::io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Inbound block requests aren't supported",
+        ))
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> std::io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let message = read_length_prefixed(io, MAX_RESPONSE_SIZE).await?;
+
+        decode_block_response(&message)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+        block_id: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, encode_block_request(block_id)).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        _io: &mut T,
+        _response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Inbound block requests aren't supported",
+        ))
+    }
+}
+
+/// Block request protocol names of the chains of the Kademlia protocols
+pub(crate) fn block_request_protocols(kad_protocols: &[String]) -> Vec<BlockRequestProtocol> {
+    chain_protocol_names(kad_protocols, BLOCK_REQUEST_PROTOCOL_SUFFIX)
+        .into_iter()
+        .map(BlockRequestProtocol)
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum BlockProbeError {
+    #[error("Peer supports none of the block request protocols")]
+    UnsupportedProtocol,
+    #[error("Peer doesn't serve the block")]
+    NotFound,
+    #[error("Malformed response: {0}")]
+    MalformedResponse(String),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Request failed: {0}")]
+    Request(String),
+}
+
+impl BlockProbeError {
+    /// Short machine-readable name of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UnsupportedProtocol => "unsupported_protocol",
+            Self::NotFound => "not_found",
+            Self::MalformedResponse(_) => "malformed_response",
+            Self::Timeout(_) => "timeout",
+            Self::Request(_) => "request",
+        }
+    }
+}
+
+/// Block served by a peer
+#[derive(Debug, Clone)]
+pub struct FetchedBlock {
+    pub hash: BlockHash,
+    /// Decoded header, `None` if the peer didn't return it
+    pub header: Option<BlockHeader>,
+    pub extrinsics: usize,
+    pub has_justification: bool,
+}
+
+impl FetchedBlock {
+    fn decode(block_id: BlockId, block_data: BlockData) -> Result<Self, String> {
+        let hash = BlockHash::try_from(block_data.hash.as_slice())
+            .map_err(|_| format!("Bad block hash of {} bytes", block_data.hash.len()))?;
+        let header = (!block_data.header.is_empty())
+            .then(|| BlockHeader::decode(&block_data.header))
+            .transpose()?;
+
+        match (block_id, &header) {
+            (BlockId::Hash(requested_hash), _) if requested_hash != hash => {
+                return Err(format!("Returned block {} instead", hex_hash(&hash)))
+            }
+            (BlockId::Number(requested_number), Some(header))
+                if u64::from(requested_number) != header.number =>
+            {
+                return Err(format!("Returned block #{} instead", header.number))
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            hash,
+            header,
+            extrinsics: block_data.extrinsics,
+            has_justification: block_data.has_justification,
+        })
+    }
+}
+
+/// Outcome of requesting a single block from a peer
+pub struct BlockProbe {
+    pub requested: BlockId,
+    /// Time taken from sending the request to its response or failure
+    pub response_in: Duration,
+    pub outcome: Result<FetchedBlock, BlockProbeError>,
+}
+
+impl Display for BlockProbe {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        let block = match &self.outcome {
+            Ok(block) => block,
+            Err(error) => {
+                return write!(
+                    formatter,
+                    "  {}: unavailable after {:?}, {} ({error})",
+                    self.requested,
+                    self.response_in,
+                    error.kind()
+                )
+            }
+        };
+
+        writeln!(
+            formatter,
+            "  - block {} served in {:?}",
+            hex_hash(&block.hash),
+            self.response_in
+        )?;
+
+        match &block.header {
+            Some(header) => {
+                writeln!(formatter, "  - number: #{}", header.number)?;
+                writeln!(formatter, "  - parent: {}", hex_hash(&header.parent_hash))?;
+                writeln!(
+                    formatter,
+                    "  - state root: {}",
+                    hex_hash(&header.state_root)
+                )?;
+                writeln!(
+                    formatter,
+                    "  - extrinsics root: {}",
+                    hex_hash(&header.extrinsics_root)
+                )?;
+                writeln!(
+                    formatter,
+                    "  - digest: [{}]",
+                    header
+                        .digest
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+            }
+            None => writeln!(formatter, "  - header: not returned")?,
+        }
+
+        writeln!(formatter, "  - body: {} extrinsic(s)", block.extrinsics)?;
+        write!(
+            formatter,
+            "  - justification: {}",
+            if block.has_justification {
+                "present"
+            } else {
+                "none"
+            }
+        )
+    }
+}
+
+impl Serialize for BlockProbe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let block = self.outcome.as_ref().ok();
+        let header = block.and_then(|block| block.header.as_ref());
+        let mut state = serializer.serialize_struct("BlockProbe", 12)?;
+        state.serialize_field("requested", &self.requested.to_string())?;
+        state.serialize_field("response_in_ms", &(self.response_in.as_secs_f64() * 1000.0))?;
+        state.serialize_field("hash", &block.map(|block| hex_hash(&block.hash)))?;
+        state.serialize_field("number", &header.map(|header| header.number))?;
+        state.serialize_field(
+            "parent_hash",
+            &header.map(|header| hex_hash(&header.parent_hash)),
+        )?;
+        state.serialize_field(
+            "state_root",
+            &header.map(|header| hex_hash(&header.state_root)),
+        )?;
+        state.serialize_field(
+            "extrinsics_root",
+            &header.map(|header| hex_hash(&header.extrinsics_root)),
+        )?;
+        state.serialize_field(
+            "digest",
+            &header.map(|header| {
+                header
+                    .digest
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            }),
+        )?;
+        state.serialize_field("extrinsics", &block.map(|block| block.extrinsics))?;
+        state.serialize_field(
+            "has_justification",
+            &block.map(|block| block.has_justification),
+        )?;
+        state.serialize_field(
+            "error",
+            &self.outcome.as_ref().err().map(SerializableBlockProbeError),
+        )?;
+
+        state.end()
+    }
+}
+
+struct SerializableBlockProbeError<'a>(&'a BlockProbeError);
+
+impl Serialize for SerializableBlockProbeError<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("BlockProbeError", 2)?;
+        state.serialize_field("kind", self.0.kind())?;
+        state.serialize_field("message", &self.0.to_string())?;
+
+        state.end()
+    }
+}
+
+async fn wait_for_block(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    block_id: BlockId,
+) -> Result<FetchedBlock, BlockProbeError> {
+    let request_id = swarm
+        .behaviour_mut()
+        .block_requests
+        .send_request(&peer_id, block_id);
+
+    loop {
+        match swarm
+            .next()
+            .await
+            .expect("Programmatic error: infinite streams!")
+        {
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::BlockRequests(
+                RequestResponseEvent::Message {
+                    message:
+                        RequestResponseMessage::Response {
+                            request_id: response_id,
+                            response,
+                        },
+                    ..
+                },
+            )) if response_id == request_id => {
+                let block_data = response
+                    .into_iter()
+                    .next()
+                    .ok_or(BlockProbeError::NotFound)?;
+
+                return FetchedBlock::decode(block_id, block_data)
+                    .map_err(BlockProbeError::MalformedResponse);
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::BlockRequests(
+                RequestResponseEvent::OutboundFailure {
+                    request_id: failed_id,
+                    error,
+                    ..
+                },
+            )) if failed_id == request_id => {
+                return Err(match error {
+                    OutboundFailure::UnsupportedProtocols => BlockProbeError::UnsupportedProtocol,
+                    other_error => BlockProbeError::Request(other_error.to_string()),
+                });
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+}
+
+/// Requests a single block from a connected peer, timing the response
+pub(crate) async fn fetch_block(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    block_id: BlockId,
+    request_timeout: Duration,
+) -> BlockProbe {
+    let requested_at = Instant::now();
+    let outcome = timeout(request_timeout, wait_for_block(swarm, peer_id, block_id))
+        .await
+        .unwrap_or(Err(BlockProbeError::Timeout(request_timeout)));
+    let response_in = requested_at.elapsed();
+
+    match &outcome {
+        Ok(_) => log_info!("Peer \"{peer_id}\" served block {block_id} in {response_in:?}"),
+        Err(error) => log_info!("Failed to fetch block {block_id} from \"{peer_id}\": {error}"),
+    }
+
+    BlockProbe {
+        requested: block_id,
+        response_in,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hash of Polkadot's genesis block
+    const GENESIS_HASH: &str = "91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+    const GENESIS_STATE_ROOT: &str =
+        "29d0d972cd27cbc511e9589fcb7a4506d5eb6a9e8df205f00472e5ab354a4e17";
+    const GENESIS_EXTRINSICS_ROOT: &str =
+        "03170a2e7597b7b7e3d84c05391d139a62b157e78786d8c082f29dcf4c111314";
+
+    fn hash(hex_hash: &str) -> BlockHash {
+        hex::decode(hex_hash).unwrap().try_into().unwrap()
+    }
+
+    /// SCALE-encoded header of Polkadot's genesis block
+    fn genesis_header() -> Vec<u8> {
+        hex::decode(format!(
+            "{}00{GENESIS_STATE_ROOT}{GENESIS_EXTRINSICS_ROOT}00",
+            "00".repeat(32)
+        ))
+        .unwrap()
+    }
+
+    fn decode_compact(input: &[u8]) -> Result<u64, String> {
+        ScaleReader { input }.take_compact()
+    }
+
+    #[test]
+    fn decodes_compact_integers() {
+        // Single-byte mode
+        assert_eq!(decode_compact(&[0x00]), Ok(0));
+        assert_eq!(decode_compact(&[0xfc]), Ok(63));
+        // Two-byte mode
+        assert_eq!(decode_compact(&[0x01, 0x01]), Ok(64));
+        assert_eq!(decode_compact(&[0xfd, 0xff]), Ok(16_383));
+        // Four-byte mode
+        assert_eq!(decode_compact(&[0x02, 0x00, 0x01, 0x00]), Ok(16_384));
+        assert_eq!(decode_compact(&[0xfe, 0xff, 0xff, 0xff]), Ok((1 << 30) - 1));
+        // Big-integer mode
+        assert_eq!(decode_compact(&[0x03, 0x00, 0x00, 0x00, 0x40]), Ok(1 << 30));
+        assert_eq!(
+            decode_compact(&[0x13, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_compact_integers() {
+        assert_eq!(
+            decode_compact(&[0x17, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err("Compact integer of 9 bytes overflows".to_owned())
+        );
+        assert!(decode_compact(&[]).is_err());
+        assert!(decode_compact(&[0x01]).is_err());
+        assert!(decode_compact(&[0x03, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn decodes_genesis_header() {
+        let header = BlockHeader::decode(&genesis_header()).unwrap();
+
+        assert_eq!(header.parent_hash, [0; 32]);
+        assert_eq!(header.number, 0);
+        assert_eq!(header.state_root, hash(GENESIS_STATE_ROOT));
+        assert_eq!(header.extrinsics_root, hash(GENESIS_EXTRINSICS_ROOT));
+        assert!(header.digest.is_empty());
+    }
+
+    #[test]
+    fn decodes_header_digest() {
+        let mut header = genesis_header();
+        header.truncate(header.len() - 1);
+        header.push(5 << 2);
+        header.extend_from_slice(&[6, b'B', b'A', b'B', b'E', 2 << 2, 0x01, 0x02]);
+        header.extend_from_slice(&[4, b'F', b'R', b'N', b'K', 0]);
+        header.extend_from_slice(&[0, 1 << 2, 0xff]);
+        header.push(8);
+        header.extend_from_slice(&[5, b'B', b'A', b'B', b'E', 0x01, 0x01]);
+        header.extend_from_slice(&[0; 64]);
+
+        assert_eq!(
+            BlockHeader::decode(&header).unwrap().digest,
+            vec![
+                DigestItem::PreRuntime(*b"BABE"),
+                DigestItem::Consensus(*b"FRNK"),
+                DigestItem::Other,
+                DigestItem::RuntimeEnvironmentUpdated,
+                DigestItem::Seal(*b"BABE"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let header = genesis_header();
+
+        assert!(BlockHeader::decode(&header[..header.len() - 1]).is_err());
+        assert!(BlockHeader::decode(&[header.as_slice(), &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn decodes_block_response() {
+        let mut block = Vec::new();
+        put_bytes_field(&mut block, BLOCK_DATA_HASH_FIELD, &hash(GENESIS_HASH));
+        put_bytes_field(&mut block, BLOCK_DATA_HEADER_FIELD, &genesis_header());
+        put_varint_field(&mut block, BLOCK_DATA_EMPTY_JUSTIFICATION_FIELD, 0);
+        let mut response = Vec::new();
+        put_bytes_field(&mut response, BLOCK_RESPONSE_BLOCKS_FIELD, &block);
+
+        let mut blocks = decode_block_response(&response).unwrap();
+        assert_eq!(blocks.len(), 1);
+        let block = FetchedBlock::decode(BlockId::Number(0), blocks.remove(0)).unwrap();
+
+        assert_eq!(block.hash, hash(GENESIS_HASH));
+        assert_eq!(block.header.unwrap().state_root, hash(GENESIS_STATE_ROOT));
+        assert_eq!(block.extrinsics, 0);
+        assert!(!block.has_justification);
+    }
+
+    #[test]
+    fn rejects_other_blocks() {
+        let block_data = BlockData {
+            hash: hash(GENESIS_HASH).to_vec(),
+            header: genesis_header(),
+            ..BlockData::default()
+        };
+
+        assert!(FetchedBlock::decode(BlockId::Number(1), block_data.clone()).is_err());
+        assert!(FetchedBlock::decode(BlockId::Hash([0; 32]), block_data).is_err());
+    }
+
+    #[test]
+    fn encodes_block_requests() {
+        assert_eq!(
+            hex::encode(encode_block_request(BlockId::Number(1))),
+            "0880808098011a040100000030013801"
+        );
+        assert_eq!(
+            hex::encode(encode_block_request(BlockId::Hash(hash(GENESIS_HASH)))),
+            format!("0880808098011220{GENESIS_HASH}30013801")
+        );
+    }
+}
@@ -0,0 +1,882 @@
+use crate::logging::{log_debug, log_info};
+use crate::node::{PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+use libp2p::core::upgrade::{
+    read_length_prefixed, write_length_prefixed, DeniedUpgrade, NegotiationError, OutboundUpgrade,
+    ProtocolName, UpgradeError, UpgradeInfo,
+};
+use libp2p::core::Endpoint;
+use libp2p::swarm::handler::{ConnectionEvent, DialUpgradeError, FullyNegotiatedOutbound};
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr,
+    ConnectionId, FromSwarm, KeepAlive, NegotiatedSubstream, NetworkBehaviour, NotifyHandler,
+    PollParameters, SubstreamProtocol, SwarmEvent, THandler, THandlerInEvent, THandlerOutEvent,
+    ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use std::iter;
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::time::{timeout, Duration};
+
+/// Legacy protocol prefix of Substrate chains, used when the genesis hash isn't known
+const DEFAULT_CHAIN_PREFIX: &str = "/sup";
+const BLOCK_ANNOUNCES_PROTOCOL_SUFFIX: &str = "/block-announces/1";
+const KAD_PROTOCOL_SUFFIX: &str = "/kad";
+/// Maximum size of a notifications handshake accepted by Substrate
+const MAX_HANDSHAKE_SIZE: usize = 1024;
+/// Size of the role byte and of the best and genesis hashes, the rest being the block number
+const HANDSHAKE_FIXED_SIZE: usize = 1 + 32 + 32;
+const ROLE_FULL: u8 = 0b0001;
+const ROLE_LIGHT: u8 = 0b0010;
+const ROLE_AUTHORITY: u8 = 0b0100;
+
+pub type BlockHash = [u8; 32];
+
+pub(crate) fn hex_hash(hash: &BlockHash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+/// Genesis hash prefixing a Substrate protocol name, like `/<genesis hash>/kad`
+pub(crate) fn protocol_genesis_hash(protocol_name: &str) -> Option<BlockHash> {
+    let chain_prefix = protocol_name.strip_prefix('/')?.split('/').next()?;
+
+    hex::decode(chain_prefix).ok()?.try_into().ok()
+}
+
+/// Role of a Substrate node, as announced in its handshakes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    Full,
+    Light,
+    Authority,
+    /// Roles byte not matching a single known role
+    Unknown(u8),
+}
+
+impl NodeRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Light => "light",
+            Self::Authority => "authority",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+
+    fn from_byte(roles: u8) -> Self {
+        match roles {
+            ROLE_FULL => Self::Full,
+            ROLE_LIGHT => Self::Light,
+            ROLE_AUTHORITY => Self::Authority,
+            other_roles => Self::Unknown(other_roles),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Full => ROLE_FULL,
+            Self::Light => ROLE_LIGHT,
+            Self::Authority => ROLE_AUTHORITY,
+            Self::Unknown(roles) => roles,
+        }
+    }
+}
+
+impl Display for NodeRole {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Unknown(roles) => write!(formatter, "unknown ({roles:#010b})"),
+            known_role => formatter.write_str(known_role.as_str()),
+        }
+    }
+}
+
+impl Serialize for NodeRole {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// SCALE-encoded handshake of Substrate's block announces protocol
+#[derive(Debug, Clone)]
+pub struct BlockAnnouncesHandshake {
+    pub role: NodeRole,
+    pub best_number: u64,
+    pub best_hash: BlockHash,
+    pub genesis_hash: BlockHash,
+}
+
+impl BlockAnnouncesHandshake {
+    /// Handshake of a light client still at genesis, light clients being the least demanding
+    /// peers for full nodes to accept
+    pub(crate) fn light_client(genesis_hash: BlockHash) -> Self {
+        Self {
+            role: NodeRole::Light,
+            best_number: 0,
+            best_hash: genesis_hash,
+            genesis_hash,
+        }
+    }
+
+    /// Encodes the block number as a `u32`, like GoRo and most Substrate chains do
+    fn encode(&self) -> Vec<u8> {
+        let best_number = u32::try_from(self.best_number).unwrap_or(u32::MAX);
+        let mut message = Vec::with_capacity(HANDSHAKE_FIXED_SIZE + 4);
+        message.push(self.role.to_byte());
+        message.extend_from_slice(&best_number.to_le_bytes());
+        message.extend_from_slice(&self.best_hash);
+        message.extend_from_slice(&self.genesis_hash);
+
+        message
+    }
+
+    /// Decodes `u32` and `u64` block numbers, told apart by the handshake size
+    fn decode(message: &[u8]) -> Result<Self, String> {
+        let (&roles, rest) = message
+            .split_first()
+            .ok_or_else(|| "empty handshake".to_owned())?;
+        let best_number = match message.len().checked_sub(HANDSHAKE_FIXED_SIZE) {
+            Some(4) => u64::from(u32::from_le_bytes(
+                rest[..4]
+                    .try_into()
+                    .expect("Programmatic error: bad slice!"),
+            )),
+            Some(8) => u64::from_le_bytes(
+                rest[..8]
+                    .try_into()
+                    .expect("Programmatic error: bad slice!"),
+            ),
+            _ => {
+                return Err(format!(
+                    "unexpected handshake size of {} bytes",
+                    message.len()
+                ))
+            }
+        };
+        let hashes = &rest[rest.len() - 64..];
+
+        Ok(Self {
+            role: NodeRole::from_byte(roles),
+            best_number,
+            best_hash: hashes[..32]
+                .try_into()
+                .expect("Programmatic error: bad slice!"),
+            genesis_hash: hashes[32..]
+                .try_into()
+                .expect("Programmatic error: bad slice!"),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockAnnouncesProtocol(String);
+
+impl ProtocolName for BlockAnnouncesProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Performs the block announces handshake on an outbound substream, then drops the substream
+/// without waiting for any announce
+pub struct BlockAnnouncesUpgrade {
+    protocol: BlockAnnouncesProtocol,
+    local_handshake: BlockAnnouncesHandshake,
+}
+
+impl UpgradeInfo for BlockAnnouncesUpgrade {
+    type Info = BlockAnnouncesProtocol;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(self.protocol.clone())
+    }
+}
+
+impl OutboundUpgrade<NegotiatedSubstream> for BlockAnnouncesUpgrade {
+    /// Handshake of the remote peer
+    type Output = BlockAnnouncesHandshake;
+    type Error = ChainProbeError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(
+        self,
+        mut substream: NegotiatedSubstream,
+        _protocol: Self::Info,
+    ) -> Self::Future {
+        async move {
+            write_length_prefixed(&mut substream, self.local_handshake.encode())
+                .await
+                .map_err(|error| ChainProbeError::Request(error.to_string()))?;
+
+            match read_length_prefixed(&mut substream, MAX_HANDSHAKE_SIZE).await {
+                Ok(message) => BlockAnnouncesHandshake::decode(&message)
+                    .map_err(ChainProbeError::MalformedHandshake),
+                Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    Err(ChainProbeError::Refused)
+                }
+                Err(error) => Err(ChainProbeError::Request(error.to_string())),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Handshake of the remote peer on a block announces protocol, or why it wasn't received
+type HandshakeOutcome = (String, Result<BlockAnnouncesHandshake, ChainProbeError>);
+
+/// Opens an outbound substream per requested handshake, answering inbound handshakes isn't
+/// supported
+pub struct BlockAnnouncesHandler {
+    pending_handshakes: VecDeque<(String, BlockAnnouncesHandshake)>,
+    pending_outcomes: VecDeque<HandshakeOutcome>,
+}
+
+impl ConnectionHandler for BlockAnnouncesHandler {
+    /// Block announces protocol to open along with the local handshake
+    type InEvent = (String, BlockAnnouncesHandshake);
+    type OutEvent = HandshakeOutcome;
+    type Error = Infallible;
+    type InboundProtocol = DeniedUpgrade;
+    type OutboundProtocol = BlockAnnouncesUpgrade;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = String;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(DeniedUpgrade, ())
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        KeepAlive::No
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        if let Some(outcome) = self.pending_outcomes.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::Custom(outcome));
+        }
+
+        if let Some((protocol, local_handshake)) = self.pending_handshakes.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(
+                    BlockAnnouncesUpgrade {
+                        protocol: BlockAnnouncesProtocol(protocol.clone()),
+                        local_handshake,
+                    },
+                    protocol,
+                ),
+            });
+        }
+
+        Poll::Pending
+    }
+
+    fn on_behaviour_event(&mut self, handshake: Self::InEvent) {
+        self.pending_handshakes.push_back(handshake);
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        let outcome = match event {
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: handshake,
+                info: protocol,
+            }) => (protocol, Ok(handshake)),
+            ConnectionEvent::DialUpgradeError(DialUpgradeError {
+                info: protocol,
+                error,
+            }) => match error {
+                ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(
+                    NegotiationError::Failed,
+                )) => (protocol, Err(ChainProbeError::UnsupportedProtocol)),
+                ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Apply(error)) => {
+                    (protocol, Err(error))
+                }
+                other_error => (
+                    protocol,
+                    Err(ChainProbeError::Request(other_error.to_string())),
+                ),
+            },
+            _ => return,
+        };
+
+        self.pending_outcomes.push_back(outcome);
+    }
+}
+
+/// Outcome of a block announces handshake with a connected peer
+#[derive(Debug)]
+pub struct BlockAnnouncesEvent {
+    pub peer_id: PeerId,
+    /// Block announces protocol the handshake was performed on
+    pub protocol: String,
+    pub outcome: Result<BlockAnnouncesHandshake, ChainProbeError>,
+}
+
+/// Performs block announces handshakes with connected peers on protocols chosen per peer, the
+/// chain of a peer being unknown until it's identified
+#[derive(Default)]
+pub struct BlockAnnouncesBehaviour {
+    pending_actions: VecDeque<ToSwarm<BlockAnnouncesEvent, (String, BlockAnnouncesHandshake)>>,
+}
+
+impl BlockAnnouncesBehaviour {
+    /// Sends the local handshake to the peer on the protocol, over any of its connections
+    pub fn handshake(
+        &mut self,
+        peer_id: PeerId,
+        protocol: String,
+        local_handshake: BlockAnnouncesHandshake,
+    ) {
+        self.pending_actions.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: (protocol, local_handshake),
+        });
+    }
+
+    fn create_handler() -> BlockAnnouncesHandler {
+        BlockAnnouncesHandler {
+            pending_handshakes: VecDeque::new(),
+            pending_outcomes: VecDeque::new(),
+        }
+    }
+}
+
+impl NetworkBehaviour for BlockAnnouncesBehaviour {
+    type ConnectionHandler = BlockAnnouncesHandler;
+    type OutEvent = BlockAnnouncesEvent;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Self::create_handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Self::create_handler())
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm<Self::ConnectionHandler>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _connection_id: ConnectionId,
+        (protocol, outcome): THandlerOutEvent<Self>,
+    ) {
+        self.pending_actions
+            .push_back(ToSwarm::GenerateEvent(BlockAnnouncesEvent {
+                peer_id,
+                protocol,
+                outcome,
+            }));
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        match self.pending_actions.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Names of a Substrate protocol on the chains of the Kademlia protocols, `/<chain>/kad` giving
+/// `/<chain><protocol suffix>`, on the legacy `/sup` chain if none is a Substrate one
+pub(crate) fn chain_protocol_names(kad_protocols: &[String], protocol_suffix: &str) -> Vec<String> {
+    let protocol_names = kad_protocols
+        .iter()
+        .filter_map(|kad_protocol| kad_protocol.strip_suffix(KAD_PROTOCOL_SUFFIX))
+        .map(|chain_prefix| format!("{chain_prefix}{protocol_suffix}"))
+        .collect::<Vec<_>>();
+
+    if protocol_names.is_empty() {
+        return vec![format!("{DEFAULT_CHAIN_PREFIX}{protocol_suffix}")];
+    }
+
+    protocol_names
+}
+
+#[derive(Debug, Error)]
+pub enum ChainProbeError {
+    #[error("Peer supports none of the block announces protocols")]
+    UnsupportedProtocol,
+    #[error("Peer closed the substream without handshake (no free slot or genesis hash rejected)")]
+    Refused,
+    #[error("Malformed handshake: {0}")]
+    MalformedHandshake(String),
+    #[error("Genesis hash unknown, neither expected nor advertised by the peer")]
+    UnknownGenesis,
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Handshake failed: {0}")]
+    Request(String),
+}
+
+impl ChainProbeError {
+    /// Short machine-readable name of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UnsupportedProtocol => "unsupported_protocol",
+            Self::Refused => "refused",
+            Self::MalformedHandshake(_) => "malformed_handshake",
+            Self::UnknownGenesis => "unknown_genesis",
+            Self::Timeout(_) => "timeout",
+            Self::Request(_) => "request",
+        }
+    }
+}
+
+/// Chain state of a peer, as announced in its block announces handshake
+#[derive(Debug, Clone)]
+pub struct ChainState {
+    /// Block announces protocol the handshake was performed on
+    pub protocol: String,
+    pub role: NodeRole,
+    pub best_number: u64,
+    pub best_hash: BlockHash,
+    pub genesis_hash: BlockHash,
+}
+
+/// Outcome of the block announces handshake with a peer
+pub struct ChainProbe {
+    /// Genesis hash of the DHT network the peer was looked up on, if known
+    pub expected_genesis_hash: Option<BlockHash>,
+    pub outcome: Result<ChainState, ChainProbeError>,
+}
+
+impl ChainProbe {
+    /// Whether the peer is on the expected chain, `None` if either genesis hash is unknown
+    pub fn genesis_matches(&self) -> Option<bool> {
+        let chain_state = self.outcome.as_ref().ok()?;
+
+        self.expected_genesis_hash
+            .map(|expected_genesis_hash| expected_genesis_hash == chain_state.genesis_hash)
+    }
+}
+
+impl Display for ChainProbe {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        let chain_state = match &self.outcome {
+            Ok(chain_state) => chain_state,
+            Err(error) => return write!(formatter, "  unavailable, {} ({error})", error.kind()),
+        };
+
+        writeln!(formatter, "  - protocol \"{}\"", chain_state.protocol)?;
+        writeln!(formatter, "  - role: {}", chain_state.role)?;
+        writeln!(
+            formatter,
+            "  - best block: #{} ({})",
+            chain_state.best_number,
+            hex_hash(&chain_state.best_hash)
+        )?;
+        write!(
+            formatter,
+            "  - genesis: {}",
+            hex_hash(&chain_state.genesis_hash)
+        )?;
+
+        match (self.genesis_matches(), &self.expected_genesis_hash) {
+            (Some(false), Some(expected_genesis_hash)) => write!(
+                formatter,
+                " (MISMATCH, expected {})",
+                hex_hash(expected_genesis_hash)
+            ),
+            (Some(true), _) => formatter.write_str(" (matches the network)"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Serialize for ChainProbe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let chain_state = self.outcome.as_ref().ok();
+        let mut state = serializer.serialize_struct("ChainProbe", 8)?;
+        state.serialize_field(
+            "protocol",
+            &chain_state.map(|chain_state| &chain_state.protocol),
+        )?;
+        state.serialize_field("role", &chain_state.map(|chain_state| chain_state.role))?;
+        state.serialize_field(
+            "best_number",
+            &chain_state.map(|chain_state| chain_state.best_number),
+        )?;
+        state.serialize_field(
+            "best_hash",
+            &chain_state.map(|chain_state| hex_hash(&chain_state.best_hash)),
+        )?;
+        state.serialize_field(
+            "genesis_hash",
+            &chain_state.map(|chain_state| hex_hash(&chain_state.genesis_hash)),
+        )?;
+        state.serialize_field(
+            "expected_genesis_hash",
+            &self.expected_genesis_hash.as_ref().map(hex_hash),
+        )?;
+        state.serialize_field("genesis_matches", &self.genesis_matches())?;
+        state.serialize_field(
+            "error",
+            &self.outcome.as_ref().err().map(SerializableChainProbeError),
+        )?;
+
+        state.end()
+    }
+}
+
+struct SerializableChainProbeError<'a>(&'a ChainProbeError);
+
+impl Serialize for SerializableChainProbeError<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ChainProbeError", 2)?;
+        state.serialize_field("kind", self.0.kind())?;
+        state.serialize_field("message", &self.0.to_string())?;
+
+        state.end()
+    }
+}
+
+/// Block announces protocol to perform the handshake on and genesis hash to announce on it:
+/// the advertised protocol of the expected chain, else of the first advertised chain so that a
+/// mismatch is reported, else the legacy or expected chain's one if the genesis hash is expected
+fn select_block_announces_protocol(
+    peer_protocols: &[String],
+    expected_genesis_hash: Option<BlockHash>,
+) -> Result<(String, BlockHash), ChainProbeError> {
+    let advertised_protocols = peer_protocols
+        .iter()
+        .filter(|protocol| protocol.ends_with(BLOCK_ANNOUNCES_PROTOCOL_SUFFIX))
+        .collect::<Vec<_>>();
+    let advertised_chains = advertised_protocols
+        .iter()
+        .filter_map(|protocol| {
+            protocol_genesis_hash(protocol).map(|genesis_hash| ((*protocol).clone(), genesis_hash))
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(chain) = advertised_chains
+        .iter()
+        .find(|(_, genesis_hash)| Some(*genesis_hash) == expected_genesis_hash)
+        .or_else(|| advertised_chains.first())
+    {
+        return Ok(chain.clone());
+    }
+
+    let genesis_hash = expected_genesis_hash.ok_or(ChainProbeError::UnknownGenesis)?;
+    let protocol = advertised_protocols
+        .first()
+        .map(|protocol| (*protocol).clone())
+        .unwrap_or_else(|| {
+            format!(
+                "/{}{BLOCK_ANNOUNCES_PROTOCOL_SUFFIX}",
+                hex::encode(genesis_hash)
+            )
+        });
+
+    Ok((protocol, genesis_hash))
+}
+
+async fn wait_for_handshake(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    protocol: String,
+    local_handshake: BlockAnnouncesHandshake,
+) -> Result<ChainState, ChainProbeError> {
+    swarm
+        .behaviour_mut()
+        .block_announces
+        .handshake(peer_id, protocol, local_handshake);
+
+    loop {
+        match swarm
+            .next()
+            .await
+            .expect("Programmatic error: infinite streams!")
+        {
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::BlockAnnounces(event))
+                if event.peer_id == peer_id =>
+            {
+                let handshake = event.outcome?;
+
+                return Ok(ChainState {
+                    protocol: event.protocol,
+                    role: handshake.role,
+                    best_number: handshake.best_number,
+                    best_hash: handshake.best_hash,
+                    genesis_hash: handshake.genesis_hash,
+                });
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id: closed_peer_id,
+                num_established: 0,
+                ..
+            } if closed_peer_id == peer_id => {
+                return Err(ChainProbeError::Request("connection closed".to_owned()));
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+}
+
+/// Performs the block announces handshake with a connected peer on its advertised block
+/// announces protocol, announcing the genesis hash of that protocol's chain
+pub(crate) async fn probe_chain(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    peer_protocols: &[String],
+    expected_genesis_hash: Option<BlockHash>,
+    handshake_timeout: Duration,
+) -> ChainProbe {
+    let outcome = match select_block_announces_protocol(peer_protocols, expected_genesis_hash) {
+        Ok((protocol, genesis_hash)) => timeout(
+            handshake_timeout,
+            wait_for_handshake(
+                swarm,
+                peer_id,
+                protocol,
+                BlockAnnouncesHandshake::light_client(genesis_hash),
+            ),
+        )
+        .await
+        .unwrap_or(Err(ChainProbeError::Timeout(handshake_timeout))),
+        Err(error) => Err(error),
+    };
+
+    match &outcome {
+        Ok(chain_state) => log_info!(
+            "Peer \"{peer_id}\" is a {} node at block #{}",
+            chain_state.role,
+            chain_state.best_number
+        ),
+        Err(error) => log_info!("Failed to read the chain state of \"{peer_id}\": {error}"),
+    }
+
+    ChainProbe {
+        expected_genesis_hash,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Genesis hash of Polkadot
+    const GENESIS_HASH: &str = "91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+
+    fn genesis_hash() -> BlockHash {
+        hex::decode(GENESIS_HASH).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn round_trips_handshakes() {
+        let handshake = BlockAnnouncesHandshake {
+            role: NodeRole::Authority,
+            best_number: 17_000_000,
+            best_hash: [0xab; 32],
+            genesis_hash: genesis_hash(),
+        };
+        let message = handshake.encode();
+        assert_eq!(message.len(), HANDSHAKE_FIXED_SIZE + 4);
+        assert_eq!(message[..5], [ROLE_AUTHORITY, 0x40, 0x66, 0x03, 0x01]);
+
+        let decoded = BlockAnnouncesHandshake::decode(&message).unwrap();
+        assert_eq!(decoded.role, handshake.role);
+        assert_eq!(decoded.best_number, handshake.best_number);
+        assert_eq!(decoded.best_hash, handshake.best_hash);
+        assert_eq!(decoded.genesis_hash, handshake.genesis_hash);
+    }
+
+    #[test]
+    fn encodes_light_client_handshake() {
+        let message = BlockAnnouncesHandshake::light_client(genesis_hash()).encode();
+
+        assert_eq!(
+            hex::encode(message),
+            format!("0200000000{GENESIS_HASH}{GENESIS_HASH}")
+        );
+    }
+
+    #[test]
+    fn decodes_u64_block_numbers() {
+        let mut message = vec![ROLE_FULL];
+        message.extend_from_slice(&(u64::from(u32::MAX) + 1).to_le_bytes());
+        message.extend_from_slice(&[0xab; 32]);
+        message.extend_from_slice(&genesis_hash());
+
+        let handshake = BlockAnnouncesHandshake::decode(&message).unwrap();
+        assert_eq!(handshake.role, NodeRole::Full);
+        assert_eq!(handshake.best_number, u64::from(u32::MAX) + 1);
+        assert_eq!(handshake.best_hash, [0xab; 32]);
+        assert_eq!(handshake.genesis_hash, genesis_hash());
+    }
+
+    #[test]
+    fn rejects_truncated_and_oversize_handshakes() {
+        let message = BlockAnnouncesHandshake::light_client(genesis_hash()).encode();
+
+        assert_eq!(
+            BlockAnnouncesHandshake::decode(&[]).unwrap_err(),
+            "empty handshake"
+        );
+
+        for size in [1, HANDSHAKE_FIXED_SIZE, HANDSHAKE_FIXED_SIZE + 3] {
+            assert_eq!(
+                BlockAnnouncesHandshake::decode(&message[..size]).unwrap_err(),
+                format!("unexpected handshake size of {size} bytes")
+            );
+        }
+
+        for extra_size in [1, 5] {
+            let oversize_message = [message.as_slice(), &vec![0; extra_size]].concat();
+
+            assert_eq!(
+                BlockAnnouncesHandshake::decode(&oversize_message).unwrap_err(),
+                format!(
+                    "unexpected handshake size of {} bytes",
+                    message.len() + extra_size
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn parses_node_roles() {
+        assert_eq!(NodeRole::from_byte(0b0001), NodeRole::Full);
+        assert_eq!(NodeRole::from_byte(0b0010), NodeRole::Light);
+        assert_eq!(NodeRole::from_byte(0b0100), NodeRole::Authority);
+        assert_eq!(NodeRole::from_byte(0), NodeRole::Unknown(0));
+        assert_eq!(NodeRole::from_byte(0b0101), NodeRole::Unknown(0b0101));
+
+        for roles in 0..=u8::MAX {
+            assert_eq!(NodeRole::from_byte(roles).to_byte(), roles);
+        }
+
+        assert_eq!(NodeRole::Authority.to_string(), "authority");
+        assert_eq!(
+            NodeRole::Unknown(0b0101).to_string(),
+            "unknown (0b00000101)"
+        );
+    }
+
+    #[test]
+    fn parses_protocol_genesis_hashes() {
+        assert_eq!(
+            protocol_genesis_hash(&format!("/{GENESIS_HASH}/block-announces/1")),
+            Some(genesis_hash())
+        );
+        assert_eq!(
+            protocol_genesis_hash(&format!("/{GENESIS_HASH}")),
+            Some(genesis_hash())
+        );
+        assert_eq!(protocol_genesis_hash("/sup/block-announces/1"), None);
+        assert_eq!(protocol_genesis_hash("/dot/kad"), None);
+        assert_eq!(
+            protocol_genesis_hash(&format!("/{}/kad", &GENESIS_HASH[2..])),
+            None
+        );
+        assert_eq!(
+            protocol_genesis_hash(&format!("/0x{GENESIS_HASH}/kad")),
+            None
+        );
+        assert_eq!(protocol_genesis_hash(&format!("{GENESIS_HASH}/kad")), None);
+        assert_eq!(protocol_genesis_hash(""), None);
+        assert_eq!(protocol_genesis_hash("/"), None);
+    }
+
+    #[test]
+    fn names_chain_protocols() {
+        let kad_protocols = vec![format!("/{GENESIS_HASH}/kad"), "/ipfs/kad/1.0.0".to_owned()];
+
+        assert_eq!(
+            chain_protocol_names(&kad_protocols, BLOCK_ANNOUNCES_PROTOCOL_SUFFIX),
+            vec![format!("/{GENESIS_HASH}/block-announces/1")]
+        );
+        assert_eq!(
+            chain_protocol_names(&[], BLOCK_ANNOUNCES_PROTOCOL_SUFFIX),
+            vec!["/sup/block-announces/1".to_owned()]
+        );
+    }
+
+    #[test]
+    fn selects_advertised_block_announces_protocols() {
+        let other_genesis_hash = [0x11; 32];
+        let protocol = format!("/{GENESIS_HASH}/block-announces/1");
+        let other_protocol = format!("/{}/block-announces/1", hex::encode(other_genesis_hash));
+        let peer_protocols = vec![
+            "/ipfs/ping/1.0.0".to_owned(),
+            other_protocol.clone(),
+            protocol.clone(),
+        ];
+
+        assert_eq!(
+            select_block_announces_protocol(&peer_protocols, Some(genesis_hash())).unwrap(),
+            (protocol, genesis_hash())
+        );
+        assert_eq!(
+            select_block_announces_protocol(&peer_protocols, None).unwrap(),
+            (other_protocol.clone(), other_genesis_hash)
+        );
+        assert_eq!(
+            select_block_announces_protocol(&peer_protocols[..2], Some(genesis_hash())).unwrap(),
+            (other_protocol, other_genesis_hash)
+        );
+    }
+
+    #[test]
+    fn selects_fallback_block_announces_protocols() {
+        let legacy_protocols = vec!["/sup/block-announces/1".to_owned()];
+
+        assert_eq!(
+            select_block_announces_protocol(&legacy_protocols, Some(genesis_hash())).unwrap(),
+            ("/sup/block-announces/1".to_owned(), genesis_hash())
+        );
+        assert_eq!(
+            select_block_announces_protocol(&[], Some(genesis_hash())).unwrap(),
+            (format!("/{GENESIS_HASH}/block-announces/1"), genesis_hash())
+        );
+        assert!(matches!(
+            select_block_announces_protocol(&legacy_protocols, None),
+            Err(ChainProbeError::UnknownGenesis)
+        ));
+        assert!(matches!(
+            select_block_announces_protocol(&[], None),
+            Err(ChainProbeError::UnknownGenesis)
+        ));
+    }
+}
@@ -0,0 +1,605 @@
+use crate::logging::{log_debug, log_info};
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{LookupTimeouts, PeerInfo, PeerLookupError};
+use futures::StreamExt;
+use libp2p::core::upgrade::UpgradeError;
+use libp2p::core::ConnectedPoint;
+use libp2p::identify::Event as IdentityEvent;
+use libp2p::kad::{
+    GetClosestPeersError as KademliaGetClosestPeersError,
+    GetClosestPeersOk as KademliaGetClosestPeersOk, KademliaEvent, QueryId as KademliaQueryId,
+    QueryResult as KademliaQueryResult,
+};
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::{ConnectionHandlerUpgrErr, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+/// How far the inspector got with a crawled peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    /// Connected and identified
+    Identified,
+    /// Connected, but the identify message never came or couldn't be negotiated
+    Unidentified,
+    /// Couldn't be connected to
+    Unreachable,
+    /// Not probed before the crawl deadline
+    Unknown,
+}
+
+impl Reachability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Identified => "identified",
+            Self::Unidentified => "unidentified",
+            Self::Unreachable => "unreachable",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Peer found while crawling the DHT
+pub struct CrawledPeer {
+    pub peer_id: PeerId,
+    /// Addresses the peer is known by in the DHT
+    pub addresses: Vec<Multiaddr>,
+    pub reachability: Reachability,
+    /// Identification of the peer, if it's identified
+    pub info: Option<PeerInfo>,
+    /// Why the peer isn't identified, if it isn't
+    pub error: Option<String>,
+}
+
+impl Serialize for CrawledPeer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CrawledPeer", 5)?;
+        state.serialize_field("peer_id", &self.peer_id.to_string())?;
+        state.serialize_field(
+            "addresses",
+            &self
+                .addresses
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("reachability", self.reachability.as_str())?;
+        state.serialize_field("info", &self.info)?;
+        state.serialize_field("error", &self.error)?;
+
+        state.end()
+    }
+}
+
+/// Outcome of a whole DHT crawl
+pub struct CrawlReport {
+    pub network: String,
+    /// Every peer found, sorted by peer ID
+    pub peers: Vec<CrawledPeer>,
+    /// Number of random FIND_NODE walks issued
+    pub queries: usize,
+    pub elapsed: Duration,
+}
+
+impl CrawlReport {
+    /// Number of identified peers per agent version
+    pub fn agent_versions(&self) -> BTreeMap<&str, usize> {
+        let mut agent_versions = BTreeMap::new();
+
+        for info in self.peers.iter().filter_map(|peer| peer.info.as_ref()) {
+            *agent_versions.entry(info.agent_version()).or_default() += 1;
+        }
+
+        agent_versions
+    }
+
+    /// Number of peers per reachability
+    pub fn count(&self, reachability: Reachability) -> usize {
+        self.peers
+            .iter()
+            .filter(|peer| peer.reachability == reachability)
+            .count()
+    }
+}
+
+impl Serialize for CrawlReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CrawlReport", 5)?;
+        state.serialize_field("network", &self.network)?;
+        state.serialize_field("queries", &self.queries)?;
+        state.serialize_field("elapsed_ms", &(self.elapsed.as_secs_f64() * 1000.0))?;
+        state.serialize_field("agent_versions", &self.agent_versions())?;
+        state.serialize_field("peers", &self.peers)?;
+
+        state.end()
+    }
+}
+
+enum ProbePhase {
+    Discovered,
+    Dialing(Instant),
+    Identifying {
+        since: Instant,
+        established_in: Duration,
+    },
+    Done {
+        reachability: Reachability,
+        info: Option<PeerInfo>,
+        error: Option<String>,
+    },
+}
+
+struct PeerProbe {
+    addresses: Vec<Multiaddr>,
+    phase: ProbePhase,
+}
+
+impl PeerProbe {
+    fn is_probing(&self) -> bool {
+        matches!(
+            self.phase,
+            ProbePhase::Dialing(_) | ProbePhase::Identifying { .. }
+        )
+    }
+
+    fn finish(
+        &mut self,
+        reachability: Reachability,
+        info: Option<PeerInfo>,
+        error: Option<String>,
+    ) {
+        self.phase = ProbePhase::Done {
+            reachability,
+            info,
+            error,
+        };
+    }
+}
+
+/// Maps a DHT network by walking its keyspace with random FIND_NODE queries, then identifies
+/// every peer found
+pub struct CrawlClient {
+    network: String,
+    swarm: Swarm<PeerLookupBehaviour>,
+    probes: HashMap<PeerId, PeerProbe>,
+    queries_left: usize,
+    queries_in_flight: HashSet<KademliaQueryId>,
+    queries: usize,
+    parallelism: usize,
+    timeouts: LookupTimeouts,
+    /// Entry points of the walks, kept connected for the whole crawl
+    bootnodes: HashSet<PeerId>,
+    /// Probed peers to disconnect from once no walk may still query them
+    pending_releases: Vec<PeerId>,
+}
+
+impl CrawlClient {
+    /// Starts configuring a crawl of the given DHT network
+    pub fn builder(dht_network: DHTNetwork) -> CrawlClientBuilder {
+        CrawlClientBuilder::new(dht_network)
+    }
+
+    fn discover(&mut self, peer_id: PeerId, addresses: impl IntoIterator<Item = Multiaddr>) {
+        if &peer_id == self.swarm.local_peer_id() {
+            return;
+        }
+
+        let probe = self.probes.entry(peer_id).or_insert_with(|| {
+            log_debug!("Discovered \"{peer_id}\"");
+
+            PeerProbe {
+                addresses: Vec::new(),
+                phase: ProbePhase::Discovered,
+            }
+        });
+
+        for address in addresses {
+            if !probe.addresses.contains(&address) {
+                probe.addresses.push(address);
+            }
+        }
+    }
+
+    fn start_queries(&mut self) {
+        while self.queries_left > 0 && self.queries_in_flight.len() < self.parallelism {
+            let query_id = self
+                .swarm
+                .behaviour_mut()
+                .dht
+                .get_closest_peers(PeerId::random());
+            self.queries_in_flight.insert(query_id);
+            self.queries_left -= 1;
+        }
+    }
+
+    fn start_probes(&mut self) {
+        let probing = self
+            .probes
+            .values()
+            .filter(|probe| probe.is_probing())
+            .count();
+        let discovered = self
+            .probes
+            .iter()
+            .filter(|(_, probe)| matches!(probe.phase, ProbePhase::Discovered))
+            .map(|(peer_id, _)| *peer_id)
+            .take(self.parallelism.saturating_sub(probing))
+            .collect::<Vec<_>>();
+
+        for peer_id in discovered {
+            let probe = self
+                .probes
+                .get_mut(&peer_id)
+                .expect("Programmatic error: unknown probe!");
+
+            if self.swarm.is_connected(&peer_id) {
+                probe.phase = ProbePhase::Identifying {
+                    since: Instant::now(),
+                    established_in: Duration::ZERO,
+                };
+
+                continue;
+            }
+
+            let dial_opts = DialOpts::peer_id(peer_id)
+                .addresses(probe.addresses.clone())
+                .condition(PeerCondition::NotDialing)
+                .build();
+
+            match self.swarm.dial(dial_opts) {
+                Ok(()) => probe.phase = ProbePhase::Dialing(Instant::now()),
+                Err(error) => {
+                    probe.finish(Reachability::Unreachable, None, Some(error.to_string()))
+                }
+            }
+        }
+    }
+
+    fn has_walks_left(&self) -> bool {
+        self.queries_left > 0 || !self.queries_in_flight.is_empty()
+    }
+
+    /// Closes the connections to a probed peer, so that the crawl doesn't keep every peer it
+    /// identified connected, once the walks are done as they may still query it
+    fn release(&mut self, peer_id: PeerId) {
+        if self.bootnodes.contains(&peer_id) {
+            return;
+        }
+
+        if self.has_walks_left() {
+            self.pending_releases.push(peer_id);
+
+            return;
+        }
+
+        if self.swarm.disconnect_peer_id(peer_id).is_ok() {
+            log_debug!("Disconnected from \"{peer_id}\"");
+        }
+    }
+
+    fn release_pending(&mut self) {
+        if self.has_walks_left() {
+            return;
+        }
+
+        for peer_id in std::mem::take(&mut self.pending_releases) {
+            self.release(peer_id);
+        }
+    }
+
+    fn on_query_progressed(
+        &mut self,
+        id: KademliaQueryId,
+        query_result: Result<KademliaGetClosestPeersOk, KademliaGetClosestPeersError>,
+        last: bool,
+    ) {
+        let peers = match query_result {
+            Ok(KademliaGetClosestPeersOk { peers, .. }) => peers,
+            Err(KademliaGetClosestPeersError::Timeout { peers, .. }) => peers,
+        };
+
+        for peer_id in peers {
+            self.discover(peer_id, None);
+        }
+
+        if last && self.queries_in_flight.remove(&id) {
+            log_info!(
+                "Walk {}/{} done, {} peers found so far",
+                self.queries - self.queries_left - self.queries_in_flight.len(),
+                self.queries,
+                self.probes.len()
+            );
+        }
+    }
+
+    fn handle_event<THandlerErr: Debug>(
+        &mut self,
+        event: SwarmEvent<PeerLookupBehaviourEvent, THandlerErr>,
+    ) {
+        match event {
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                KademliaEvent::RoutingUpdated {
+                    peer, addresses, ..
+                },
+            )) => {
+                self.discover(peer, addresses.into_vec());
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: KademliaQueryResult::GetClosestPeers(query_result),
+                    step,
+                    ..
+                },
+            )) => {
+                self.on_query_progressed(id, query_result, step.last);
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                established_in,
+                ..
+            } => {
+                let dialed_address = match endpoint {
+                    ConnectedPoint::Dialer { address, .. } => Some(address),
+                    ConnectedPoint::Listener { .. } => None,
+                };
+                self.discover(peer_id, dialed_address);
+
+                if let Some(probe) = self.probes.get_mut(&peer_id) {
+                    if matches!(probe.phase, ProbePhase::Discovered | ProbePhase::Dialing(_)) {
+                        probe.phase = ProbePhase::Identifying {
+                            since: Instant::now(),
+                            established_in,
+                        };
+                    }
+                }
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+            } => {
+                if let Some(probe) = self.probes.get_mut(&peer_id) {
+                    if matches!(probe.phase, ProbePhase::Dialing(_)) {
+                        probe.finish(Reachability::Unreachable, None, Some(error.to_string()));
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(
+                IdentityEvent::Received { peer_id, info },
+            )) => {
+                self.discover(peer_id, None);
+                let probe = self
+                    .probes
+                    .get_mut(&peer_id)
+                    .expect("Programmatic error: undiscovered identified peer!");
+                let (established_in, identified_in) = match probe.phase {
+                    ProbePhase::Identifying {
+                        since,
+                        established_in,
+                    } => (established_in, since.elapsed()),
+                    _ => (Duration::ZERO, Duration::ZERO),
+                };
+
+                if !matches!(
+                    probe.phase,
+                    ProbePhase::Done {
+                        reachability: Reachability::Identified,
+                        ..
+                    }
+                ) {
+                    probe.finish(
+                        Reachability::Identified,
+                        Some(PeerInfo::new(info, established_in, identified_in)),
+                        None,
+                    );
+                }
+
+                self.release(peer_id);
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(IdentityEvent::Error {
+                peer_id,
+                error: ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(error)),
+            })) => {
+                if let Some(probe) = self.probes.get_mut(&peer_id) {
+                    if probe.is_probing() {
+                        probe.finish(
+                            Reachability::Unidentified,
+                            None,
+                            Some(
+                                PeerLookupError::ProtocolMismatch(format!(
+                                    "identify protocol can't be negotiated ({error})"
+                                ))
+                                .to_string(),
+                            ),
+                        );
+                        self.release(peer_id);
+                    }
+                }
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+
+    fn expire_probes(&mut self) {
+        let mut unidentified = Vec::new();
+
+        for (peer_id, probe) in self.probes.iter_mut() {
+            match probe.phase {
+                ProbePhase::Dialing(since) if since.elapsed() >= self.timeouts.dial => {
+                    probe.finish(
+                        Reachability::Unreachable,
+                        None,
+                        Some(PeerLookupError::DialTimeout(self.timeouts.dial).to_string()),
+                    );
+                }
+                ProbePhase::Identifying { since, .. }
+                    if since.elapsed() >= self.timeouts.identify =>
+                {
+                    probe.finish(
+                        Reachability::Unidentified,
+                        None,
+                        Some(PeerLookupError::IdentifyTimeout(self.timeouts.identify).to_string()),
+                    );
+                    unidentified.push(*peer_id);
+                }
+                _ => {}
+            }
+        }
+
+        for peer_id in unidentified {
+            self.release(peer_id);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.queries_left == 0
+            && self.queries_in_flight.is_empty()
+            && self
+                .probes
+                .values()
+                .all(|probe| matches!(probe.phase, ProbePhase::Done { .. }))
+    }
+
+    fn into_report(self, elapsed: Duration) -> CrawlReport {
+        let mut peers = self
+            .probes
+            .into_iter()
+            .map(|(peer_id, probe)| {
+                let (reachability, info, error) = match probe.phase {
+                    ProbePhase::Done {
+                        reachability,
+                        info,
+                        error,
+                    } => (reachability, info, error),
+                    _ => (Reachability::Unknown, None, None),
+                };
+
+                CrawledPeer {
+                    peer_id,
+                    addresses: probe.addresses,
+                    reachability,
+                    info,
+                    error,
+                }
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by_key(|peer| peer.peer_id.to_base58());
+
+        CrawlReport {
+            network: self.network,
+            peers,
+            queries: self.queries - self.queries_left,
+            elapsed,
+        }
+    }
+
+    /// Crawls the network until every walk is done and every peer found is probed, or until the
+    /// total deadline
+    pub async fn do_crawl(mut self) -> CrawlReport {
+        let started_at = Instant::now();
+        let mut deadline_check = interval(Duration::from_millis(250));
+        deadline_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            self.start_queries();
+            self.start_probes();
+            self.release_pending();
+
+            if self.is_done() {
+                break;
+            }
+
+            if let Some(total_timeout) = self.timeouts.total {
+                if started_at.elapsed() >= total_timeout {
+                    log_info!("Crawl deadline of {total_timeout:?} reached");
+
+                    break;
+                }
+            }
+
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_event(event),
+                _ = deadline_check.tick() => self.expire_probes(),
+            }
+        }
+
+        self.into_report(started_at.elapsed())
+    }
+}
+
+/// Explicit configuration of a [`CrawlClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct CrawlClientBuilder {
+    dht_network: DHTNetwork,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    queries: usize,
+    parallelism: usize,
+}
+
+impl CrawlClientBuilder {
+    pub fn new(dht_network: DHTNetwork) -> Self {
+        Self {
+            dht_network,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+            queries: 32,
+            parallelism: 16,
+        }
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    /// Number of FIND_NODE walks towards random keys
+    pub fn queries(mut self, queries: usize) -> Self {
+        self.queries = queries.max(1);
+
+        self
+    }
+
+    /// Maximum number of walks, and of peer probes, running at the same time
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+
+        self
+    }
+
+    pub fn build(self) -> Result<CrawlClient, PeerLookupError> {
+        Ok(CrawlClient {
+            network: self.dht_network.name().to_owned(),
+            swarm: create_swarm(
+                &self.node_config,
+                Some(&self.dht_network),
+                self.timeouts.dht_query,
+            )?,
+            probes: HashMap::new(),
+            queries_left: self.queries,
+            queries_in_flight: HashSet::new(),
+            queries: self.queries,
+            parallelism: self.parallelism,
+            timeouts: self.timeouts,
+            bootnodes: self
+                .dht_network
+                .bootnodes()
+                .iter()
+                .map(|(_, peer_id)| *peer_id)
+                .collect(),
+            pending_releases: Vec::new(),
+        })
+    }
+}
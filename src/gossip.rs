@@ -0,0 +1,709 @@
+use crate::batch::BatchTarget;
+use crate::logging::{log_debug, log_info, log_warning};
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{within, LookupTimeouts, PeerInfo, PeerLookupClient, PeerLookupError};
+use futures::StreamExt;
+use libp2p::gossipsub::{
+    Behaviour as GossipsubBehaviour, Event as GossipsubEvent, IdentTopic,
+    MessageAcceptance as GossipsubMessageAcceptance, TopicHash,
+};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{timeout, Duration};
+
+/// Gossipsub message received from the mesh, only on its first receipt since gossipsub drops
+/// duplicates without reporting them
+pub struct ObservedMessage {
+    pub message_id: String,
+    /// Peer which forwarded the message to us
+    pub propagation_source: PeerId,
+    /// Publisher of the message, `None` for anonymous messages
+    pub source: Option<PeerId>,
+    /// Sequence number given by the publisher, increasing per message but not a timestamp
+    pub sequence_number: Option<u64>,
+    pub topic: String,
+    /// Size of the message data in bytes
+    pub size: usize,
+}
+
+/// Observation of the gossipsub traffic of a target
+pub enum GossipEvent {
+    /// Target connected and identified, observation starts
+    Connected(PeerInfo),
+    /// Target refused the gossipsub protocol
+    GossipsubNotSupported,
+    /// Local subscription to a topic, given or discovered
+    Subscribed {
+        topic: String,
+    },
+    SubscribeFailed {
+        topic: String,
+        error: String,
+    },
+    /// Subscription of a connected peer, the target or another one
+    PeerSubscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+    PeerUnsubscribed {
+        peer_id: PeerId,
+        topic: String,
+    },
+    Message(ObservedMessage),
+    /// Explicitly requested publication of a message
+    Published {
+        topic: String,
+        message_id: String,
+        size: usize,
+    },
+    PublishFailed {
+        topic: String,
+        error: String,
+    },
+    /// Last connection to the target closed, observation ends
+    Disconnected {
+        cause: Option<String>,
+    },
+}
+
+impl GossipEvent {
+    /// Short machine-readable name of the event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Connected(_) => "connected",
+            Self::GossipsubNotSupported => "gossipsub_not_supported",
+            Self::Subscribed { .. } => "subscribed",
+            Self::SubscribeFailed { .. } => "subscribe_failed",
+            Self::PeerSubscribed { .. } => "peer_subscribed",
+            Self::PeerUnsubscribed { .. } => "peer_unsubscribed",
+            Self::Message(_) => "message",
+            Self::Published { .. } => "published",
+            Self::PublishFailed { .. } => "publish_failed",
+            Self::Disconnected { .. } => "disconnected",
+        }
+    }
+}
+
+impl Display for GossipEvent {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Connected(peer_info) => {
+                write!(formatter, "connected\n********\n{peer_info}********")
+            }
+            Self::GossipsubNotSupported => write!(formatter, "gossipsub not supported by target"),
+            Self::Subscribed { topic } => write!(formatter, "subscribed to \"{topic}\""),
+            Self::SubscribeFailed { topic, error } => {
+                write!(formatter, "can't subscribe to \"{topic}\": {error}")
+            }
+            Self::PeerSubscribed { peer_id, topic } => {
+                write!(formatter, "\"{peer_id}\" subscribed to \"{topic}\"")
+            }
+            Self::PeerUnsubscribed { peer_id, topic } => {
+                write!(formatter, "\"{peer_id}\" unsubscribed from \"{topic}\"")
+            }
+            Self::Message(message) => {
+                write!(
+                    formatter,
+                    "message {} on \"{}\", {} bytes",
+                    message.message_id, message.topic, message.size
+                )?;
+
+                match message.source {
+                    Some(source) => write!(formatter, " from \"{source}\"")?,
+                    None => write!(formatter, " from an anonymous source")?,
+                }
+
+                if let Some(sequence_number) = message.sequence_number {
+                    write!(formatter, " (seqno {sequence_number})")?;
+                }
+
+                write!(formatter, " via \"{}\"", message.propagation_source)
+            }
+            Self::Published {
+                topic,
+                message_id,
+                size,
+            } => {
+                write!(
+                    formatter,
+                    "published message {message_id} on \"{topic}\", {size} bytes"
+                )
+            }
+            Self::PublishFailed { topic, error } => {
+                write!(formatter, "can't publish on \"{topic}\": {error}")
+            }
+            Self::Disconnected { cause: Some(cause) } => {
+                write!(formatter, "disconnected ({cause})")
+            }
+            Self::Disconnected { cause: None } => write!(formatter, "disconnected"),
+        }
+    }
+}
+
+/// Event of a gossipsub observation
+pub struct GossipRecord {
+    pub at: SystemTime,
+    pub event: GossipEvent,
+}
+
+fn as_millis_f64(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Serialize for GossipRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let timestamp_ms = self
+            .at
+            .duration_since(UNIX_EPOCH)
+            .map(as_millis_f64)
+            .unwrap_or_default();
+        let mut state = serializer.serialize_struct("GossipRecord", 8)?;
+        state.serialize_field("timestamp_ms", &timestamp_ms)?;
+        state.serialize_field("event", self.event.kind())?;
+
+        match &self.event {
+            GossipEvent::Connected(peer_info) => {
+                state.serialize_field("peer", peer_info)?;
+            }
+            GossipEvent::GossipsubNotSupported => {}
+            GossipEvent::Subscribed { topic } => {
+                state.serialize_field("topic", topic)?;
+            }
+            GossipEvent::SubscribeFailed { topic, error }
+            | GossipEvent::PublishFailed { topic, error } => {
+                state.serialize_field("topic", topic)?;
+                state.serialize_field("error", error)?;
+            }
+            GossipEvent::PeerSubscribed { peer_id, topic }
+            | GossipEvent::PeerUnsubscribed { peer_id, topic } => {
+                state.serialize_field("peer_id", &peer_id.to_string())?;
+                state.serialize_field("topic", topic)?;
+            }
+            GossipEvent::Message(message) => {
+                state.serialize_field("message_id", &message.message_id)?;
+                state.serialize_field(
+                    "propagation_source",
+                    &message.propagation_source.to_string(),
+                )?;
+                state
+                    .serialize_field("source", &message.source.map(|source| source.to_string()))?;
+                state.serialize_field("sequence_number", &message.sequence_number)?;
+                state.serialize_field("topic", &message.topic)?;
+                state.serialize_field("size", &message.size)?;
+            }
+            GossipEvent::Published {
+                topic,
+                message_id,
+                size,
+            } => {
+                state.serialize_field("topic", topic)?;
+                state.serialize_field("message_id", message_id)?;
+                state.serialize_field("size", size)?;
+            }
+            GossipEvent::Disconnected { cause } => {
+                state.serialize_field("cause", cause)?;
+            }
+        }
+
+        state.end()
+    }
+}
+
+/// Connects to a single peer and observes the gossipsub topics and messages flowing through it,
+/// without forwarding any message nor publishing unless explicitly asked
+pub struct GossipObserverClient {
+    swarm: Swarm<PeerLookupBehaviour>,
+    target: BatchTarget,
+    timeouts: LookupTimeouts,
+    /// Topics subscribed to upon start, topics of the peers are subscribed to if empty
+    topics: Vec<String>,
+    /// Data published once on every topic, as soon as the target subscribes to it
+    publish: Option<Vec<u8>>,
+    unpublished_topics: Vec<String>,
+    duration: Option<Duration>,
+}
+
+impl GossipObserverClient {
+    /// Starts configuring a gossipsub observer of the given target
+    pub fn builder(target: BatchTarget) -> GossipObserverClientBuilder {
+        GossipObserverClientBuilder::new(target)
+    }
+
+    fn emit(event: GossipEvent, on_record: &mut impl FnMut(GossipRecord)) {
+        on_record(GossipRecord {
+            at: SystemTime::now(),
+            event,
+        });
+    }
+
+    fn gossipsub(&mut self) -> &mut GossipsubBehaviour {
+        self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .as_mut()
+            .expect("Programmatic error: gossipsub disabled!")
+    }
+
+    fn subscribe(&mut self, topic: &str) -> Option<GossipEvent> {
+        match self.gossipsub().subscribe(&IdentTopic::new(topic)) {
+            Ok(true) => Some(GossipEvent::Subscribed {
+                topic: topic.to_owned(),
+            }),
+            Ok(false) => None,
+            Err(error) => Some(GossipEvent::SubscribeFailed {
+                topic: topic.to_owned(),
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    fn publish(&mut self, topic: String, data: Vec<u8>) -> GossipEvent {
+        let size = data.len();
+
+        match self
+            .gossipsub()
+            .publish(IdentTopic::new(topic.clone()), data)
+        {
+            Ok(message_id) => GossipEvent::Published {
+                topic,
+                message_id: message_id.to_string(),
+                size,
+            },
+            Err(error) => GossipEvent::PublishFailed {
+                topic,
+                error: error.to_string(),
+            },
+        }
+    }
+
+    /// Subscribes to the topic when discovering topics, and publishes on it if the target awaited
+    fn on_peer_subscribed(
+        &mut self,
+        target_peer_id: PeerId,
+        peer_id: PeerId,
+        topic: TopicHash,
+    ) -> Vec<GossipEvent> {
+        let topic = topic.into_string();
+        let mut events = vec![GossipEvent::PeerSubscribed {
+            peer_id,
+            topic: topic.clone(),
+        }];
+
+        if self.topics.is_empty() {
+            events.extend(self.subscribe(&topic));
+        }
+
+        if peer_id == target_peer_id {
+            if let Some(position) = self
+                .unpublished_topics
+                .iter()
+                .position(|unpublished_topic| *unpublished_topic == topic)
+            {
+                let topic = self.unpublished_topics.swap_remove(position);
+                let data = self
+                    .publish
+                    .clone()
+                    .expect("Programmatic error: unpublished topic without data!");
+                events.push(self.publish(topic, data));
+            }
+        }
+
+        events
+    }
+
+    fn on_gossipsub_event(
+        &mut self,
+        target_peer_id: PeerId,
+        event: GossipsubEvent,
+    ) -> Vec<GossipEvent> {
+        match event {
+            GossipsubEvent::Message {
+                propagation_source,
+                message_id,
+                message,
+            } => {
+                // Observed messages are neither forwarded nor penalized
+                if let Err(error) = self.gossipsub().report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    GossipsubMessageAcceptance::Ignore,
+                ) {
+                    log_debug!("Can't ignore message {message_id}: {error}");
+                }
+
+                vec![GossipEvent::Message(ObservedMessage {
+                    message_id: message_id.to_string(),
+                    propagation_source,
+                    source: message.source,
+                    sequence_number: message.sequence_number,
+                    topic: message.topic.into_string(),
+                    size: message.data.len(),
+                })]
+            }
+            GossipsubEvent::Subscribed { peer_id, topic } => {
+                self.on_peer_subscribed(target_peer_id, peer_id, topic)
+            }
+            GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                vec![GossipEvent::PeerUnsubscribed {
+                    peer_id,
+                    topic: topic.into_string(),
+                }]
+            }
+            GossipsubEvent::GossipsubNotSupported { peer_id } if peer_id == target_peer_id => {
+                vec![GossipEvent::GossipsubNotSupported]
+            }
+            GossipsubEvent::GossipsubNotSupported { .. } => Vec::new(),
+        }
+    }
+
+    async fn lookup(&mut self) -> Result<PeerInfo, PeerLookupError> {
+        match &self.target {
+            BatchTarget::Address(address) => {
+                PeerLookupClient::lookup_directly(&mut self.swarm, address.clone(), self.timeouts)
+                    .await
+            }
+            BatchTarget::PeerId(peer_id) => {
+                PeerLookupClient::lookup_via_dht(
+                    &mut self.swarm,
+                    *peer_id,
+                    self.timeouts,
+                    None,
+                    false,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Reports the gossipsub events until the target disconnects
+    async fn observe(&mut self, target_peer_id: PeerId, on_record: &mut impl FnMut(GossipRecord)) {
+        // Subscriptions are exchanged as soon as connected, usually before the identification
+        let known_subscriptions = self
+            .gossipsub()
+            .all_peers()
+            .flat_map(|(peer_id, topics)| {
+                topics
+                    .into_iter()
+                    .map(|topic| (*peer_id, topic.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for (peer_id, topic) in known_subscriptions {
+            for event in self.on_peer_subscribed(target_peer_id, peer_id, topic) {
+                Self::emit(event, on_record);
+            }
+        }
+
+        loop {
+            match self
+                .swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Gossipsub(event)) => {
+                    for event in self.on_gossipsub_event(target_peer_id, event) {
+                        Self::emit(event, on_record);
+                    }
+                }
+                SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    num_established: 0,
+                    cause,
+                    ..
+                } if peer_id == target_peer_id => {
+                    Self::emit(
+                        GossipEvent::Disconnected {
+                            cause: cause.as_ref().map(|cause| format!("{cause:?}")),
+                        },
+                        on_record,
+                    );
+
+                    return;
+                }
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    }
+
+    /// Looks the target up, then observes it until it disconnects or the observation duration
+    /// elapses, reporting every event through `on_record`
+    pub async fn do_observe(
+        mut self,
+        mut on_record: impl FnMut(GossipRecord),
+    ) -> Result<(), PeerLookupError> {
+        for topic in self.topics.clone() {
+            if let Some(event) = self.subscribe(&topic) {
+                Self::emit(event, &mut on_record);
+            }
+        }
+
+        let peer_info = match self.timeouts.total {
+            Some(total_timeout) => {
+                within(
+                    total_timeout,
+                    PeerLookupError::LookupTimeout(total_timeout),
+                    self.lookup(),
+                )
+                .await?
+            }
+            None => self.lookup().await?,
+        };
+        let target_peer_id = *peer_info.id();
+
+        if !peer_info
+            .protocols()
+            .iter()
+            .any(|protocol| protocol.starts_with("/meshsub/"))
+        {
+            log_warning!("\"{target_peer_id}\" doesn't advertise gossipsub");
+        }
+
+        Self::emit(GossipEvent::Connected(peer_info), &mut on_record);
+
+        match self.duration {
+            Some(duration) => {
+                if timeout(duration, self.observe(target_peer_id, &mut on_record))
+                    .await
+                    .is_err()
+                {
+                    log_info!("Observation of \"{target_peer_id}\" ended after {duration:?}");
+                }
+            }
+            None => self.observe(target_peer_id, &mut on_record).await,
+        }
+
+        Ok(())
+    }
+}
+
+/// Explicit configuration of a [`GossipObserverClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct GossipObserverClientBuilder {
+    target: BatchTarget,
+    dht_network: Option<DHTNetwork>,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    topics: Vec<String>,
+    publish: Option<Vec<u8>>,
+    duration: Option<Duration>,
+}
+
+impl GossipObserverClientBuilder {
+    pub fn new(target: BatchTarget) -> Self {
+        Self {
+            target,
+            dht_network: None,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+            topics: Vec::new(),
+            publish: None,
+            duration: None,
+        }
+    }
+
+    /// DHT network used to find the target given by peer ID
+    pub fn dht_network(mut self, dht_network: DHTNetwork) -> Self {
+        self.dht_network = Some(dht_network);
+
+        self
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    /// Topics to subscribe to, the ones the peers subscribe to are discovered and subscribed to if
+    /// empty
+    pub fn topics(mut self, topics: Vec<String>) -> Self {
+        self.topics = topics;
+
+        self
+    }
+
+    /// Data to publish once on every given topic, as soon as the target subscribes to it, nothing
+    /// is ever published if unset
+    pub fn publish(mut self, publish: Option<Vec<u8>>) -> Self {
+        self.publish = publish;
+
+        self
+    }
+
+    /// Duration of the observation once connected, until the target disconnects if unset
+    pub fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.duration = duration;
+
+        self
+    }
+
+    pub fn build(mut self) -> Result<GossipObserverClient, PeerLookupError> {
+        self.node_config.gossipsub = true;
+        let unpublished_topics = if self.publish.is_some() {
+            self.topics.clone()
+        } else {
+            Vec::new()
+        };
+
+        Ok(GossipObserverClient {
+            swarm: create_swarm(
+                &self.node_config,
+                self.dht_network.as_ref(),
+                self.timeouts.dht_query,
+            )?,
+            target: self.target,
+            timeouts: self.timeouts,
+            topics: self.topics,
+            publish: self.publish,
+            unpublished_topics,
+            duration: self.duration,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TOPIC: &str = "goro/blocks";
+
+    fn observer_client(target_peer_id: PeerId, publish: Option<Vec<u8>>) -> GossipObserverClient {
+        GossipObserverClient::builder(BatchTarget::PeerId(target_peer_id))
+            .topics(vec![TOPIC.to_owned()])
+            .publish(publish)
+            .build()
+            .unwrap()
+    }
+
+    fn event_kinds(events: &[GossipEvent]) -> Vec<&'static str> {
+        events.iter().map(GossipEvent::kind).collect()
+    }
+
+    #[test]
+    fn serializes_message_records() {
+        let propagation_source = PeerId::random();
+        let source = PeerId::random();
+        let record = GossipRecord {
+            at: UNIX_EPOCH + Duration::from_millis(1_500),
+            event: GossipEvent::Message(ObservedMessage {
+                message_id: "0123".to_owned(),
+                propagation_source,
+                source: Some(source),
+                sequence_number: Some(42),
+                topic: TOPIC.to_owned(),
+                size: 7,
+            }),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            json!({
+                "timestamp_ms": 1500.0,
+                "event": "message",
+                "message_id": "0123",
+                "propagation_source": propagation_source.to_string(),
+                "source": source.to_string(),
+                "sequence_number": 42,
+                "topic": TOPIC,
+                "size": 7,
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_other_records() {
+        let peer_id = PeerId::random();
+        let record = |event| GossipRecord {
+            at: UNIX_EPOCH,
+            event,
+        };
+
+        assert_eq!(
+            serde_json::to_value(record(GossipEvent::PeerSubscribed {
+                peer_id,
+                topic: TOPIC.to_owned(),
+            }))
+            .unwrap(),
+            json!({
+                "timestamp_ms": 0.0,
+                "event": "peer_subscribed",
+                "peer_id": peer_id.to_string(),
+                "topic": TOPIC,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(record(GossipEvent::PublishFailed {
+                topic: TOPIC.to_owned(),
+                error: "InsufficientPeers".to_owned(),
+            }))
+            .unwrap(),
+            json!({
+                "timestamp_ms": 0.0,
+                "event": "publish_failed",
+                "topic": TOPIC,
+                "error": "InsufficientPeers",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(record(GossipEvent::Disconnected { cause: None })).unwrap(),
+            json!({"timestamp_ms": 0.0, "event": "disconnected", "cause": null})
+        );
+        assert_eq!(
+            serde_json::to_value(record(GossipEvent::GossipsubNotSupported)).unwrap(),
+            json!({"timestamp_ms": 0.0, "event": "gossipsub_not_supported"})
+        );
+    }
+
+    #[tokio::test]
+    async fn publishes_once_the_target_subscribes() {
+        let target_peer_id = PeerId::random();
+        let other_peer_id = PeerId::random();
+        let mut client = observer_client(target_peer_id, Some(b"hello".to_vec()));
+
+        // Neither another peer subscribing to the topic nor the target to another topic publishes
+        let events =
+            client.on_peer_subscribed(target_peer_id, other_peer_id, TopicHash::from_raw(TOPIC));
+        assert_eq!(event_kinds(&events), vec!["peer_subscribed"]);
+        let events = client.on_peer_subscribed(
+            target_peer_id,
+            target_peer_id,
+            TopicHash::from_raw("goro/transactions"),
+        );
+        assert_eq!(event_kinds(&events), vec!["peer_subscribed"]);
+
+        // No peer is connected, so the publication itself fails
+        let events =
+            client.on_peer_subscribed(target_peer_id, target_peer_id, TopicHash::from_raw(TOPIC));
+        assert_eq!(
+            event_kinds(&events),
+            vec!["peer_subscribed", "publish_failed"]
+        );
+
+        let events =
+            client.on_peer_subscribed(target_peer_id, target_peer_id, TopicHash::from_raw(TOPIC));
+        assert_eq!(event_kinds(&events), vec!["peer_subscribed"]);
+    }
+
+    #[tokio::test]
+    async fn never_publishes_without_data() {
+        let target_peer_id = PeerId::random();
+        let mut client = observer_client(target_peer_id, None);
+
+        let events =
+            client.on_peer_subscribed(target_peer_id, target_peer_id, TopicHash::from_raw(TOPIC));
+        assert_eq!(event_kinds(&events), vec!["peer_subscribed"]);
+    }
+}
@@ -0,0 +1,493 @@
+use crate::logging::log_info;
+use crate::protobuf::{put_bytes_field, put_varint_field, FieldReader, FieldValue};
+use blake2::{Blake2b512, Digest};
+use libp2p::identity::{ecdsa, ed25519, secp256k1, Keypair, PublicKey};
+use libp2p::PeerId;
+use multibase::Base as MultibaseBase;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// `Type` field of the libp2p `PublicKey` and `PrivateKey` protobuf messages
+const PROTOBUF_TYPE_FIELD: u32 = 1;
+/// `Data` field of the libp2p `PublicKey` and `PrivateKey` protobuf messages
+const PROTOBUF_DATA_FIELD: u32 = 2;
+const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
+/// Prefix of the SS58 checksum preimage
+const SS58_CHECKSUM_PREFIX: &[u8] = b"SS58PRE";
+const SS58_CHECKSUM_LENGTH: usize = 2;
+const DER_INTEGER_TAG: u8 = 0x02;
+const DER_BIT_STRING_TAG: u8 = 0x03;
+const DER_OCTET_STRING_TAG: u8 = 0x04;
+const DER_SEQUENCE_TAG: u8 = 0x30;
+/// `[0]` parameters of a SEC1 `ECPrivateKey`
+const DER_EC_PARAMETERS_TAG: u8 = 0xa0;
+/// `[1]` public key of a SEC1 `ECPrivateKey`
+const DER_EC_PUBLIC_KEY_TAG: u8 = 0xa1;
+/// Version of the SEC1 `ECPrivateKey` structure (RFC 5915)
+const SEC1_VERSION: u8 = 1;
+/// DER-encoded OID of the P-256 named curve (1.2.840.10045.3.1.7)
+const P256_CURVE_OID: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+#[derive(Debug, Error)]
+pub enum KeyFileError {
+    #[error("Can't read key file \"{0}\": {1}")]
+    Unreadable(PathBuf, #[source] std::io::Error),
+    #[error("Can't write key file \"{0}\": {1}")]
+    Unwritable(PathBuf, #[source] std::io::Error),
+    #[error("Bad key file \"{0}\": {1}")]
+    Malformed(PathBuf, String),
+}
+
+/// Type of a generated local keypair
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyType {
+    #[default]
+    Ed25519,
+    Secp256k1,
+    Ecdsa,
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ed25519" => Ok(Self::Ed25519),
+            "secp256k1" => Ok(Self::Secp256k1),
+            "ecdsa" => Ok(Self::Ecdsa),
+            _ => Err("Supported key types are ed25519, secp256k1 and ecdsa.".to_string()),
+        }
+    }
+}
+
+impl KeyType {
+    /// `KeyType` enum value of the libp2p protobuf messages
+    fn protobuf_value(&self) -> u64 {
+        match self {
+            Self::Ed25519 => 1,
+            Self::Secp256k1 => 2,
+            Self::Ecdsa => 3,
+        }
+    }
+
+    fn from_protobuf_value(value: u64) -> Result<Self, String> {
+        match value {
+            1 => Ok(Self::Ed25519),
+            2 => Ok(Self::Secp256k1),
+            3 => Ok(Self::Ecdsa),
+            0 => Err("RSA keys aren't supported".to_owned()),
+            _ => Err(format!("Unknown key type {value}")),
+        }
+    }
+
+    pub fn generate(&self) -> Keypair {
+        match self {
+            Self::Ed25519 => Keypair::generate_ed25519(),
+            Self::Secp256k1 => Keypair::generate_secp256k1(),
+            Self::Ecdsa => Keypair::generate_ecdsa(),
+        }
+    }
+}
+
+/// Appends a DER element, every element of an ECDSA private key being shorter than 128 bytes
+fn put_der_element(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    out.push(content.len() as u8);
+    out.extend_from_slice(content);
+}
+
+/// Takes a DER element of less than 128 bytes off the front of the input
+fn take_der_element<'a>(input: &mut &'a [u8]) -> Result<(u8, &'a [u8]), String> {
+    let bytes = *input;
+    let &[tag, length, ref rest @ ..] = bytes else {
+        return Err("Truncated DER element".to_owned());
+    };
+    let length = usize::from(length);
+
+    if length >= 0x80 || rest.len() < length {
+        return Err("Bad DER element length".to_owned());
+    }
+
+    let (content, rest) = rest.split_at(length);
+    *input = rest;
+
+    Ok((tag, content))
+}
+
+/// Encodes an ECDSA keypair as a SEC1 `ECPrivateKey` (RFC 5915), with the curve and public key
+fn encode_ecdsa_keypair(keypair: &ecdsa::Keypair) -> Vec<u8> {
+    let mut public_key_bits = vec![0];
+    public_key_bits.extend(keypair.public().to_bytes());
+    let mut public_key = Vec::new();
+    put_der_element(&mut public_key, DER_BIT_STRING_TAG, &public_key_bits);
+
+    let mut private_key = Vec::new();
+    put_der_element(&mut private_key, DER_INTEGER_TAG, &[SEC1_VERSION]);
+    put_der_element(
+        &mut private_key,
+        DER_OCTET_STRING_TAG,
+        &keypair.secret().to_bytes(),
+    );
+    put_der_element(&mut private_key, DER_EC_PARAMETERS_TAG, P256_CURVE_OID);
+    put_der_element(&mut private_key, DER_EC_PUBLIC_KEY_TAG, &public_key);
+    let mut encoded = Vec::new();
+    put_der_element(&mut encoded, DER_SEQUENCE_TAG, &private_key);
+
+    encoded
+}
+
+/// Secret scalar of a SEC1 `ECPrivateKey` (RFC 5915) of a P-256 key, its public key being
+/// derived from the scalar rather than read
+fn decode_ecdsa_secret_scalar(encoded: &[u8]) -> Result<&[u8], String> {
+    let mut input = encoded;
+    let (DER_SEQUENCE_TAG, mut private_key) = take_der_element(&mut input)? else {
+        return Err("Not a SEC1 EC private key".to_owned());
+    };
+
+    if !input.is_empty() {
+        return Err(format!(
+            "{} trailing bytes after EC private key",
+            input.len()
+        ));
+    }
+
+    let (DER_INTEGER_TAG, &[SEC1_VERSION]) = take_der_element(&mut private_key)? else {
+        return Err("Unsupported SEC1 EC private key version".to_owned());
+    };
+    let (DER_OCTET_STRING_TAG, secret_scalar) = take_der_element(&mut private_key)? else {
+        return Err("SEC1 EC private key without secret".to_owned());
+    };
+
+    while !private_key.is_empty() {
+        if let (DER_EC_PARAMETERS_TAG, parameters) = take_der_element(&mut private_key)? {
+            if parameters != P256_CURVE_OID {
+                return Err("EC private key isn't on the P-256 curve".to_owned());
+            }
+        }
+    }
+
+    Ok(secret_scalar)
+}
+
+/// Encodes a keypair as a libp2p `PrivateKey` protobuf message, holding the 64 bytes secret and
+/// public key for Ed25519, the 32 bytes secret scalar for secp256k1, or a DER-encoded SEC1
+/// `ECPrivateKey` for ECDSA
+pub fn encode_keypair(keypair: &Keypair) -> Result<Vec<u8>, String> {
+    #[allow(deprecated)]
+    let (key_type, data) = match keypair {
+        Keypair::Ed25519(keypair) => (KeyType::Ed25519, keypair.encode().to_vec()),
+        Keypair::Secp256k1(keypair) => (KeyType::Secp256k1, keypair.secret().to_bytes().to_vec()),
+        Keypair::Ecdsa(keypair) => (KeyType::Ecdsa, encode_ecdsa_keypair(keypair)),
+        Keypair::Rsa(_) => return Err("RSA keys aren't supported".to_owned()),
+    };
+    let mut encoded = Vec::new();
+    put_varint_field(&mut encoded, PROTOBUF_TYPE_FIELD, key_type.protobuf_value());
+    put_bytes_field(&mut encoded, PROTOBUF_DATA_FIELD, &data);
+
+    Ok(encoded)
+}
+
+/// Decodes a keypair encoded by [`encode_keypair`]
+pub fn decode_keypair(encoded: &[u8]) -> Result<Keypair, String> {
+    let mut key_type = None;
+    let mut data = None;
+
+    for field in FieldReader::new(encoded) {
+        match field? {
+            (PROTOBUF_TYPE_FIELD, FieldValue::Varint(value)) => {
+                key_type = Some(KeyType::from_protobuf_value(value)?)
+            }
+            (PROTOBUF_DATA_FIELD, FieldValue::Bytes(bytes)) => data = Some(bytes),
+            _ => {}
+        }
+    }
+
+    let (Some(key_type), Some(data)) = (key_type, data) else {
+        return Err("Not a libp2p private key protobuf message".to_owned());
+    };
+    let mut data = data.to_vec();
+
+    match key_type {
+        KeyType::Ed25519 => ed25519::Keypair::decode(&mut data).map(Keypair::from),
+        KeyType::Secp256k1 => secp256k1::SecretKey::from_bytes(&mut data)
+            .map(|secret_key| Keypair::from(secp256k1::Keypair::from(secret_key))),
+        KeyType::Ecdsa => ecdsa::SecretKey::from_bytes(decode_ecdsa_secret_scalar(&data)?)
+            .map(|secret_key| Keypair::from(ecdsa::Keypair::from(secret_key))),
+    }
+    .map_err(|error| error.to_string())
+}
+
+/// Encodes raw Ed25519 public key bytes as a libp2p `PublicKey` protobuf message
+fn encode_ed25519_public_key(public_key: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    put_varint_field(
+        &mut encoded,
+        PROTOBUF_TYPE_FIELD,
+        KeyType::Ed25519.protobuf_value(),
+    );
+    put_bytes_field(&mut encoded, PROTOBUF_DATA_FIELD, public_key);
+
+    encoded
+}
+
+/// Public key of an SS58 address, which is assumed to be of an Ed25519 key as Substrate network
+/// keys are
+fn decode_ss58_public_key(address: &str) -> Result<Vec<u8>, String> {
+    let decoded = MultibaseBase::Base58Btc
+        .decode(address)
+        .map_err(|error| error.to_string())?;
+    let prefix_length = match decoded.first() {
+        Some(0..=63) => 1,
+        Some(64..=127) => 2,
+        _ => return Err("Unsupported SS58 address type".to_owned()),
+    };
+
+    if decoded.len() != prefix_length + ED25519_PUBLIC_KEY_LENGTH + SS58_CHECKSUM_LENGTH {
+        return Err(format!(
+            "SS58 address of {} bytes doesn't hold a {ED25519_PUBLIC_KEY_LENGTH} bytes public key",
+            decoded.len()
+        ));
+    }
+
+    let (payload, checksum) = decoded.split_at(decoded.len() - SS58_CHECKSUM_LENGTH);
+    let hash = Blake2b512::new()
+        .chain_update(SS58_CHECKSUM_PREFIX)
+        .chain_update(payload)
+        .finalize();
+
+    if hash[..SS58_CHECKSUM_LENGTH] != *checksum {
+        return Err("Bad SS58 checksum".to_owned());
+    }
+
+    Ok(payload[prefix_length..].to_vec())
+}
+
+/// Parses a peer ID, given either by itself or by the public key it derives from:
+/// - a base58 peer ID (e.g. "12D3KooW...")
+/// - an SS58 address of an Ed25519 network key (e.g. "5F...")
+/// - a hex-encoded Ed25519 public key (32 bytes, `0x` prefix optional)
+/// - a hex-encoded libp2p `PublicKey` protobuf message, of any key type
+pub fn parse_peer_id(source: &str) -> Result<PeerId, String> {
+    let source = source.trim();
+
+    if let Ok(peer_id) = source.parse::<PeerId>() {
+        return Ok(peer_id);
+    }
+
+    let encoded_public_key = match hex::decode(source.strip_prefix("0x").unwrap_or(source)) {
+        Ok(encoded) if encoded.len() != ED25519_PUBLIC_KEY_LENGTH => encoded,
+        Ok(public_key) => encode_ed25519_public_key(&public_key),
+        Err(_) => {
+            let public_key = decode_ss58_public_key(source).map_err(|error| {
+                format!(
+                    "\"{source}\" isn't a peer ID, an SS58 address or a hex public key ({error})"
+                )
+            })?;
+
+            encode_ed25519_public_key(&public_key)
+        }
+    };
+    #[allow(deprecated)]
+    let public_key = PublicKey::from_protobuf_encoding(&encoded_public_key)
+        .map_err(|error| format!("Bad public key \"{source}\": {error}"))?;
+
+    Ok(public_key.to_peer_id())
+}
+
+/// Loads the keypair of a key file (whatever its type), or generates one of the given type and
+/// saves it if the file doesn't exist yet
+pub fn load_or_generate_keypair(path: &Path, key_type: KeyType) -> Result<Keypair, KeyFileError> {
+    if path.exists() {
+        let encoded = std::fs::read(path)
+            .map_err(|error| KeyFileError::Unreadable(path.to_owned(), error))?;
+        let keypair = decode_keypair(&encoded)
+            .map_err(|error| KeyFileError::Malformed(path.to_owned(), error))?;
+
+        return Ok(keypair);
+    }
+
+    let keypair = key_type.generate();
+    let encoded = encode_keypair(&keypair).expect("Programmatic error: unsupported generated key!");
+    write_key_file(path, &encoded)
+        .map_err(|error| KeyFileError::Unwritable(path.to_owned(), error))?;
+    log_info!("Generated {key_type:?} key file \"{}\"", path.display());
+
+    Ok(keypair)
+}
+
+#[cfg(unix)]
+fn write_key_file(path: &Path, encoded: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(encoded)
+}
+
+#[cfg(not(unix))]
+fn write_key_file(path: &Path, encoded: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Public key of Substrate's well-known "Alice" development account
+    const ALICE_PUBLIC_KEY: &str =
+        "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+    /// Peer ID derived from [`ALICE_PUBLIC_KEY`] taken as an Ed25519 key
+    const ALICE_PEER_ID: &str = "12D3KooWQ6jz4ttZfoBNKopouQWWkUVg93oWApg7ShLETzbnV3ec";
+    /// ECDSA private key test vector of the libp2p peer ID specification
+    const ECDSA_PRIVATE_KEY: &str = "08031279307702010104203e5b1fe9712e6c314942a750bd67485de3c1efe85b1bfb520ae8f9ae3dfa4a4ca00a06082a8648ce3d030107a14403420004de3d300fa36ae0e8f5d530899d83abab44abf3161f162a4bc901d8e6ecda020e8b6d5f8da30525e71d6851510c098e5c47c646a597fb4dcec034e9f77c409e62";
+    /// Peer ID of [`ECDSA_PRIVATE_KEY`]
+    const ECDSA_PEER_ID: &str = "QmVMT29id3TUASyfZZ6k9hmNyc2nYabCo4uMSpDw4zrgDk";
+
+    #[test]
+    fn parses_base58_peer_id() {
+        assert_eq!(
+            parse_peer_id(ALICE_PEER_ID).unwrap().to_string(),
+            ALICE_PEER_ID
+        );
+    }
+
+    #[test]
+    fn parses_ss58_address() {
+        assert_eq!(
+            parse_peer_id("5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutQY")
+                .unwrap()
+                .to_string(),
+            ALICE_PEER_ID
+        );
+    }
+
+    #[test]
+    fn parses_ss58_address_with_two_bytes_prefix() {
+        // Address type 1284
+        let address = "VdvKmYJfD4VXA9fzz1SbmCo2eYHSzUFbaDCZSuaNKJAe8YNg6";
+
+        assert_eq!(
+            hex::encode(decode_ss58_public_key(address).unwrap()),
+            ALICE_PUBLIC_KEY
+        );
+        assert_eq!(parse_peer_id(address).unwrap().to_string(), ALICE_PEER_ID);
+    }
+
+    #[test]
+    fn rejects_ss58_address_with_bad_checksum() {
+        let address = "5GrwvaEF5zXb26Fz9rcQpDWS57CtERHpNehXCPcNoHGKutL8";
+
+        assert_eq!(
+            decode_ss58_public_key(address).unwrap_err(),
+            "Bad SS58 checksum"
+        );
+        assert!(parse_peer_id(address).is_err());
+    }
+
+    #[test]
+    fn parses_hex_ed25519_public_key() {
+        assert_eq!(
+            parse_peer_id(ALICE_PUBLIC_KEY).unwrap().to_string(),
+            ALICE_PEER_ID
+        );
+        assert_eq!(
+            parse_peer_id(&format!("0x{ALICE_PUBLIC_KEY}"))
+                .unwrap()
+                .to_string(),
+            ALICE_PEER_ID
+        );
+    }
+
+    #[test]
+    fn parses_hex_protobuf_public_key() {
+        assert_eq!(
+            parse_peer_id(&format!("0x08011220{ALICE_PUBLIC_KEY}"))
+                .unwrap()
+                .to_string(),
+            ALICE_PEER_ID
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_peer_id("not a peer").is_err());
+        assert!(parse_peer_id("0x0801").is_err());
+    }
+
+    #[test]
+    fn round_trips_keypairs() {
+        for key_type in [KeyType::Ed25519, KeyType::Secp256k1, KeyType::Ecdsa] {
+            let keypair = key_type.generate();
+            let encoded = encode_keypair(&keypair).unwrap();
+            let decoded = decode_keypair(&encoded).unwrap();
+
+            assert_eq!(
+                decoded.public().to_peer_id(),
+                keypair.public().to_peer_id(),
+                "{key_type:?}"
+            );
+            assert_eq!(encode_keypair(&decoded).unwrap(), encoded, "{key_type:?}");
+        }
+    }
+
+    #[test]
+    fn decodes_ecdsa_spec_key() {
+        let encoded = hex::decode(ECDSA_PRIVATE_KEY).unwrap();
+        let keypair = decode_keypair(&encoded).unwrap();
+
+        assert_eq!(keypair.public().to_peer_id().to_string(), ECDSA_PEER_ID);
+        assert_eq!(encode_keypair(&keypair).unwrap(), encoded);
+    }
+
+    #[test]
+    fn decodes_ecdsa_key_without_optional_fields() {
+        let secret_scalar = &hex::decode(ECDSA_PRIVATE_KEY).unwrap()[11..43];
+        let mut private_key = Vec::new();
+        put_der_element(&mut private_key, DER_INTEGER_TAG, &[SEC1_VERSION]);
+        put_der_element(&mut private_key, DER_OCTET_STRING_TAG, secret_scalar);
+        let mut sec1_private_key = Vec::new();
+        put_der_element(&mut sec1_private_key, DER_SEQUENCE_TAG, &private_key);
+
+        assert_eq!(
+            decode_ecdsa_secret_scalar(&sec1_private_key).unwrap(),
+            secret_scalar
+        );
+
+        // secp256k1 (1.3.132.0.10) curve
+        let secp256k1_curve_oid = [0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+        put_der_element(
+            &mut private_key,
+            DER_EC_PARAMETERS_TAG,
+            &secp256k1_curve_oid,
+        );
+        let mut sec1_private_key = Vec::new();
+        put_der_element(&mut sec1_private_key, DER_SEQUENCE_TAG, &private_key);
+
+        assert_eq!(
+            decode_ecdsa_secret_scalar(&sec1_private_key).unwrap_err(),
+            "EC private key isn't on the P-256 curve"
+        );
+    }
+
+    #[test]
+    fn rejects_raw_ecdsa_scalar() {
+        let mut encoded = Vec::new();
+        put_varint_field(
+            &mut encoded,
+            PROTOBUF_TYPE_FIELD,
+            KeyType::Ecdsa.protobuf_value(),
+        );
+        put_bytes_field(
+            &mut encoded,
+            PROTOBUF_DATA_FIELD,
+            &hex::decode(ECDSA_PRIVATE_KEY).unwrap()[11..43],
+        );
+
+        assert!(decode_keypair(&encoded).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! GoRo Network - LibP2P inspectors
+//!
+//! Looks up a libp2p peer, either directly by its address or via a Kademlia DHT, and reports
+//! the identification it announces. Whole Kademlia DHT networks can be crawled the same way, and
+//! their routing tables dumped and their provider and value records queried. The gossipsub
+//! traffic flowing through a peer can be observed as well.
+
+pub mod addresses;
+pub mod batch;
+pub mod blocks;
+pub mod chain;
+pub mod crawl;
+pub mod gossip;
+pub mod keys;
+pub mod logging;
+pub mod metrics;
+pub mod negotiate;
+pub mod networks;
+pub mod node;
+pub mod peer;
+pub mod ping;
+pub mod probe;
+pub(crate) mod protobuf;
+pub mod protocols;
+pub mod records;
+pub mod routing;
+pub mod trace;
+pub mod transport;
+pub mod watch;
+
+pub use addresses::{AddressClass, AddressWarning};
+pub use batch::{
+    parse_batch_targets, BatchLookupClient, BatchLookupClientBuilder, BatchLookupRecord,
+    BatchTarget,
+};
+pub use blocks::{BlockHeader, BlockId, BlockProbe, BlockProbeError, DigestItem, FetchedBlock};
+pub use chain::{ChainProbe, ChainProbeError, ChainState, NodeRole};
+pub use crawl::{CrawlClient, CrawlClientBuilder, CrawlReport, CrawledPeer, Reachability};
+pub use gossip::{
+    GossipEvent, GossipObserverClient, GossipObserverClientBuilder, GossipRecord, ObservedMessage,
+};
+pub use keys::{KeyFileError, KeyType};
+pub use metrics::WatchMetrics;
+pub use negotiate::{NegotiationOutcome, ProtocolNegotiation};
+pub use networks::{DHTNetwork, NetworkRegistry, NetworksFileError};
+pub use node::{NodeConfig, PeerLookupBehaviour};
+pub use peer::{
+    LookupTarget, LookupTimeouts, PeerInfo, PeerLookupClient, PeerLookupClientBuilder,
+    PeerLookupError, PeerLookupResult,
+};
+pub use ping::PingStatistics;
+pub use probe::{AddressProbe, AddressProbeError};
+pub use protocols::{ParsedProtocol, ProtocolCategory, ProtocolChain};
+pub use records::{
+    FoundProvider, FoundRecord, RecordQuery, RecordQueryClient, RecordQueryClientBuilder,
+    RecordQueryError, RecordQueryReport,
+};
+pub use routing::{
+    RoutingTableBucket, RoutingTableClient, RoutingTableClientBuilder, RoutingTableEntry,
+    RoutingTableReport,
+};
+pub use trace::{QueryHop, QueryTrace};
+pub use transport::{Multiplexing, TransportConfig};
+pub use watch::{WatchClient, WatchClientBuilder, WatchEvent, WatchRecord, WatchStatus};
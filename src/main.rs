@@ -1,25 +1,645 @@
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-pub(crate) mod logging;
-pub(crate) mod networks;
 pub(crate) mod options;
-pub(crate) mod peer;
+pub(crate) mod output;
+
+use goro_p2p_inspector::logging::{self, log_error, log_info, log_warning};
+use goro_p2p_inspector::metrics::serve_metrics;
+use goro_p2p_inspector::{
+    parse_batch_targets, BatchLookupClient, BatchTarget, CrawlClient, DHTNetwork,
+    GossipObserverClient, GossipObserverClientBuilder, LookupTarget, LookupTimeouts,
+    NetworkRegistry, NodeConfig, PeerLookupClient, PeerLookupClientBuilder, Reachability,
+    RecordQuery, RecordQueryClient, RecordQueryError, RoutingTableClient, WatchClient,
+    WatchMetrics,
+};
+use libp2p::kad::RecordKey;
+use options::{Command, Options};
+use output::OutputFormat;
+use prometheus_client::registry::Registry;
+use std::io::{BufReader, Read};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use structopt::StructOpt;
+use tokio::net::TcpListener;
+
+/// Exit code of a batch lookup where at least one target failed
+const BATCH_FAILURE_EXIT_CODE: u8 = 6;
+/// Exit code of a provider or record query which found nothing, of an empty routing table, or of
+/// a crawl which identified no peer
+const NOT_FOUND_EXIT_CODE: u8 = 7;
+/// Exit code of an unusable configuration or input: key, networks or targets file, unknown
+/// network, or metrics address
+const CONFIG_ERROR_EXIT_CODE: u8 = 8;
+
+/// Built-in networks, extended by the given networks file or else the default one (if it exists)
+fn load_network_registry(networks_file: Option<PathBuf>) -> Result<NetworkRegistry, String> {
+    let mut network_registry = NetworkRegistry::default();
+    let networks_file = match networks_file {
+        Some(path) => Some(path),
+        None => NetworkRegistry::default_networks_file().filter(|path| path.is_file()),
+    };
+
+    if let Some(path) = networks_file {
+        network_registry
+            .load_networks_file(&path)
+            .map_err(|error| error.to_string())?;
+    }
+
+    Ok(network_registry)
+}
+
+async fn lookup_single(
+    lookup_client_builder: PeerLookupClientBuilder,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    let (lookup_result, query_trace) = match lookup_client_builder.build() {
+        Ok(lookup_client) => lookup_client.do_traced_lookup().await,
+        Err(error) => (Err(error), None),
+    };
+
+    if let Some(report_format) = report_format {
+        report_format.print_lookup_result(&lookup_result, query_trace.as_ref());
+    }
+
+    match lookup_result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(error) => ExitCode::from(error.exit_code()),
+    }
+}
+
+fn read_batch_targets(input: Option<PathBuf>) -> Result<Vec<BatchTarget>, String> {
+    let reader: Box<dyn Read> = match input {
+        Some(path) if path.as_os_str() != "-" => Box::new(
+            std::fs::File::open(&path)
+                .map_err(|error| format!("Can't open \"{}\": {error}", path.display()))?,
+        ),
+        _ => Box::new(std::io::stdin()),
+    };
+
+    parse_batch_targets(BufReader::new(reader))
+}
+
+async fn lookup_batch(
+    targets: Vec<BatchTarget>,
+    dht_network: Option<DHTNetwork>,
+    parallelism: usize,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    if dht_network.is_none()
+        && targets
+            .iter()
+            .any(|target| matches!(target, BatchTarget::PeerId(_)))
+    {
+        log_warning!("Peers given by ID can't be found without \"--dht-network\"");
+    }
+
+    let mut builder = BatchLookupClient::builder(targets)
+        .node_config(node_config)
+        .timeouts(timeouts)
+        .parallelism(parallelism);
+
+    if let Some(dht_network) = dht_network {
+        builder = builder.dht_network(dht_network);
+    }
+
+    let batch_client = match builder.build() {
+        Ok(batch_client) => batch_client,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            return ExitCode::from(exit_code);
+        }
+    };
+    let mut all_succeeded = true;
+    batch_client
+        .do_lookup(|record| {
+            all_succeeded &= record.result.is_ok();
+
+            if let Some(report_format) = report_format {
+                report_format.print_batch_record(&record);
+            }
+        })
+        .await;
+
+    if all_succeeded {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(BATCH_FAILURE_EXIT_CODE)
+    }
+}
+
+async fn crawl(
+    dht_network: DHTNetwork,
+    queries: usize,
+    parallelism: usize,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    let crawl_client = match CrawlClient::builder(dht_network)
+        .node_config(node_config)
+        .timeouts(timeouts)
+        .queries(queries)
+        .parallelism(parallelism)
+        .build()
+    {
+        Ok(crawl_client) => crawl_client,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            return ExitCode::from(exit_code);
+        }
+    };
+    let crawl_report = crawl_client.do_crawl().await;
+
+    if let Some(report_format) = report_format {
+        report_format.print_crawl_report(&crawl_report);
+    }
+
+    if crawl_report.count(Reachability::Identified) == 0 {
+        ExitCode::from(NOT_FOUND_EXIT_CODE)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+async fn dump_routing_table(
+    dht_network: DHTNetwork,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    let routing_table_client = match RoutingTableClient::builder(dht_network)
+        .node_config(node_config)
+        .timeouts(timeouts)
+        .build()
+    {
+        Ok(routing_table_client) => routing_table_client,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            return ExitCode::from(exit_code);
+        }
+    };
+    let routing_table_report = routing_table_client.do_bootstrap().await;
+
+    if let Some(report_format) = report_format {
+        report_format.print_routing_table_report(&routing_table_report);
+    }
+
+    if routing_table_report.peer_count() == 0 {
+        ExitCode::from(NOT_FOUND_EXIT_CODE)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Watches the targets until interrupted, only returning if the swarm can't be created or the
+/// metrics address can't be bound
+async fn watch(
+    targets: Vec<BatchTarget>,
+    dht_network: Option<DHTNetwork>,
+    interval: Duration,
+    metrics_address: Option<SocketAddr>,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    if dht_network.is_none()
+        && targets
+            .iter()
+            .any(|target| matches!(target, BatchTarget::PeerId(_)))
+    {
+        log_warning!("Peers given by ID can't be found without \"--dht-network\"");
+    }
+
+    let mut builder = WatchClient::builder(targets)
+        .node_config(node_config)
+        .timeouts(timeouts)
+        .interval(interval);
+
+    if let Some(dht_network) = dht_network {
+        builder = builder.dht_network(dht_network);
+    }
+
+    if let Some(metrics_address) = metrics_address {
+        let listener = match TcpListener::bind(metrics_address).await {
+            Ok(listener) => listener,
+            Err(error) => {
+                log_error!("Can't serve metrics on \"{metrics_address}\": {error}");
+
+                return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+            }
+        };
+        let mut registry = Registry::default();
+        builder = builder.metrics(Arc::new(WatchMetrics::new(&mut registry)));
+        tokio::spawn(serve_metrics(listener, registry));
+        log_info!("Serving metrics on \"http://{metrics_address}/metrics\"");
+    }
+
+    let watch_client = match builder.build() {
+        Ok(watch_client) => watch_client,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            return ExitCode::from(exit_code);
+        }
+    };
+    watch_client
+        .do_watch(|record| {
+            if let Some(report_format) = report_format {
+                report_format.print_watch_record(&record);
+            }
+        })
+        .await;
+
+    ExitCode::SUCCESS
+}
+
+/// Observes the gossipsub traffic of the target until it disconnects or the duration elapses
+async fn observe_gossip(
+    observer_client_builder: GossipObserverClientBuilder,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    let observe_result = match observer_client_builder.build() {
+        Ok(observer_client) => {
+            observer_client
+                .do_observe(|record| {
+                    if let Some(report_format) = report_format {
+                        report_format.print_gossip_record(&record);
+                    }
+                })
+                .await
+        }
+        Err(error) => Err(error),
+    };
+
+    match observe_result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            ExitCode::from(exit_code)
+        }
+    }
+}
+
+/// Runs a provider or record query, then looks up every provider found if
+/// `identify_parallelism` is set
+async fn query_record(
+    query: RecordQuery,
+    key: RecordKey,
+    dht_network: DHTNetwork,
+    identify_parallelism: Option<usize>,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    report_format: Option<OutputFormat>,
+) -> ExitCode {
+    let query_client = match RecordQueryClient::builder(query, key, dht_network.clone())
+        .node_config(node_config.clone())
+        .timeouts(timeouts)
+        .build()
+    {
+        Ok(query_client) => query_client,
+        Err(error) => {
+            let exit_code = error.exit_code();
+
+            if let Some(report_format) = report_format {
+                report_format.print_lookup_result(&Err(error), None);
+            }
+
+            return ExitCode::from(exit_code);
+        }
+    };
+    let query_report = query_client.do_query().await;
+
+    if let Some(report_format) = report_format {
+        report_format.print_record_query_report(&query_report);
+    }
+
+    if !query_report.is_found() {
+        let exit_code = query_report
+            .error
+            .as_ref()
+            .and_then(RecordQueryError::exit_code)
+            .unwrap_or(NOT_FOUND_EXIT_CODE);
+
+        return ExitCode::from(exit_code);
+    }
+
+    match identify_parallelism {
+        Some(parallelism) => {
+            let targets = query_report
+                .providers
+                .iter()
+                .map(|provider| BatchTarget::PeerId(provider.peer_id))
+                .collect();
+
+            lookup_batch(
+                targets,
+                Some(dht_network),
+                parallelism,
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    logging::init_logger();
+async fn main() -> ExitCode {
+    let options = Options::from_args();
 
-    let lookup_client = peer::PeerLookupClient::create_from_options();
+    if !options.quiet {
+        logging::init_logger();
+    }
+
+    let node_config = match options.node_config() {
+        Ok(node_config) => node_config,
+        Err(error) => {
+            log_error!("{error}");
 
-    match lookup_client.do_lookup().await {
+            return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+        }
+    };
+    let timeouts = options.lookup_timeouts();
+    let report_format = options.report_format();
+    let network_registry = match load_network_registry(options.networks_file) {
+        Ok(network_registry) => network_registry,
         Err(error_message) => {
-            logging::log_error!("{error_message}");
+            log_error!("{error_message}");
+
+            return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
         }
-        Ok(lookup_result) => {
-            logging::log_info!("Peer information from lookup\n********\n{lookup_result}********");
+    };
+    match options.command {
+        Command::Direct {
+            address,
+            ping_samples,
+            probe_chain,
+            fetch_block,
+            verify_protocols,
+            extra_protocols,
+        } => {
+            let lookup_client_builder = PeerLookupClient::builder(LookupTarget::Direct(address))
+                .node_config(node_config)
+                .timeouts(timeouts)
+                .ping_samples(ping_samples)
+                .probe_chain(probe_chain)
+                .fetch_block(fetch_block)
+                .verify_protocols(verify_protocols)
+                .extra_protocols(extra_protocols);
+
+            lookup_single(lookup_client_builder, report_format).await
         }
-    }
+        Command::Dht {
+            peer_id,
+            network,
+            trace_query,
+            probe_all_addresses,
+            ping_samples,
+            probe_chain,
+            fetch_block,
+            verify_protocols,
+            extra_protocols,
+        } => {
+            let dht_network = match network.resolve(&network_registry) {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
 
-    Ok(())
+            let lookup_client_builder = PeerLookupClient::builder(LookupTarget::Dht {
+                peer_id,
+                dht_network,
+            })
+            .node_config(node_config)
+            .timeouts(timeouts)
+            .trace_query(trace_query)
+            .probe_all_addresses(probe_all_addresses)
+            .ping_samples(ping_samples)
+            .probe_chain(probe_chain)
+            .fetch_block(fetch_block)
+            .verify_protocols(verify_protocols)
+            .extra_protocols(extra_protocols);
+
+            lookup_single(lookup_client_builder, report_format).await
+        }
+        Command::Batch {
+            input,
+            dht_network,
+            parallelism,
+        } => {
+            let dht_network = match dht_network
+                .map(|name| network_registry.get(&name).cloned())
+                .transpose()
+            {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            let targets = match read_batch_targets(input) {
+                Ok(targets) => targets,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            lookup_batch(
+                targets,
+                dht_network,
+                parallelism,
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+        Command::Watch {
+            targets,
+            dht_network,
+            interval,
+            metrics_address,
+        } => {
+            let dht_network = match dht_network
+                .map(|name| network_registry.get(&name).cloned())
+                .transpose()
+            {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            watch(
+                targets,
+                dht_network,
+                Duration::from_secs(interval),
+                metrics_address,
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+        Command::ObserveGossip {
+            target,
+            dht_network,
+            topics,
+            publish,
+            duration,
+        } => {
+            let dht_network = match dht_network
+                .map(|name| network_registry.get(&name).cloned())
+                .transpose()
+            {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            if dht_network.is_none() && matches!(target, BatchTarget::PeerId(_)) {
+                log_warning!("Peers given by ID can't be found without \"--dht-network\"");
+            }
+
+            let mut observer_client_builder = GossipObserverClient::builder(target)
+                .node_config(node_config)
+                .timeouts(timeouts)
+                .topics(topics)
+                .publish(publish.map(String::into_bytes))
+                .duration(duration.map(Duration::from_secs));
+
+            if let Some(dht_network) = dht_network {
+                observer_client_builder = observer_client_builder.dht_network(dht_network);
+            }
+
+            observe_gossip(observer_client_builder, report_format).await
+        }
+        Command::Crawl {
+            network,
+            queries,
+            parallelism,
+        } => {
+            let dht_network = match network.resolve(&network_registry) {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            crawl(
+                dht_network,
+                queries,
+                parallelism,
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+        Command::RoutingTable { network } => {
+            let dht_network = match network.resolve(&network_registry) {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            dump_routing_table(dht_network, node_config, timeouts, report_format).await
+        }
+        Command::Providers {
+            key,
+            network,
+            identify,
+            parallelism,
+        } => {
+            let dht_network = match network.resolve(&network_registry) {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            query_record(
+                RecordQuery::Providers,
+                key,
+                dht_network,
+                identify.then_some(parallelism),
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+        Command::GetRecord { key, network } => {
+            let dht_network = match network.resolve(&network_registry) {
+                Ok(dht_network) => dht_network,
+                Err(error_message) => {
+                    log_error!("{error_message}");
+
+                    return ExitCode::from(CONFIG_ERROR_EXIT_CODE);
+                }
+            };
+
+            query_record(
+                RecordQuery::Record,
+                key,
+                dht_network,
+                None,
+                node_config,
+                timeouts,
+                report_format,
+            )
+            .await
+        }
+    }
 }
@@ -0,0 +1,316 @@
+use crate::logging::{log_debug, log_warning};
+use crate::node::PeerLookupBehaviourEvent;
+use crate::peer::PeerLookupError;
+use crate::watch::WatchEvent;
+use libp2p::metrics::{Metrics as Libp2pMetrics, Recorder};
+use libp2p::swarm::{DialError as SwarmDialError, SwarmEvent};
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::{Registry, Unit};
+use std::fmt::{Debug, Formatter, Result as FormatterResult};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+/// Prefix of the metrics of the inspector itself, libp2p's ones being prefixed by "libp2p"
+const METRICS_PREFIX: &str = "goro_p2p_inspector";
+const METRICS_CONTENT_TYPE: &str = "application/openmetrics-text;charset=utf-8;version=1.0.0";
+/// Maximum size of a scrape request head, larger requests are dropped
+const MAX_REQUEST_HEAD_SIZE: usize = 8 * 1024;
+/// Deadline of answering a scrape, so that a stalled client doesn't block the next ones
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct TargetLabels {
+    target: String,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, EncodeLabelSet, Debug)]
+struct ConnectionErrorLabels {
+    target: String,
+    kind: String,
+}
+
+/// 1ms to ~32s
+fn create_duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 16))
+}
+
+/// Short name of a dial error, as labelled in the metrics
+fn dial_error_kind(error: &SwarmDialError) -> &'static str {
+    match error {
+        SwarmDialError::Banned => "banned",
+        SwarmDialError::ConnectionLimit(_) => "connection_limit",
+        SwarmDialError::LocalPeerId { .. } => "local_peer_id",
+        SwarmDialError::NoAddresses => "no_addresses",
+        SwarmDialError::DialPeerConditionFalse(_) => "dial_peer_condition_false",
+        SwarmDialError::Aborted => "aborted",
+        SwarmDialError::InvalidPeerId(_) => "invalid_peer_id",
+        SwarmDialError::WrongPeerId { .. } => "wrong_peer_id",
+        SwarmDialError::Denied { .. } => "denied",
+        SwarmDialError::Transport(_) => "transport",
+    }
+}
+
+/// Prometheus metrics of a watch, along with libp2p's swarm and protocol metrics
+pub struct WatchMetrics {
+    libp2p: Libp2pMetrics,
+    up: Family<TargetLabels, Gauge>,
+    ping_rtt: Family<TargetLabels, Histogram>,
+    identify_latency: Family<TargetLabels, Histogram>,
+    dht_query_duration: Family<TargetLabels, Histogram>,
+    connection_errors: Family<ConnectionErrorLabels, Counter>,
+}
+
+impl Debug for WatchMetrics {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        formatter
+            .debug_struct("WatchMetrics")
+            .finish_non_exhaustive()
+    }
+}
+
+impl WatchMetrics {
+    /// Registers every metric into the given registry
+    pub fn new(registry: &mut Registry) -> Self {
+        let libp2p = Libp2pMetrics::new(registry);
+        let sub_registry = registry.sub_registry_with_prefix(METRICS_PREFIX);
+
+        let up = Family::default();
+        sub_registry.register(
+            "target_up",
+            "Whether the target is connected (1) or not (0)",
+            up.clone(),
+        );
+
+        let ping_rtt = Family::new_with_constructor(create_duration_histogram as fn() -> Histogram);
+        sub_registry.register_with_unit(
+            "target_ping_rtt",
+            "Round-trip time of the pings sent to the target",
+            Unit::Seconds,
+            ping_rtt.clone(),
+        );
+
+        let identify_latency =
+            Family::new_with_constructor(create_duration_histogram as fn() -> Histogram);
+        sub_registry.register_with_unit(
+            "target_identify_latency",
+            "Time from connection establishment to the identify message of the target",
+            Unit::Seconds,
+            identify_latency.clone(),
+        );
+
+        let dht_query_duration =
+            Family::new_with_constructor(create_duration_histogram as fn() -> Histogram);
+        sub_registry.register_with_unit(
+            "target_dht_query_duration",
+            "Duration of the DHT walks looking up the target",
+            Unit::Seconds,
+            dht_query_duration.clone(),
+        );
+
+        let connection_errors = Family::default();
+        sub_registry.register(
+            "target_connection_errors",
+            "Failed connection attempts to the target by kind of dial error",
+            connection_errors.clone(),
+        );
+
+        Self {
+            libp2p,
+            up,
+            ping_rtt,
+            identify_latency,
+            dht_query_duration,
+            connection_errors,
+        }
+    }
+
+    fn target_labels(target: &str) -> TargetLabels {
+        TargetLabels {
+            target: target.to_owned(),
+        }
+    }
+
+    /// Exposes the target as down until it's connected
+    pub(crate) fn add_target(&self, target: &str) {
+        self.up.get_or_create(&Self::target_labels(target)).set(0);
+    }
+
+    pub(crate) fn record_swarm_event<THandlerErr>(
+        &self,
+        event: &SwarmEvent<PeerLookupBehaviourEvent, THandlerErr>,
+    ) {
+        self.libp2p.record(event);
+
+        match event {
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(event)) => {
+                self.libp2p.record(event)
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Ping(event)) => {
+                self.libp2p.record(event)
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(event)) => {
+                self.libp2p.record(event)
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn record_watch_event(&self, target: &str, event: &WatchEvent) {
+        let error_kind = match event {
+            WatchEvent::Connected { .. } => {
+                self.up.get_or_create(&Self::target_labels(target)).set(1);
+
+                return;
+            }
+            WatchEvent::Disconnected { .. } => {
+                self.up.get_or_create(&Self::target_labels(target)).set(0);
+
+                return;
+            }
+            WatchEvent::LookupFailed(PeerLookupError::FailedToDialPeer(error)) => {
+                dial_error_kind(error)
+            }
+            WatchEvent::LookupFailed(PeerLookupError::DialTimeout(_)) => "timeout",
+            _ => return,
+        };
+
+        self.connection_errors
+            .get_or_create(&ConnectionErrorLabels {
+                target: target.to_owned(),
+                kind: error_kind.to_owned(),
+            })
+            .inc();
+    }
+
+    pub(crate) fn observe_ping_rtt(&self, target: &str, rtt: Duration) {
+        self.ping_rtt
+            .get_or_create(&Self::target_labels(target))
+            .observe(rtt.as_secs_f64());
+    }
+
+    pub(crate) fn observe_identify_latency(&self, target: &str, latency: Duration) {
+        self.identify_latency
+            .get_or_create(&Self::target_labels(target))
+            .observe(latency.as_secs_f64());
+    }
+
+    pub(crate) fn observe_dht_query_duration(&self, target: &str, duration: Duration) {
+        self.dht_query_duration
+            .get_or_create(&Self::target_labels(target))
+            .observe(duration.as_secs_f64());
+    }
+}
+
+/// Reads the request head, answering the metrics to `GET /metrics` and 404 to anything else
+async fn answer_scrape(mut stream: TcpStream, registry: &Registry) -> std::io::Result<()> {
+    let mut request_head = Vec::new();
+    let mut buffer = [0; 1024];
+
+    while !request_head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read_size = stream.read(&mut buffer).await?;
+
+        if read_size == 0 || request_head.len() + read_size > MAX_REQUEST_HEAD_SIZE {
+            return Ok(());
+        }
+
+        request_head.extend_from_slice(&buffer[..read_size]);
+    }
+
+    let request_line = request_head
+        .split(|byte| *byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut request_parts = request_line.split(|byte| *byte == b' ');
+    let is_scrape = request_parts.next() == Some(b"GET".as_slice())
+        && request_parts.next().map_or(false, |path| {
+            path == b"/metrics" || path.starts_with(b"/metrics?")
+        });
+    let response = if is_scrape {
+        let mut body = String::new();
+        encode(&mut body, registry).expect("Programmatic error: unencodable metrics!");
+
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {METRICS_CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Serves the metrics of the registry over HTTP on `/metrics`, one scrape at a time, forever
+pub async fn serve_metrics(listener: TcpListener, registry: Registry) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, remote_address)) => {
+                log_debug!("Metrics scrape from \"{remote_address}\"");
+
+                match timeout(SCRAPE_TIMEOUT, answer_scrape(stream, &registry)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(error)) => log_warning!(
+                        "Failed to answer metrics scrape from \"{remote_address}\": {error}"
+                    ),
+                    Err(_) => log_warning!(
+                        "Metrics scrape from \"{remote_address}\" timed out after {SCRAPE_TIMEOUT:?}"
+                    ),
+                }
+            }
+            Err(error) => log_warning!("Failed to accept metrics scrape: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn request(address: std::net::SocketAddr, request_line: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("{request_line}\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+    }
+
+    #[tokio::test]
+    async fn serves_watch_metrics() {
+        let mut registry = Registry::default();
+        let metrics = WatchMetrics::new(&mut registry);
+        metrics.add_target("alice");
+        metrics.add_target("bob");
+        metrics.record_watch_event(
+            "alice",
+            &WatchEvent::Connected {
+                established_in: Duration::from_millis(10),
+            },
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve_metrics(listener, registry));
+
+        let response = request(address, "GET /metrics HTTP/1.1").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("goro_p2p_inspector_target_up{target=\"alice\"} 1\n"));
+        assert!(response.contains("goro_p2p_inspector_target_up{target=\"bob\"} 0\n"));
+
+        let response = request(address, "GET /other HTTP/1.1").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = request(address, "POST /metrics HTTP/1.1").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
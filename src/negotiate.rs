@@ -0,0 +1,341 @@
+use crate::logging::{log_debug, log_info};
+use crate::node::{PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use futures::StreamExt;
+use libp2p::core::upgrade::{
+    DeniedUpgrade, NegotiationError, ProtocolName, ReadyUpgrade, UpgradeError,
+};
+use libp2p::core::Endpoint;
+use libp2p::swarm::handler::{ConnectionEvent, DialUpgradeError, FullyNegotiatedOutbound};
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent, ConnectionHandlerUpgrErr,
+    ConnectionId, FromSwarm, KeepAlive, NetworkBehaviour, NotifyHandler, PollParameters,
+    SubstreamProtocol, SwarmEvent, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use std::task::{Context, Poll};
+use tokio::time::Duration;
+
+/// Deadline of negotiating a single protocol on a fresh substream
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of proposing a protocol on a fresh outbound substream through multistream-select
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NegotiationOutcome {
+    Accepted,
+    /// The peer answered it doesn't support the protocol
+    Rejected,
+    Timeout,
+    /// The substream couldn't be opened or negotiated, e.g. the connection closed
+    Failed(String),
+}
+
+impl NegotiationOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Accepted => "accepted",
+            Self::Rejected => "rejected",
+            Self::Timeout => "timeout",
+            Self::Failed(_) => "failed",
+        }
+    }
+}
+
+impl Display for NegotiationOutcome {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Failed(error) => write!(formatter, "failed ({error})"),
+            other_outcome => formatter.write_str(other_outcome.as_str()),
+        }
+    }
+}
+
+/// Negotiation of a single protocol with a peer
+#[derive(Debug, Clone)]
+pub struct ProtocolNegotiation {
+    pub protocol: String,
+    /// Whether the peer listed the protocol in its identify message
+    pub advertised: bool,
+    pub outcome: NegotiationOutcome,
+}
+
+impl ProtocolNegotiation {
+    /// Whether the peer claims a protocol it doesn't serve
+    pub fn is_misadvertised(&self) -> bool {
+        self.advertised && self.outcome == NegotiationOutcome::Rejected
+    }
+}
+
+impl Serialize for ProtocolNegotiation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let error = match &self.outcome {
+            NegotiationOutcome::Failed(error) => Some(error),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("ProtocolNegotiation", 4)?;
+        state.serialize_field("protocol", &self.protocol)?;
+        state.serialize_field("advertised", &self.advertised)?;
+        state.serialize_field("outcome", self.outcome.as_str())?;
+        state.serialize_field("error", &error)?;
+
+        state.end()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NegotiatedProtocol(String);
+
+impl ProtocolName for NegotiatedProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Opens an outbound substream per requested protocol, dropping it as soon as it's negotiated
+pub struct ProtocolNegotiationHandler {
+    pending_protocols: VecDeque<String>,
+    pending_outcomes: VecDeque<(String, NegotiationOutcome)>,
+}
+
+impl ConnectionHandler for ProtocolNegotiationHandler {
+    /// Protocol to negotiate
+    type InEvent = String;
+    type OutEvent = (String, NegotiationOutcome);
+    type Error = Infallible;
+    type InboundProtocol = DeniedUpgrade;
+    type OutboundProtocol = ReadyUpgrade<NegotiatedProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = String;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(DeniedUpgrade, ())
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        KeepAlive::No
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        if let Some(outcome) = self.pending_outcomes.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::Custom(outcome));
+        }
+
+        if let Some(protocol) = self.pending_protocols.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(
+                    ReadyUpgrade::new(NegotiatedProtocol(protocol.clone())),
+                    protocol,
+                )
+                .with_timeout(NEGOTIATION_TIMEOUT),
+            });
+        }
+
+        Poll::Pending
+    }
+
+    fn on_behaviour_event(&mut self, protocol: Self::InEvent) {
+        self.pending_protocols.push_back(protocol);
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        let outcome = match event {
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                info: protocol,
+                ..
+            }) => (protocol, NegotiationOutcome::Accepted),
+            ConnectionEvent::DialUpgradeError(DialUpgradeError {
+                info: protocol,
+                error,
+            }) => match error {
+                ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(
+                    NegotiationError::Failed,
+                )) => (protocol, NegotiationOutcome::Rejected),
+                ConnectionHandlerUpgrErr::Timeout => (protocol, NegotiationOutcome::Timeout),
+                other_error => (
+                    protocol,
+                    NegotiationOutcome::Failed(other_error.to_string()),
+                ),
+            },
+            _ => return,
+        };
+
+        self.pending_outcomes.push_back(outcome);
+    }
+}
+
+/// Outcome of negotiating a protocol with a connected peer
+#[derive(Debug)]
+pub struct ProtocolNegotiationEvent {
+    pub peer_id: PeerId,
+    pub protocol: String,
+    pub outcome: NegotiationOutcome,
+}
+
+/// Negotiates arbitrary protocols with connected peers, without using the negotiated substreams
+#[derive(Default)]
+pub struct ProtocolNegotiationBehaviour {
+    pending_actions: VecDeque<ToSwarm<ProtocolNegotiationEvent, String>>,
+}
+
+impl ProtocolNegotiationBehaviour {
+    /// Proposes the protocol to the peer on a fresh substream of any of its connections
+    pub fn negotiate(&mut self, peer_id: PeerId, protocol: String) {
+        self.pending_actions.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: protocol,
+        });
+    }
+
+    fn create_handler() -> ProtocolNegotiationHandler {
+        ProtocolNegotiationHandler {
+            pending_protocols: VecDeque::new(),
+            pending_outcomes: VecDeque::new(),
+        }
+    }
+}
+
+impl NetworkBehaviour for ProtocolNegotiationBehaviour {
+    type ConnectionHandler = ProtocolNegotiationHandler;
+    type OutEvent = ProtocolNegotiationEvent;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Self::create_handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Self::create_handler())
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm<Self::ConnectionHandler>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _connection_id: ConnectionId,
+        (protocol, outcome): THandlerOutEvent<Self>,
+    ) {
+        self.pending_actions
+            .push_back(ToSwarm::GenerateEvent(ProtocolNegotiationEvent {
+                peer_id,
+                protocol,
+                outcome,
+            }));
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Self::OutEvent, THandlerInEvent<Self>>> {
+        match self.pending_actions.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Negotiates every advertised protocol of a connected peer along with extra ones, all at once,
+/// in the order of the advertised protocols then of the extra ones
+pub(crate) async fn verify_protocols(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    advertised_protocols: &[String],
+    extra_protocols: &[String],
+) -> Vec<ProtocolNegotiation> {
+    let mut protocols: Vec<String> = Vec::new();
+
+    for protocol in advertised_protocols.iter().chain(extra_protocols) {
+        if !protocols.contains(protocol) {
+            protocols.push(protocol.clone());
+        }
+    }
+
+    for protocol in &protocols {
+        swarm
+            .behaviour_mut()
+            .negotiation
+            .negotiate(peer_id, protocol.clone());
+    }
+
+    let mut outcomes = HashMap::new();
+
+    while outcomes.len() < protocols.len() {
+        match swarm
+            .next()
+            .await
+            .expect("Programmatic error: infinite streams!")
+        {
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Negotiation(event))
+                if event.peer_id == peer_id =>
+            {
+                log_debug!("Protocol \"{}\" {}", event.protocol, event.outcome);
+                outcomes.insert(event.protocol, event.outcome);
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id: closed_peer_id,
+                num_established: 0,
+                ..
+            } if closed_peer_id == peer_id => {
+                log_debug!("Connection to \"{peer_id}\" closed while verifying protocols");
+
+                break;
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+
+    let negotiations = protocols
+        .into_iter()
+        .map(|protocol| ProtocolNegotiation {
+            advertised: advertised_protocols.contains(&protocol),
+            outcome: outcomes
+                .remove(&protocol)
+                .unwrap_or_else(|| NegotiationOutcome::Failed("connection closed".to_owned())),
+            protocol,
+        })
+        .collect::<Vec<_>>();
+    let misadvertised_count = negotiations
+        .iter()
+        .filter(|negotiation| negotiation.is_misadvertised())
+        .count();
+    log_info!(
+        "Verified {} protocol(s) of \"{peer_id}\", {misadvertised_count} advertised but rejected",
+        negotiations.len()
+    );
+
+    negotiations
+}
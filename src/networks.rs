@@ -1,101 +1,578 @@
+use crate::chain::{protocol_genesis_hash, BlockHash};
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
 use libp2p::{Multiaddr, PeerId};
-use std::str::FromStr;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
-pub(crate) enum DHTNetwork {
-    GoRo,
-    Ipfs,
-    Krigan,
+const NETWORKS_FILE_DIRECTORY: &str = "goro-p2p-inspector";
+const NETWORKS_FILE_NAME: &str = "networks.toml";
+const KRIGAN_GENESIS_HASH: &str =
+    "c2f2d8da3e2d6b63970b6de8e365b84393ee0c69a9aec3a0bc750029a3552d07";
+const SUBSTRATE_DEFAULT_CHAIN_ID: &str = "sup";
+
+#[derive(Debug, Error)]
+pub enum NetworksFileError {
+    #[error("Can't read networks file \"{0}\": {1}")]
+    Unreadable(PathBuf, #[source] std::io::Error),
+    #[error("Bad networks file \"{0}\": {1}")]
+    Malformed(PathBuf, #[source] toml::de::Error),
+    #[error("Network \"{0}\" has no bootnode")]
+    NoBootnodes(String),
+    #[error("Bad bootnode \"{1}\" of network \"{0}\": {2}")]
+    BadBootnode(String, String, String),
+    #[error("Bad genesis hash of network \"{0}\": {1}")]
+    BadGenesisHash(String, String),
 }
 
-impl FromStr for DHTNetwork {
-    type Err = String;
+/// Kademlia DHT network, either built-in or defined in a networks file
+#[derive(Debug, Clone)]
+pub struct DHTNetwork {
+    name: String,
+    bootnodes: Vec<(Multiaddr, PeerId)>,
+    kad_protocols: Vec<String>,
+    identify_protocol_version: Option<String>,
+}
 
-    fn from_str(source: &str) -> Result<Self, Self::Err> {
-        match source.to_lowercase().as_str() {
-            "goro" => Ok(Self::GoRo),
-            "ipfs" => Ok(Self::Ipfs),
-            "krigan" => Ok(Self::Krigan),
-            _ => Err("Supported networks are GoRo, IPFS, and Krigan.".to_owned()),
+impl DHTNetwork {
+    pub fn new(
+        name: String,
+        bootnodes: Vec<(Multiaddr, PeerId)>,
+        kad_protocols: Vec<String>,
+        identify_protocol_version: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            bootnodes,
+            kad_protocols,
+            identify_protocol_version,
         }
     }
-}
 
-impl DHTNetwork {
-    pub(crate) fn bootnodes(&self) -> Vec<(Multiaddr, PeerId)> {
-        match self {
-            Self::GoRo => {
-                vec![(
-                    "/ip4/108.136.33.203/tcp/21212"
+    /// Network without any bootnode nor protocol, to be completed ad hoc
+    pub fn ad_hoc() -> Self {
+        Self::new("ad-hoc".to_owned(), Vec::new(), Vec::new(), None)
+    }
+
+    pub fn goro() -> Self {
+        Self::new(
+            "GoRo".to_owned(),
+            vec![(
+                "/ip4/108.136.33.203/tcp/21212"
+                    .parse()
+                    .expect("Bad MultiAddr!"),
+                "12D3KooWPHzn5X8uGTSZKTHBgfAyMViJZGwpKaej26gXNsLuGKua"
+                    .parse()
+                    .expect("Bad PeerID!"),
+            )],
+            Vec::new(),
+            None,
+        )
+        .with_kad_protocols(
+            substrate_kad_protocols(None, Some(SUBSTRATE_DEFAULT_CHAIN_ID))
+                .expect("Programmatic error: bad built-in chain!"),
+        )
+    }
+
+    pub fn ipfs() -> Self {
+        Self::new(
+            "IPFS".to_owned(),
+            vec![
+                (
+                    "/ip4/104.131.131.82/tcp/4001"
                         .parse()
                         .expect("Bad MultiAddr!"),
-                    "12D3KooWPHzn5X8uGTSZKTHBgfAyMViJZGwpKaej26gXNsLuGKua"
+                    "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
                         .parse()
                         .expect("Bad PeerID!"),
-                )]
-            }
-            Self::Ipfs => {
-                vec![
-                    (
-                        "/ip4/104.131.131.82/tcp/4001"
-                            .parse()
-                            .expect("Bad MultiAddr!"),
-                        "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
-                            .parse()
-                            .expect("Bad PeerID!"),
-                    ),
-                    (
-                        "/dnsaddr/bootstrap.libp2p.io"
-                            .parse()
-                            .expect("Bad MultiAddr!"),
-                        "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
-                            .parse()
-                            .expect("Bad PeerID!"),
-                    ),
-                    (
-                        "/dnsaddr/bootstrap.libp2p.io"
-                            .parse()
-                            .expect("Bad MultiAddr!"),
-                        "QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa"
-                            .parse()
-                            .expect("Bad PeerID!"),
-                    ),
-                    (
-                        "/dnsaddr/bootstrap.libp2p.io"
-                            .parse()
-                            .expect("Bad MultiAddr!"),
-                        "QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb"
-                            .parse()
-                            .expect("Bad PeerID!"),
-                    ),
-                    (
-                        "/dnsaddr/bootstrap.libp2p.io"
-                            .parse()
-                            .expect("Bad MultiAddr!"),
-                        "QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt"
-                            .parse()
-                            .expect("Bad PeerID!"),
-                    ),
-                ]
-            }
-            Self::Krigan => {
-                vec![(
-                    "/ip4/108.136.33.203/tcp/30333"
+                ),
+                (
+                    "/dnsaddr/bootstrap.libp2p.io"
+                        .parse()
+                        .expect("Bad MultiAddr!"),
+                    "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
+                        .parse()
+                        .expect("Bad PeerID!"),
+                ),
+                (
+                    "/dnsaddr/bootstrap.libp2p.io"
+                        .parse()
+                        .expect("Bad MultiAddr!"),
+                    "QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa"
+                        .parse()
+                        .expect("Bad PeerID!"),
+                ),
+                (
+                    "/dnsaddr/bootstrap.libp2p.io"
                         .parse()
                         .expect("Bad MultiAddr!"),
-                    "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"
+                    "QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb"
                         .parse()
                         .expect("Bad PeerID!"),
-                )]
+                ),
+                (
+                    "/dnsaddr/bootstrap.libp2p.io"
+                        .parse()
+                        .expect("Bad MultiAddr!"),
+                    "QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt"
+                        .parse()
+                        .expect("Bad PeerID!"),
+                ),
+            ],
+            Vec::new(),
+            None,
+        )
+    }
+
+    pub fn krigan() -> Self {
+        Self::new(
+            "Krigan".to_owned(),
+            vec![(
+                "/ip4/108.136.33.203/tcp/30333"
+                    .parse()
+                    .expect("Bad MultiAddr!"),
+                "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"
+                    .parse()
+                    .expect("Bad PeerID!"),
+            )],
+            Vec::new(),
+            None,
+        )
+        .with_kad_protocols(
+            substrate_kad_protocols(Some(KRIGAN_GENESIS_HASH), Some(SUBSTRATE_DEFAULT_CHAIN_ID))
+                .expect("Programmatic error: bad built-in chain!"),
+        )
+    }
+
+    /// Adds bootnodes on top of the network's own ones
+    pub fn with_bootnodes(
+        mut self,
+        bootnodes: impl IntoIterator<Item = (Multiaddr, PeerId)>,
+    ) -> Self {
+        self.bootnodes.extend(bootnodes);
+
+        self
+    }
+
+    /// Adds Kademlia protocol names on top of the network's own ones, all of them are queried
+    pub fn with_kad_protocols(mut self, kad_protocols: impl IntoIterator<Item = String>) -> Self {
+        for kad_protocol in kad_protocols {
+            if !self.kad_protocols.contains(&kad_protocol) {
+                self.kad_protocols.push(kad_protocol);
             }
         }
+
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn bootnodes(&self) -> &[(Multiaddr, PeerId)] {
+        &self.bootnodes
+    }
+
+    /// Kademlia protocol names, libp2p's default (`/ipfs/kad/1.0.0`) is used if empty
+    pub fn kad_protocols(&self) -> &[String] {
+        &self.kad_protocols
+    }
+
+    /// Genesis hash of the Substrate chain, as found in a `/<genesis hash>/kad` protocol name
+    pub fn genesis_hash(&self) -> Option<BlockHash> {
+        self.kad_protocols
+            .iter()
+            .find_map(|kad_protocol| protocol_genesis_hash(kad_protocol))
+    }
+
+    /// Identify protocol version announced to the network's peers
+    pub fn identify_protocol_version(&self) -> Option<&str> {
+        self.identify_protocol_version.as_deref()
+    }
+}
+
+/// Kademlia protocol names of a Substrate chain, `/<genesis hash>/kad` followed by the legacy
+/// `/<chain ID>/kad`, the genesis hash being 32 hex-encoded bytes (optionally prefixed by `0x`)
+pub fn substrate_kad_protocols(
+    genesis_hash: Option<&str>,
+    chain_id: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let mut kad_protocols = Vec::new();
+
+    if let Some(genesis_hash) = genesis_hash {
+        let genesis_hash = genesis_hash
+            .strip_prefix("0x")
+            .unwrap_or(genesis_hash)
+            .to_ascii_lowercase();
+        let genesis_bytes = hex::decode(&genesis_hash).map_err(|error| error.to_string())?;
+
+        if genesis_bytes.len() != 32 {
+            return Err(format!(
+                "Genesis hash must be 32 bytes long, got {} bytes",
+                genesis_bytes.len()
+            ));
+        }
+
+        kad_protocols.push(format!("/{genesis_hash}/kad"));
+    }
+
+    if let Some(chain_id) = chain_id {
+        kad_protocols.push(format!("/{chain_id}/kad"));
+    }
+
+    Ok(kad_protocols)
+}
+
+/// Splits a bootnode address into its dialable part and its trailing `/p2p/<peer ID>`
+pub fn parse_bootnode(source: &str) -> Result<(Multiaddr, PeerId), String> {
+    let mut address = source
+        .parse::<Multiaddr>()
+        .map_err(|error| error.to_string())?;
+
+    match address.pop() {
+        Some(MultiaddrProtocol::P2p(multihash)) => {
+            let peer_id = PeerId::from_multihash(multihash)
+                .map_err(|_| "Bad peer ID in \"/p2p\" component".to_owned())?;
+
+            Ok((address, peer_id))
+        }
+        _ => Err("Bootnode address must end with \"/p2p/<peer ID>\"".to_owned()),
+    }
+}
+
+#[derive(Deserialize)]
+struct NetworksFile {
+    #[serde(default, rename = "network")]
+    networks: Vec<NetworkDefinition>,
+}
+
+#[derive(Deserialize)]
+struct NetworkDefinition {
+    name: String,
+    #[serde(default)]
+    bootnodes: Vec<String>,
+    #[serde(default)]
+    kad_protocols: Vec<String>,
+    genesis_hash: Option<String>,
+    chain_id: Option<String>,
+    identify_protocol_version: Option<String>,
+}
+
+impl TryFrom<NetworkDefinition> for DHTNetwork {
+    type Error = NetworksFileError;
+
+    fn try_from(value: NetworkDefinition) -> Result<Self, Self::Error> {
+        if value.bootnodes.is_empty() {
+            return Err(NetworksFileError::NoBootnodes(value.name));
+        }
+
+        let bootnodes = value
+            .bootnodes
+            .iter()
+            .map(|bootnode| {
+                parse_bootnode(bootnode).map_err(|error| {
+                    NetworksFileError::BadBootnode(value.name.clone(), bootnode.clone(), error)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let substrate_kad_protocols =
+            substrate_kad_protocols(value.genesis_hash.as_deref(), value.chain_id.as_deref())
+                .map_err(|error| NetworksFileError::BadGenesisHash(value.name.clone(), error))?;
+
+        Ok(Self::new(
+            value.name,
+            bootnodes,
+            substrate_kad_protocols,
+            value.identify_protocol_version,
+        )
+        .with_kad_protocols(value.kad_protocols))
+    }
+}
+
+/// Known DHT networks, the built-in ones followed by the ones of a networks file
+#[derive(Debug, Clone)]
+pub struct NetworkRegistry {
+    networks: Vec<DHTNetwork>,
+}
+
+impl Default for NetworkRegistry {
+    fn default() -> Self {
+        Self {
+            networks: vec![DHTNetwork::goro(), DHTNetwork::ipfs(), DHTNetwork::krigan()],
+        }
+    }
+}
+
+impl NetworkRegistry {
+    /// Networks file location under the XDG config directory
+    /// (`$XDG_CONFIG_HOME/goro-p2p-inspector/networks.toml`, `$XDG_CONFIG_HOME` defaulting to
+    /// `$HOME/.config`)
+    pub fn default_networks_file() -> Option<PathBuf> {
+        let config_directory = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(
+            config_directory
+                .join(NETWORKS_FILE_DIRECTORY)
+                .join(NETWORKS_FILE_NAME),
+        )
+    }
+
+    /// Adds the networks of a TOML networks file, replacing built-in networks of the same name
+    pub fn load_networks_file(&mut self, path: &Path) -> Result<(), NetworksFileError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| NetworksFileError::Unreadable(path.to_owned(), error))?;
+
+        self.load_networks(path, &content)
+    }
+
+    fn load_networks(&mut self, path: &Path, content: &str) -> Result<(), NetworksFileError> {
+        let networks_file = toml::from_str::<NetworksFile>(content)
+            .map_err(|error| NetworksFileError::Malformed(path.to_owned(), error))?;
+
+        for definition in networks_file.networks {
+            let network = DHTNetwork::try_from(definition)?;
+            self.networks
+                .retain(|known| !known.name.eq_ignore_ascii_case(&network.name));
+            self.networks.push(network);
+        }
+
+        Ok(())
+    }
+
+    /// Finds a network by its case-insensitive name
+    pub fn get(&self, name: &str) -> Result<&DHTNetwork, String> {
+        self.networks
+            .iter()
+            .find(|network| network.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let known_names = self
+                    .networks
+                    .iter()
+                    .map(DHTNetwork::name)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                format!("Unknown network \"{name}\", known networks are {known_names}.")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NETWORKS_FILE: &str = r#"
+[[network]]
+name = "my-testnet"
+bootnodes = [
+    "/dns/boot.my-testnet.example/tcp/30333/p2p/12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2",
+]
+genesis_hash = "0xc2f2d8da3e2d6b63970b6de8e365b84393ee0c69a9aec3a0bc750029a3552d07"
+chain_id = "sup"
+kad_protocols = ["/my-testnet/kad"]
+identify_protocol_version = "/my-testnet/1.0"
+
+[[network]]
+name = "ipfs"
+bootnodes = ["/ip4/127.0.0.1/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"]
+"#;
+
+    fn load_networks(content: &str) -> Result<NetworkRegistry, NetworksFileError> {
+        let mut network_registry = NetworkRegistry::default();
+        network_registry.load_networks(Path::new("networks.toml"), content)?;
+
+        Ok(network_registry)
+    }
+
+    #[test]
+    fn loads_networks_files() {
+        let network_registry = load_networks(NETWORKS_FILE).unwrap();
+        let network = network_registry.get("My-Testnet").unwrap();
+
+        assert_eq!(network.name(), "my-testnet");
+        assert_eq!(
+            network.bootnodes(),
+            &[(
+                "/dns/boot.my-testnet.example/tcp/30333"
+                    .parse::<Multiaddr>()
+                    .unwrap(),
+                "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"
+                    .parse::<PeerId>()
+                    .unwrap()
+            )]
+        );
+        assert_eq!(
+            network.kad_protocols(),
+            &[
+                format!("/{KRIGAN_GENESIS_HASH}/kad"),
+                "/sup/kad".to_owned(),
+                "/my-testnet/kad".to_owned()
+            ]
+        );
+        assert_eq!(network.genesis_hash(), DHTNetwork::krigan().genesis_hash());
+        assert_eq!(network.identify_protocol_version(), Some("/my-testnet/1.0"));
+        assert!(network_registry.get("krigan").is_ok());
+        assert!(network_registry.get("unknown").is_err());
+    }
+
+    #[test]
+    fn overrides_built_in_networks() {
+        let network_registry = load_networks(NETWORKS_FILE).unwrap();
+        let network = network_registry.get("IPFS").unwrap();
+
+        assert_eq!(network.name(), "ipfs");
+        assert_eq!(network.bootnodes().len(), 1);
+        assert!(network.kad_protocols().is_empty());
+        assert_eq!(
+            network_registry
+                .networks
+                .iter()
+                .filter(|network| network.name().eq_ignore_ascii_case("ipfs"))
+                .count(),
+            1
+        );
     }
 
-    pub(crate) fn protocol(&self) -> Option<String> {
-        match self {
-            Self::GoRo => Some("/substrate/1.0".into()),
-            Self::Ipfs => None,
-            Self::Krigan => Some("/substrate/1.0".into()),
+    #[test]
+    fn rejects_bad_networks() {
+        assert!(matches!(
+            load_networks("[[network]]\nname = \"empty\"\n"),
+            Err(NetworksFileError::NoBootnodes(name)) if name == "empty"
+        ));
+        assert!(matches!(
+            load_networks("[[network]]\nbootnodes = []\n"),
+            Err(NetworksFileError::Malformed(..))
+        ));
+        assert!(matches!(
+            load_networks("[[network]]\nname = \"bad\"\nbootnodes = [\"/ip4/127.0.0.1/tcp/1\"]\n"),
+            Err(NetworksFileError::BadBootnode(..))
+        ));
+        assert!(matches!(
+            load_networks(
+                "[[network]]\nname = \"bad\"\ngenesis_hash = \"0x00\"\nbootnodes = \
+                 [\"/ip4/127.0.0.1/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ\"]\n"
+            ),
+            Err(NetworksFileError::BadGenesisHash(..))
+        ));
+        assert!(matches!(
+            load_networks("[network]"),
+            Err(NetworksFileError::Malformed(..))
+        ));
+    }
+
+    #[test]
+    fn names_substrate_kad_protocols() {
+        let krigan_kad_protocol = format!("/{KRIGAN_GENESIS_HASH}/kad");
+
+        assert_eq!(
+            substrate_kad_protocols(Some(KRIGAN_GENESIS_HASH), None).unwrap(),
+            vec![krigan_kad_protocol.clone()]
+        );
+        assert_eq!(
+            substrate_kad_protocols(
+                Some(&format!("0x{}", KRIGAN_GENESIS_HASH.to_ascii_uppercase())),
+                None
+            )
+            .unwrap(),
+            vec![krigan_kad_protocol.clone()]
+        );
+        assert_eq!(
+            substrate_kad_protocols(None, Some("sup")).unwrap(),
+            vec!["/sup/kad".to_owned()]
+        );
+        assert_eq!(
+            substrate_kad_protocols(Some(KRIGAN_GENESIS_HASH), Some("sup")).unwrap(),
+            vec![krigan_kad_protocol, "/sup/kad".to_owned()]
+        );
+        assert!(substrate_kad_protocols(None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_genesis_hashes() {
+        assert_eq!(
+            substrate_kad_protocols(Some(&KRIGAN_GENESIS_HASH[2..]), None),
+            Err("Genesis hash must be 32 bytes long, got 31 bytes".to_owned())
+        );
+        assert!(substrate_kad_protocols(Some(&format!("{KRIGAN_GENESIS_HASH}00")), None).is_err());
+        assert!(
+            substrate_kad_protocols(Some(&format!("0x0x{KRIGAN_GENESIS_HASH}")), None).is_err()
+        );
+        assert!(
+            substrate_kad_protocols(Some(&KRIGAN_GENESIS_HASH.replace('c', "g")), None).is_err()
+        );
+        assert!(substrate_kad_protocols(Some(""), None).is_err());
+    }
+
+    #[test]
+    fn parses_bootnodes() {
+        assert_eq!(
+            parse_bootnode(
+                "/ip4/108.136.33.203/tcp/30333/p2p/12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"
+            )
+            .unwrap(),
+            (
+                "/ip4/108.136.33.203/tcp/30333"
+                    .parse::<Multiaddr>()
+                    .unwrap(),
+                "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2"
+                    .parse::<PeerId>()
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            parse_bootnode(
+                "/dnsaddr/bootstrap.libp2p.io/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
+            )
+            .unwrap()
+            .0,
+            "/dnsaddr/bootstrap.libp2p.io".parse::<Multiaddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_bootnodes() {
+        assert_eq!(
+            parse_bootnode("/ip4/108.136.33.203/tcp/30333"),
+            Err("Bootnode address must end with \"/p2p/<peer ID>\"".to_owned())
+        );
+        assert!(parse_bootnode(
+            "/p2p/12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2/ip4/108.136.33.203"
+        )
+        .is_err());
+        assert!(parse_bootnode("").is_err());
+        assert!(parse_bootnode("108.136.33.203:30333").is_err());
+        assert!(parse_bootnode("/ip4/108.136.33.203/tcp/30333/p2p/not-a-peer-id").is_err());
+        assert!(parse_bootnode("/ip4/300.136.33.203/tcp/30333").is_err());
+    }
+
+    #[test]
+    fn locates_default_networks_file() {
+        let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME");
+        let home = std::env::var_os("HOME");
+
+        std::env::set_var("XDG_CONFIG_HOME", "/xdg");
+        assert_eq!(
+            NetworkRegistry::default_networks_file(),
+            Some(PathBuf::from("/xdg/goro-p2p-inspector/networks.toml"))
+        );
+
+        std::env::set_var("XDG_CONFIG_HOME", "");
+        std::env::set_var("HOME", "/home/user");
+        assert_eq!(
+            NetworkRegistry::default_networks_file(),
+            Some(PathBuf::from(
+                "/home/user/.config/goro-p2p-inspector/networks.toml"
+            ))
+        );
+
+        for (variable, value) in [("XDG_CONFIG_HOME", xdg_config_home), ("HOME", home)] {
+            match value {
+                Some(value) => std::env::set_var(variable, value),
+                None => std::env::remove_var(variable),
+            }
         }
     }
 }
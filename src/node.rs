@@ -0,0 +1,186 @@
+use crate::blocks::{block_request_protocols, BlockRequestCodec};
+use crate::chain::BlockAnnouncesBehaviour;
+use crate::logging::log_info;
+use crate::negotiate::ProtocolNegotiationBehaviour;
+use crate::networks::DHTNetwork;
+use crate::peer::PeerLookupError;
+use crate::trace::{find_node_protocols, FindNodeCodec};
+use crate::transport::{create_transport, TransportConfig};
+use libp2p::gossipsub::{
+    Behaviour as GossipsubBehaviour, ConfigBuilder as GossipsubConfigBuilder,
+    MessageAuthenticity as GossipsubMessageAuthenticity, ValidationMode as GossipsubValidationMode,
+};
+use libp2p::identify::{Behaviour as IdentifyBehaviour, Config as IdentifyConfig};
+use libp2p::identity::Keypair;
+use libp2p::kad::store::MemoryStore;
+use libp2p::kad::{Kademlia, KademliaConfig};
+use libp2p::ping::{Behaviour as PingBehaviour, Config as PingConfig};
+use libp2p::relay::client::{new as new_relay_client, Behaviour as RelayClientBehaviour};
+use libp2p::request_response::{
+    Behaviour as RequestResponseBehaviour, Config as RequestResponseConfig, ProtocolSupport,
+};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::keep_alive::Behaviour as SwarmKeepAliveBehaviour;
+use libp2p::swarm::{NetworkBehaviour as SwarmNetworkBehaviour, SwarmBuilder};
+use libp2p::{PeerId, Swarm};
+use tokio::time::Duration;
+
+/// Identify agent version announced when none is configured
+const DEFAULT_AGENT_VERSION: &str = "substrate-node/v2.0.0-85dacde-wasm32 (unknown)";
+/// Identify protocol version announced when neither configured nor defined by the DHT network
+const DEFAULT_IDENTIFY_PROTOCOL_VERSION: &str = "/substrate/1.0";
+
+#[derive(SwarmNetworkBehaviour)]
+pub struct PeerLookupBehaviour {
+    pub dht: Kademlia<MemoryStore>,
+    pub ping: PingBehaviour,
+    pub identify: IdentifyBehaviour,
+    pub relay: RelayClientBehaviour,
+    pub keep_alive: SwarmKeepAliveBehaviour,
+    /// Single Kademlia `FIND_NODE` requests, for tracing DHT queries hop by hop
+    pub find_node: RequestResponseBehaviour<FindNodeCodec>,
+    /// Substrate block announces handshakes, for reading the chain state of peers
+    pub block_announces: BlockAnnouncesBehaviour,
+    /// Substrate block requests, for fetching single blocks from peers
+    pub block_requests: RequestResponseBehaviour<BlockRequestCodec>,
+    /// Bare protocol negotiations, for verifying the protocols peers advertise
+    pub negotiation: ProtocolNegotiationBehaviour,
+    /// Gossipsub, only enabled when observing gossip so that other modes don't advertise it
+    pub gossipsub: Toggle<GossipsubBehaviour>,
+}
+
+/// Configuration of the local libp2p node, shared by every lookup mode
+#[derive(Debug, Clone, Default)]
+pub struct NodeConfig {
+    /// Identity used by the local peer, a fresh Ed25519 keypair is generated if unset
+    pub local_key: Option<Keypair>,
+    /// Identify agent version announced to peers
+    pub agent_version: Option<String>,
+    /// Identify protocol version announced to peers, overriding the DHT network's one
+    pub protocol_version: Option<String>,
+    /// Interval of re-identifying connected peers, libp2p's default (5 minutes) if unset
+    pub identify_interval: Option<Duration>,
+    /// Interval of pinging connected peers, libp2p's default (15 seconds) if unset
+    pub ping_interval: Option<Duration>,
+    /// Whether to run gossipsub, advertising and accepting its protocols
+    pub gossipsub: bool,
+    pub transport: TransportConfig,
+}
+
+pub(crate) fn create_swarm(
+    config: &NodeConfig,
+    dht_network: Option<&DHTNetwork>,
+    dht_query_timeout: Duration,
+) -> Result<Swarm<PeerLookupBehaviour>, PeerLookupError> {
+    let local_key = config
+        .local_key
+        .clone()
+        .unwrap_or_else(Keypair::generate_ed25519);
+    let local_peer_id = PeerId::from(local_key.public());
+    log_info!("Local peer id: \"{local_peer_id}\"");
+    let (relay_transport, relay_client) = new_relay_client(local_peer_id);
+    let transport = create_transport(&config.transport, &local_key, relay_transport)?;
+
+    let behaviour = {
+        let store = MemoryStore::new(local_peer_id);
+        let mut kademlia_config = KademliaConfig::default();
+        kademlia_config.set_query_timeout(dht_query_timeout);
+
+        if let Some(dht_network) = dht_network.filter(|network| !network.kad_protocols().is_empty())
+        {
+            kademlia_config.set_protocol_names(
+                dht_network
+                    .kad_protocols()
+                    .iter()
+                    .map(|protocol_name| protocol_name.clone().into_bytes().into())
+                    .collect(),
+            );
+        }
+
+        let kademlia = Kademlia::with_config(local_peer_id, store, kademlia_config);
+        let find_node = RequestResponseBehaviour::new(
+            FindNodeCodec,
+            find_node_protocols(
+                dht_network
+                    .map(DHTNetwork::kad_protocols)
+                    .unwrap_or_default(),
+            )
+            .into_iter()
+            .map(|protocol| (protocol, ProtocolSupport::Outbound)),
+            RequestResponseConfig::default(),
+        );
+        let block_requests = RequestResponseBehaviour::new(
+            BlockRequestCodec,
+            block_request_protocols(
+                dht_network
+                    .map(DHTNetwork::kad_protocols)
+                    .unwrap_or_default(),
+            )
+            .into_iter()
+            .map(|protocol| (protocol, ProtocolSupport::Outbound)),
+            RequestResponseConfig::default(),
+        );
+        let mut ping_config = PingConfig::new();
+
+        if let Some(ping_interval) = config.ping_interval {
+            ping_config = ping_config.with_interval(ping_interval);
+        }
+
+        let ping = PingBehaviour::new(ping_config);
+        let user_agent = config
+            .agent_version
+            .clone()
+            .unwrap_or_else(|| DEFAULT_AGENT_VERSION.to_owned());
+        let proto_version = config
+            .protocol_version
+            .as_deref()
+            .or_else(|| dht_network.and_then(DHTNetwork::identify_protocol_version))
+            .unwrap_or(DEFAULT_IDENTIFY_PROTOCOL_VERSION)
+            .to_owned();
+        let mut identify_config =
+            IdentifyConfig::new(proto_version, local_key.public()).with_agent_version(user_agent);
+
+        if let Some(identify_interval) = config.identify_interval {
+            identify_config = identify_config.with_interval(identify_interval);
+        }
+
+        let identify = IdentifyBehaviour::new(identify_config);
+        let gossipsub = config.gossipsub.then(|| {
+            // Messages of any validation mode are accepted, then only reported, never forwarded
+            let gossipsub_config = GossipsubConfigBuilder::default()
+                .validation_mode(GossipsubValidationMode::Permissive)
+                .validate_messages()
+                .build()
+                .expect("Programmatic error: invalid gossipsub configuration!");
+
+            GossipsubBehaviour::new(
+                GossipsubMessageAuthenticity::Signed(local_key.clone()),
+                gossipsub_config,
+            )
+            .expect("Programmatic error: invalid gossipsub configuration!")
+        });
+
+        PeerLookupBehaviour {
+            dht: kademlia,
+            ping,
+            identify,
+            relay: relay_client,
+            keep_alive: SwarmKeepAliveBehaviour,
+            find_node,
+            block_announces: BlockAnnouncesBehaviour::default(),
+            block_requests,
+            negotiation: ProtocolNegotiationBehaviour::default(),
+            gossipsub: Toggle::from(gossipsub),
+        }
+    };
+
+    let mut swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
+
+    if let Some(dht_network) = dht_network {
+        for (addr, peer_id) in dht_network.bootnodes() {
+            swarm.behaviour_mut().dht.add_address(peer_id, addr.clone());
+        }
+    }
+
+    Ok(swarm)
+}
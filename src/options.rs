@@ -1,23 +1,312 @@
+use goro_p2p_inspector::keys::{load_or_generate_keypair, parse_peer_id};
+use goro_p2p_inspector::networks::{parse_bootnode, substrate_kad_protocols};
+use goro_p2p_inspector::records::parse_record_key;
+use goro_p2p_inspector::{
+    BatchTarget, BlockId, DHTNetwork, KeyFileError, KeyType, LookupTimeouts, Multiplexing,
+    NetworkRegistry, NodeConfig, TransportConfig,
+};
+use libp2p::kad::RecordKey;
 use libp2p::{Multiaddr, PeerId};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 use structopt::StructOpt;
 
-use crate::networks::DHTNetwork;
+use crate::output::OutputFormat;
 
 #[derive(Debug, StructOpt)]
-pub(crate) enum Options {
+pub(crate) struct Options {
+    /// Output format of the lookup result (text or json)
+    #[structopt(long, short, global = true, default_value = "text")]
+    pub(crate) output: OutputFormat,
+    /// Print nothing, only report the lookup outcome through the exit status
+    #[structopt(long, short, global = true)]
+    pub(crate) quiet: bool,
+    /// Deadline of the whole lookup in seconds (unbounded besides each phase if unset)
+    #[structopt(long, global = true)]
+    pub(crate) timeout: Option<u64>,
+    /// Deadline of establishing a connection to the peer in seconds
+    #[structopt(long, global = true, default_value = "30")]
+    pub(crate) dial_timeout: u64,
+    /// Deadline of walking the DHT to find the peer in seconds
+    #[structopt(long, global = true, default_value = "60")]
+    pub(crate) dht_timeout: u64,
+    /// Deadline of receiving the identify message once connected in seconds
+    #[structopt(long, global = true, default_value = "30")]
+    pub(crate) identify_timeout: u64,
+    /// TOML file defining additional DHT networks (defaults to
+    /// "$XDG_CONFIG_HOME/goro-p2p-inspector/networks.toml" when it exists)
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub(crate) networks_file: Option<PathBuf>,
+    /// File holding the local keypair (libp2p protobuf encoding) for a stable peer ID, generated
+    /// if it doesn't exist (a fresh Ed25519 keypair is used every run if unset)
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub(crate) key_file: Option<PathBuf>,
+    /// Type of the keypair generated into "--key-file" (ed25519, secp256k1 or ecdsa)
+    #[structopt(long, global = true, default_value = "ed25519")]
+    pub(crate) key_type: KeyType,
+    /// Identify agent version announced to peers
+    #[structopt(long, global = true)]
+    pub(crate) agent_version: Option<String>,
+    /// Identify protocol version announced to peers (the DHT network's one or "/substrate/1.0"
+    /// if unset)
+    #[structopt(long, global = true)]
+    pub(crate) protocol_version: Option<String>,
+    /// Disable the TCP transport
+    #[structopt(long, global = true)]
+    pub(crate) no_tcp: bool,
+    /// Disable the QUIC transport
+    #[structopt(long, global = true)]
+    pub(crate) no_quic: bool,
+    /// Disable dialing through circuit relays
+    #[structopt(long, global = true)]
+    pub(crate) no_relay: bool,
+    /// Disable dialing WebSocket ("/ws" and "/wss") addresses
+    #[structopt(long, global = true)]
+    pub(crate) no_websocket: bool,
+    /// Only support QUIC v1, without the draft-29 version
+    #[structopt(long, global = true)]
+    pub(crate) no_quic_draft_29: bool,
+    /// Stream multiplexer of TCP, WebSocket and relayed connections (any, yamux or mplex)
+    #[structopt(long, global = true, default_value = "any")]
+    pub(crate) muxer: Multiplexing,
+    #[structopt(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum Command {
     /// Lookup peer by it's libp2p address
     Direct {
         /// Peer address (Multi Address Format)
         #[structopt(long, short)]
         address: Multiaddr,
+        /// Once identified, ping the peer this many times (one second apart) and report its
+        /// round-trip time statistics
+        #[structopt(long, default_value = "0")]
+        ping_samples: usize,
+        /// Once identified, perform the Substrate block announces handshake to read the peer's role,
+        /// best block and genesis hash
+        #[structopt(long)]
+        probe_chain: bool,
+        /// Once identified, request this block (decimal number or 0x-prefixed hash) through the
+        /// Substrate block request protocol and report its header and response time
+        #[structopt(long)]
+        fetch_block: Option<BlockId>,
+        /// Once identified, propose every advertised protocol to the peer on a fresh substream and
+        /// report which ones it accepts, rejects or doesn't answer in time
+        #[structopt(long)]
+        verify_protocols: bool,
+        /// Protocol to verify on top of the advertised ones (repeatable), implies
+        /// --verify-protocols
+        #[structopt(long = "extra-protocol")]
+        extra_protocols: Vec<String>,
     },
     /// Lookup peer by its public ID via Kademlia DHT
     Dht {
-        /// Peer ID
-        #[structopt(long, short)]
+        /// Peer ID, or the public key it derives from: SS58 address of an Ed25519 key, hex Ed25519
+        /// public key or hex protobuf-encoded libp2p public key
+        #[structopt(long, short, parse(try_from_str = parse_peer_id))]
         peer_id: PeerId,
-        /// DHT network of the peer
+        #[structopt(flatten)]
+        network: NetworkOptions,
+        /// Report every peer contacted by the DHT query, the closer peers each one returned, their
+        /// XOR distance to the target and the latency of each hop
+        #[structopt(long)]
+        trace_query: bool,
+        /// Once identified, dial every address the peer announced or is known by in the DHT, each
+        /// on a fresh connection, and report which ones are reachable
+        #[structopt(long)]
+        probe_all_addresses: bool,
+        /// Once identified, ping the peer this many times (one second apart) and report its
+        /// round-trip time statistics
+        #[structopt(long, default_value = "0")]
+        ping_samples: usize,
+        /// Once identified, perform the Substrate block announces handshake to read the peer's role,
+        /// best block and genesis hash
+        #[structopt(long)]
+        probe_chain: bool,
+        /// Once identified, request this block (decimal number or 0x-prefixed hash) through the
+        /// Substrate block request protocol and report its header and response time
+        #[structopt(long)]
+        fetch_block: Option<BlockId>,
+        /// Once identified, propose every advertised protocol to the peer on a fresh substream and
+        /// report which ones it accepts, rejects or doesn't answer in time
+        #[structopt(long)]
+        verify_protocols: bool,
+        /// Protocol to verify on top of the advertised ones (repeatable), implies
+        /// --verify-protocols
+        #[structopt(long = "extra-protocol")]
+        extra_protocols: Vec<String>,
+    },
+    /// Lookup many peers over a single swarm, reading multi addresses and/or peer IDs or public
+    /// keys (one per line, or JSON lines with "address" or "peer_id")
+    Batch {
+        /// File to read the targets from (standard input if unset or "-")
+        #[structopt(long, short, parse(from_os_str))]
+        input: Option<PathBuf>,
+        /// DHT network of the peers given by ID, built-in or from the networks file
+        #[structopt(long, short)]
+        dht_network: Option<String>,
+        /// Maximum number of lookups running concurrently
+        #[structopt(long, short = "j", default_value = "16")]
+        parallelism: usize,
+    },
+    /// Keep many peers connected, re-identifying them periodically and reporting their changes,
+    /// status and ping round-trip times
+    Watch {
+        /// Peer to watch: multi address, peer ID or public key, repeatable
+        #[structopt(long = "target", short = "t", number_of_values = 1, required = true)]
+        targets: Vec<BatchTarget>,
+        /// DHT network of the peers given by ID, built-in or from the networks file
         #[structopt(long, short)]
-        dht_network: DHTNetwork,
+        dht_network: Option<String>,
+        /// Interval of re-identifying the peers and reporting their status in seconds
+        #[structopt(long, default_value = "60")]
+        interval: u64,
+        /// Address to serve Prometheus metrics on at "/metrics" (e.g. "127.0.0.1:9616")
+        #[structopt(long)]
+        metrics_address: Option<SocketAddr>,
+    },
+    /// Connect to a peer and observe its gossipsub traffic: the topics it and other peers
+    /// subscribe to, and the messages received on the subscribed topics. No propagation latency
+    /// is reported: messages carry no publication time, and duplicates arriving via other peers
+    /// are dropped by gossipsub before being reported
+    ObserveGossip {
+        /// Peer to observe: multi address, peer ID or public key
+        #[structopt(long, short)]
+        target: BatchTarget,
+        /// DHT network of the peer given by ID, built-in or from the networks file
+        #[structopt(long, short)]
+        dht_network: Option<String>,
+        /// Topic to subscribe to, repeatable (the topics the peers subscribe to are discovered and
+        /// subscribed to if unset)
+        #[structopt(long = "topic", number_of_values = 1)]
+        topics: Vec<String>,
+        /// Publish this message once on every given topic, as soon as the peer subscribes to it
+        /// (nothing is ever published if unset)
+        #[structopt(long, requires = "topics")]
+        publish: Option<String>,
+        /// Duration of the observation in seconds (until the peer disconnects if unset)
+        #[structopt(long)]
+        duration: Option<u64>,
+    },
+    /// Map a whole DHT network by walking it towards random keys, then identify every peer found
+    Crawl {
+        #[structopt(flatten)]
+        network: NetworkOptions,
+        /// Number of FIND_NODE walks towards random keys
+        #[structopt(long, default_value = "32")]
+        queries: usize,
+        /// Maximum number of walks, and of peer probes, running concurrently
+        #[structopt(long, short = "j", default_value = "16")]
+        parallelism: usize,
+    },
+    /// Bootstrap against a DHT network, then dump the k-buckets of the local routing table
+    RoutingTable {
+        #[structopt(flatten)]
+        network: NetworkOptions,
+    },
+    /// Find the peers providing a key on a DHT network
+    Providers {
+        /// Key: a CID, "/ipns/<peer ID>", "/pk/<peer ID>" or 0x-prefixed hex bytes
+        #[structopt(long, short, parse(try_from_str = parse_record_key))]
+        key: RecordKey,
+        #[structopt(flatten)]
+        network: NetworkOptions,
+        /// Look up and identify every provider found
+        #[structopt(long)]
+        identify: bool,
+        /// Maximum number of provider lookups running concurrently
+        #[structopt(long, short = "j", default_value = "16")]
+        parallelism: usize,
     },
+    /// Get the value records stored under a key on a DHT network
+    GetRecord {
+        /// Key: a CID, "/ipns/<peer ID>", "/pk/<peer ID>" or 0x-prefixed hex bytes
+        #[structopt(long, short, parse(try_from_str = parse_record_key))]
+        key: RecordKey,
+        #[structopt(flatten)]
+        network: NetworkOptions,
+    },
+}
+
+/// DHT network to join, either known by name and/or given ad hoc
+#[derive(Debug, StructOpt)]
+pub(crate) struct NetworkOptions {
+    /// DHT network, built-in (goro, ipfs or krigan) or from the networks file
+    #[structopt(long, short, required_unless = "bootnode")]
+    dht_network: Option<String>,
+    /// Additional bootnode (Multi Address Format ending with "/p2p/<peer ID>"), repeatable
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_bootnode))]
+    bootnode: Vec<(Multiaddr, PeerId)>,
+    /// Additional Kademlia protocol name (e.g. "/my-chain/kad"), repeatable
+    #[structopt(long, number_of_values = 1)]
+    kad_protocol: Vec<String>,
+    /// Genesis hash of a Substrate chain, queries its "/<genesis hash>/kad" protocol
+    #[structopt(long)]
+    genesis_hash: Option<String>,
+    /// Legacy protocol ID of a Substrate chain (e.g. "sup"), queries its "/<chain ID>/kad"
+    /// protocol
+    #[structopt(long)]
+    chain_id: Option<String>,
+}
+
+impl NetworkOptions {
+    /// Resolves the named network (if any) and completes it with the ad hoc options
+    pub(crate) fn resolve(self, network_registry: &NetworkRegistry) -> Result<DHTNetwork, String> {
+        let substrate_kad_protocols =
+            substrate_kad_protocols(self.genesis_hash.as_deref(), self.chain_id.as_deref())
+                .map_err(|error_message| format!("Bad genesis hash: {error_message}"))?;
+        let dht_network = match self.dht_network {
+            Some(name) => network_registry.get(&name)?.clone(),
+            None => DHTNetwork::ad_hoc(),
+        };
+
+        Ok(dht_network
+            .with_bootnodes(self.bootnode)
+            .with_kad_protocols(substrate_kad_protocols)
+            .with_kad_protocols(self.kad_protocol))
+    }
+}
+
+impl Options {
+    /// Output format of the results, nothing is printed in quiet mode
+    pub(crate) fn report_format(&self) -> Option<OutputFormat> {
+        (!self.quiet).then_some(self.output)
+    }
+
+    pub(crate) fn node_config(&self) -> Result<NodeConfig, KeyFileError> {
+        let local_key = self
+            .key_file
+            .as_deref()
+            .map(|path| load_or_generate_keypair(path, self.key_type))
+            .transpose()?;
+
+        Ok(NodeConfig {
+            local_key,
+            agent_version: self.agent_version.clone(),
+            protocol_version: self.protocol_version.clone(),
+            identify_interval: None,
+            ping_interval: None,
+            gossipsub: false,
+            transport: TransportConfig {
+                tcp: !self.no_tcp,
+                quic: !self.no_quic,
+                relay: !self.no_relay,
+                websocket: !self.no_websocket,
+                quic_draft_29: !self.no_quic_draft_29,
+                multiplexing: self.muxer,
+            },
+        })
+    }
+
+    pub(crate) fn lookup_timeouts(&self) -> LookupTimeouts {
+        LookupTimeouts {
+            total: self.timeout.map(Duration::from_secs),
+            dial: Duration::from_secs(self.dial_timeout),
+            dht_query: Duration::from_secs(self.dht_timeout),
+            identify: Duration::from_secs(self.identify_timeout),
+        }
+    }
 }
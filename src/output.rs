@@ -0,0 +1,336 @@
+use goro_p2p_inspector::logging::{log_error, log_info, log_warning};
+use goro_p2p_inspector::{
+    BatchLookupRecord, CrawlReport, GossipEvent, GossipRecord, PeerInfo, PeerLookupResult,
+    QueryTrace, Reachability, RecordQueryReport, RoutingTableReport, WatchEvent, WatchRecord,
+};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Version of the JSON document layout, bumped on every breaking change
+pub(crate) const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err("Supported output formats are text and JSON.".to_owned()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LookupDocument<'a> {
+    schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer: Option<&'a PeerInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query_trace: Option<&'a QueryTrace>,
+}
+
+impl<'a> LookupDocument<'a> {
+    fn new(target: Option<String>, lookup_result: &'a PeerLookupResult) -> Self {
+        match lookup_result {
+            Err(error_message) => Self {
+                schema_version: JSON_SCHEMA_VERSION,
+                target,
+                status: "error",
+                peer: None,
+                error: Some(error_message.to_string()),
+                query_trace: None,
+            },
+            Ok(peer_info) => Self {
+                schema_version: JSON_SCHEMA_VERSION,
+                target,
+                status: "ok",
+                peer: Some(peer_info),
+                error: None,
+                query_trace: None,
+            },
+        }
+    }
+
+    fn with_query_trace(mut self, query_trace: Option<&'a QueryTrace>) -> Self {
+        self.query_trace = query_trace;
+
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct CrawlDocument<'a> {
+    schema_version: u32,
+    status: &'static str,
+    crawl: &'a CrawlReport,
+}
+
+#[derive(Serialize)]
+struct RoutingTableDocument<'a> {
+    schema_version: u32,
+    status: &'static str,
+    routing_table: &'a RoutingTableReport,
+}
+
+#[derive(Serialize)]
+struct RecordQueryDocument<'a> {
+    schema_version: u32,
+    status: &'static str,
+    query: &'a RecordQueryReport,
+}
+
+/// Watch events are flattened into the document, one per line
+#[derive(Serialize)]
+struct WatchDocument<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    record: &'a WatchRecord,
+}
+
+/// Gossipsub events are flattened into the document, one per line
+#[derive(Serialize)]
+struct GossipDocument<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    record: &'a GossipRecord,
+}
+
+impl OutputFormat {
+    /// Prints the lookup result, preceded by the DHT query trace if any
+    pub(crate) fn print_lookup_result(
+        &self,
+        lookup_result: &PeerLookupResult,
+        query_trace: Option<&QueryTrace>,
+    ) {
+        match self {
+            Self::Text => {
+                if let Some(query_trace) = query_trace {
+                    log_info!("DHT query trace\n********\n{query_trace}********");
+                }
+
+                match lookup_result {
+                    Err(error_message) => {
+                        log_error!("{error_message}");
+                    }
+                    Ok(peer_info) => {
+                        log_info!("Peer information from lookup\n********\n{peer_info}********");
+                    }
+                }
+            }
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(
+                        &LookupDocument::new(None, lookup_result).with_query_trace(query_trace)
+                    )
+                    .expect("Programmatic error: unserializable lookup result!")
+                );
+            }
+        }
+    }
+
+    /// Prints a single batch record, JSON records are printed as one line each (JSON lines)
+    pub(crate) fn print_batch_record(&self, record: &BatchLookupRecord) {
+        let target = &record.target;
+
+        match self {
+            Self::Text => match &record.result {
+                Err(error_message) => {
+                    log_error!("Lookup of \"{target}\" failed: {error_message}");
+                }
+                Ok(peer_info) => {
+                    log_info!("Peer information from lookup of \"{target}\"\n********\n{peer_info}********");
+                }
+            },
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string(&LookupDocument::new(
+                        Some(target.to_string()),
+                        &record.result
+                    ))
+                    .expect("Programmatic error: unserializable lookup result!")
+                );
+            }
+        }
+    }
+
+    /// Prints every crawled peer followed by a census of the network
+    pub(crate) fn print_crawl_report(&self, crawl_report: &CrawlReport) {
+        match self {
+            Self::Text => {
+                for peer in &crawl_report.peers {
+                    match (&peer.info, &peer.error) {
+                        (Some(peer_info), _) => {
+                            log_info!("Crawled peer\n********\n{peer_info}********");
+                        }
+                        (None, error_message) => {
+                            log_info!(
+                                "Crawled peer \"{}\" is {} ({})",
+                                peer.peer_id,
+                                peer.reachability.as_str(),
+                                error_message.as_deref().unwrap_or("not probed")
+                            );
+                        }
+                    }
+                }
+
+                let mut census = format!(
+                    "Crawled \"{}\" in {:?} with {} walks, {} peers found\n[Reachability]\n",
+                    crawl_report.network,
+                    crawl_report.elapsed,
+                    crawl_report.queries,
+                    crawl_report.peers.len()
+                );
+
+                for reachability in [
+                    Reachability::Identified,
+                    Reachability::Unidentified,
+                    Reachability::Unreachable,
+                    Reachability::Unknown,
+                ] {
+                    census += &format!(
+                        "  - {}: {}\n",
+                        reachability.as_str(),
+                        crawl_report.count(reachability)
+                    );
+                }
+
+                census += "[Agent Versions]\n";
+
+                for (agent_version, count) in crawl_report.agent_versions() {
+                    census += &format!("  - \"{agent_version}\": {count}\n");
+                }
+
+                log_info!("Network census\n********\n{census}********");
+            }
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&CrawlDocument {
+                        schema_version: JSON_SCHEMA_VERSION,
+                        status: "ok",
+                        crawl: crawl_report,
+                    })
+                    .expect("Programmatic error: unserializable crawl report!")
+                );
+            }
+        }
+    }
+
+    /// Prints the k-buckets of a bootstrapped routing table
+    pub(crate) fn print_routing_table_report(&self, routing_table_report: &RoutingTableReport) {
+        match self {
+            Self::Text => {
+                log_info!("Routing table\n********\n{routing_table_report}********");
+            }
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&RoutingTableDocument {
+                        schema_version: JSON_SCHEMA_VERSION,
+                        status: if routing_table_report.error.is_some() {
+                            "error"
+                        } else {
+                            "ok"
+                        },
+                        routing_table: routing_table_report,
+                    })
+                    .expect("Programmatic error: unserializable routing table report!")
+                );
+            }
+        }
+    }
+
+    /// Prints a single watch event, JSON events are printed as one line each (JSON lines)
+    pub(crate) fn print_watch_record(&self, record: &WatchRecord) {
+        let target = &record.target;
+
+        match self {
+            Self::Text => match &record.event {
+                WatchEvent::LookupFailed(_) | WatchEvent::Disconnected { .. } => {
+                    log_warning!("\"{target}\" {}", record.event);
+                }
+                event => log_info!("\"{target}\" {event}"),
+            },
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string(&WatchDocument {
+                        schema_version: JSON_SCHEMA_VERSION,
+                        record,
+                    })
+                    .expect("Programmatic error: unserializable watch record!")
+                );
+            }
+        }
+    }
+
+    /// Prints a single gossipsub event, JSON events are printed as one line each (JSON lines)
+    pub(crate) fn print_gossip_record(&self, record: &GossipRecord) {
+        match self {
+            Self::Text => match &record.event {
+                GossipEvent::GossipsubNotSupported
+                | GossipEvent::SubscribeFailed { .. }
+                | GossipEvent::PublishFailed { .. }
+                | GossipEvent::Disconnected { .. } => {
+                    log_warning!("{}", record.event);
+                }
+                event => log_info!("{event}"),
+            },
+            Self::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string(&GossipDocument {
+                        schema_version: JSON_SCHEMA_VERSION,
+                        record,
+                    })
+                    .expect("Programmatic error: unserializable gossipsub record!")
+                );
+            }
+        }
+    }
+
+    /// Prints the providers or records found, with the error that ended the query if any
+    pub(crate) fn print_record_query_report(&self, query_report: &RecordQueryReport) {
+        match self {
+            Self::Text => {
+                log_info!(
+                    "DHT {} query\n********\n{query_report}********",
+                    query_report.query.as_str()
+                );
+            }
+            Self::Json => {
+                let status = if query_report.is_found() {
+                    "ok"
+                } else if query_report.error.is_some() {
+                    "error"
+                } else {
+                    "not_found"
+                };
+
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&RecordQueryDocument {
+                        schema_version: JSON_SCHEMA_VERSION,
+                        status,
+                        query: query_report,
+                    })
+                    .expect("Programmatic error: unserializable record query report!")
+                );
+            }
+        }
+    }
+}
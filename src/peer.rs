@@ -1,63 +1,84 @@
+use crate::addresses::{address_warnings, classify_address, AddressWarning};
+use crate::blocks::{fetch_block, BlockId, BlockProbe};
+use crate::chain::{hex_hash, probe_chain, BlockHash, ChainProbe};
 use crate::logging::{log_debug, log_info};
-use crate::options::Options;
-use futures::executor::block_on;
-use futures::future::Either;
-use futures::StreamExt;
-use libp2p::core::muxing::StreamMuxerBox;
-use libp2p::core::transport::OrTransport as TCPOrTransport;
-use libp2p::core::upgrade::{SelectUpgrade, Version as UpgradeVersion};
+use crate::negotiate::{verify_protocols, ProtocolNegotiation};
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::ping::{sample_pings, PingStatistics, PING_SAMPLING_INTERVAL};
+use crate::probe::{probe_addresses, AddressProbe};
+use crate::protocols::{served_genesis_hashes, ParsedProtocol, ProtocolCategory, ProtocolChain};
+use crate::trace::{QueryTrace, TracedWalk};
+use futures::{Future, StreamExt};
+use libp2p::core::upgrade::UpgradeError;
 use libp2p::core::ConnectedPoint;
-use libp2p::dns::DnsConfig;
-use libp2p::identify::{
-    Behaviour as IdentifyBehaviour, Config as IdentifyConfig, Event as IdentityEvent,
-    Info as IdentityInfo,
-};
+use libp2p::identify::{Event as IdentityEvent, Info as IdentityInfo};
 use libp2p::identity::{Keypair, PublicKey};
-use libp2p::kad::store::MemoryStore;
 use libp2p::kad::{
-    GetClosestPeersOk as KademliatGetClosestPeersOk, Kademlia, KademliaConfig, KademliaEvent,
+    GetClosestPeersError as KademliaGetClosestPeersError,
+    GetClosestPeersOk as KademliatGetClosestPeersOk, KademliaEvent,
     ProgressStep as KademliaProgressStep, QueryResult as KademliaQueryResult,
 };
-use libp2p::mplex::{MaxBufferBehaviour as MultiplexMaxBufferBehaviour, MplexConfig};
-use libp2p::noise::{Keypair as NoiseKeypair, NoiseConfig, X25519Spec};
-use libp2p::ping::{Behaviour as PingBehaviour, Config as PingConfig};
-use libp2p::quic::tokio::Transport as QUICTransport;
-use libp2p::quic::Config as QUICConfig;
-use libp2p::relay::client::{new as new_relay_client, Behaviour as RelayClientBehaviour};
-use libp2p::swarm::keep_alive::Behaviour as SwarmKeepAliveBehaviour;
-use libp2p::swarm::{
-    DialError as SwarmDialError, NetworkBehaviour as SwarmNetworkBehaviour, SwarmBuilder,
-    SwarmEvent,
-};
-use libp2p::tcp::async_io::Transport as AsyncTCPTransport;
-use libp2p::tcp::Config as TCPConfig;
-use libp2p::yamux::{WindowUpdateMode as YamuxWindowUpdateMode, YamuxConfig};
-use libp2p::{InboundUpgradeExt, Multiaddr, OutboundUpgradeExt, PeerId, Swarm, Transport};
+use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
+use libp2p::swarm::{ConnectionHandlerUpgrErr, DialError as SwarmDialError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
 use std::fmt::{Display, Formatter, Result as FormatterResult};
-use structopt::StructOpt;
 use thiserror::Error;
-use tokio::time::Duration;
+use tokio::time::{timeout, Duration, Instant};
 
-pub(crate) type PeerLookupResult = Result<PeerInfo, PeerLookupError>;
+pub type PeerLookupResult = Result<PeerInfo, PeerLookupError>;
 
 #[derive(Debug, Error)]
-pub(crate) enum PeerLookupError {
+pub enum PeerLookupError {
+    #[error("Failed to create transport: {0}")]
+    FailedToCreateTransport(#[source] std::io::Error),
     #[error(transparent)]
     FailedToDialPeer(#[from] SwarmDialError),
     #[error("Failed to find peer on DHT")]
     FailedToFindPeerOnDht,
+    #[error("Protocol mismatch with peer: {0}")]
+    ProtocolMismatch(String),
+    #[error("Lookup timed out after {0:?}")]
+    LookupTimeout(Duration),
+    #[error("Timed out after {0:?} while dialing the peer")]
+    DialTimeout(Duration),
+    #[error("Timed out after {0:?} while querying the DHT")]
+    DhtQueryTimeout(Duration),
+    #[error("Timed out after {0:?} while waiting for the identify message")]
+    IdentifyTimeout(Duration),
 }
 
-#[derive(SwarmNetworkBehaviour)]
-pub(crate) struct PeerLookupBehaviour {
-    pub(crate) dht: Kademlia<MemoryStore>,
-    pub(crate) ping: PingBehaviour,
-    pub(crate) identify: IdentifyBehaviour,
-    pub(crate) relay: RelayClientBehaviour,
-    pub(crate) keep_alive: SwarmKeepAliveBehaviour,
+impl PeerLookupError {
+    /// Process exit code reported for this error, `1` is left to command line parsing errors and
+    /// `6` to `8` to the outcomes of other subcommands and to configuration errors
+    ///
+    /// | Code | Error                       |
+    /// | ---- | --------------------------- |
+    /// | 2    | Failed to create transport  |
+    /// | 3    | Failed to dial peer         |
+    /// | 4    | Failed to find peer on DHT  |
+    /// | 5    | Protocol mismatch with peer |
+    /// | 10   | Whole lookup timed out      |
+    /// | 11   | Dialing timed out           |
+    /// | 12   | DHT query timed out         |
+    /// | 13   | Identification timed out    |
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::FailedToCreateTransport(_) => 2,
+            Self::FailedToDialPeer(_) => 3,
+            Self::FailedToFindPeerOnDht => 4,
+            Self::ProtocolMismatch(_) => 5,
+            Self::LookupTimeout(_) => 10,
+            Self::DialTimeout(_) => 11,
+            Self::DhtQueryTimeout(_) => 12,
+            Self::IdentifyTimeout(_) => 13,
+        }
+    }
 }
 
-pub(crate) struct PeerInfo {
+/// Identification of a peer, as reported by the peer itself
+pub struct PeerInfo {
     id: PeerId,
     public_key: PublicKey,
     protocol_version: String,
@@ -65,10 +86,21 @@ pub(crate) struct PeerInfo {
     listen_addresses: Vec<Multiaddr>,
     protocols: Vec<String>,
     observed_address: Multiaddr,
+    established_in: Duration,
+    identified_in: Duration,
+    address_probes: Vec<AddressProbe>,
+    ping: Option<PingStatistics>,
+    chain: Option<ChainProbe>,
+    block: Option<BlockProbe>,
+    protocol_negotiations: Vec<ProtocolNegotiation>,
 }
 
-impl From<IdentityInfo> for PeerInfo {
-    fn from(value: IdentityInfo) -> Self {
+impl PeerInfo {
+    pub(crate) fn new(
+        value: IdentityInfo,
+        established_in: Duration,
+        identified_in: Duration,
+    ) -> Self {
         Self {
             id: value.public_key.to_peer_id(),
             public_key: value.public_key,
@@ -77,7 +109,231 @@ impl From<IdentityInfo> for PeerInfo {
             listen_addresses: value.listen_addrs,
             protocols: value.protocols,
             observed_address: value.observed_addr,
+            established_in,
+            identified_in,
+            address_probes: Vec::new(),
+            ping: None,
+            chain: None,
+            block: None,
+            protocol_negotiations: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_address_probes(&mut self, address_probes: Vec<AddressProbe>) {
+        self.address_probes = address_probes;
+    }
+
+    pub(crate) fn set_ping(&mut self, ping: PingStatistics) {
+        self.ping = Some(ping);
+    }
+
+    pub(crate) fn set_chain(&mut self, chain: ChainProbe) {
+        self.chain = Some(chain);
+    }
+
+    pub(crate) fn set_block(&mut self, block: BlockProbe) {
+        self.block = Some(block);
+    }
+
+    pub(crate) fn set_protocol_negotiations(
+        &mut self,
+        protocol_negotiations: Vec<ProtocolNegotiation>,
+    ) {
+        self.protocol_negotiations = protocol_negotiations;
+    }
+
+    pub fn id(&self) -> &PeerId {
+        &self.id
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn protocol_version(&self) -> &str {
+        &self.protocol_version
+    }
+
+    pub fn agent_version(&self) -> &str {
+        &self.agent_version
+    }
+
+    pub fn listen_addresses(&self) -> &[Multiaddr] {
+        &self.listen_addresses
+    }
+
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols
+    }
+
+    pub fn observed_address(&self) -> &Multiaddr {
+        &self.observed_address
+    }
+
+    /// Time taken to establish the connection to the peer
+    pub fn established_in(&self) -> Duration {
+        self.established_in
+    }
+
+    /// Time taken from connection establishment to the identify message
+    pub fn identified_in(&self) -> Duration {
+        self.identified_in
+    }
+
+    /// Protocols split into their chain, family and version
+    pub fn parsed_protocols(&self) -> Vec<ParsedProtocol> {
+        self.protocols
+            .iter()
+            .map(|protocol| ParsedProtocol::parse(protocol))
+            .collect()
+    }
+
+    /// Distinct genesis hashes of the chains the peer serves protocols of, more than one
+    /// pointing out a multi-chain or misconfigured node
+    pub fn served_genesis_hashes(&self) -> Vec<BlockHash> {
+        served_genesis_hashes(&self.parsed_protocols())
+    }
+
+    /// Misconfigurations spotted in the listen addresses, computed from the addresses alone
+    pub fn address_warnings(&self) -> Vec<AddressWarning> {
+        address_warnings(&self.listen_addresses)
+    }
+
+    /// Reachability of every address of the peer, empty unless probed
+    pub fn address_probes(&self) -> &[AddressProbe] {
+        &self.address_probes
+    }
+
+    /// Round-trip times sampled once identified, `None` unless sampled
+    pub fn ping(&self) -> Option<&PingStatistics> {
+        self.ping.as_ref()
+    }
+
+    /// Chain state read from the block announces handshake, `None` unless probed
+    pub fn chain(&self) -> Option<&ChainProbe> {
+        self.chain.as_ref()
+    }
+
+    /// Block fetched from the peer through the block request protocol, `None` unless requested
+    pub fn block(&self) -> Option<&BlockProbe> {
+        self.block.as_ref()
+    }
+
+    /// Outcome of actively negotiating every advertised protocol (and extra ones), empty unless
+    /// verified
+    pub fn protocol_negotiations(&self) -> &[ProtocolNegotiation] {
+        &self.protocol_negotiations
+    }
+
+    fn public_key_parts(&self) -> (&'static str, Vec<u8>) {
+        #[allow(deprecated)]
+        match &self.public_key {
+            PublicKey::Ed25519(key) => ("ed25519", key.encode().to_vec()),
+            PublicKey::Secp256k1(key) => ("secp256k1", key.encode().to_vec()),
+            PublicKey::Ecdsa(key) => ("ecdsa", key.to_bytes()),
+            PublicKey::Rsa(key) => ("rsa", key.encode_x509()),
+        }
+    }
+}
+
+struct SerializablePublicKey<'a>(&'a PeerInfo);
+
+impl Serialize for SerializablePublicKey<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (key_type, key_bytes) = self.0.public_key_parts();
+        let mut state = serializer.serialize_struct("PublicKey", 2)?;
+        state.serialize_field("type", key_type)?;
+        state.serialize_field("bytes", &hex::encode(key_bytes))?;
+
+        state.end()
+    }
+}
+
+/// Class of every listen address, keyed by the address
+struct SerializableAddressClasses<'a>(&'a [Multiaddr]);
+
+impl Serialize for SerializableAddressClasses<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_map(Some(self.0.len()))?;
+
+        for address in self.0 {
+            state.serialize_entry(&address.to_string(), &classify_address(address))?;
+        }
+
+        state.end()
+    }
+}
+
+struct SerializableTiming<'a>(&'a PeerInfo);
+
+impl Serialize for SerializableTiming<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Timing", 2)?;
+        state.serialize_field(
+            "established_in_ms",
+            &(self.0.established_in.as_secs_f64() * 1000.0),
+        )?;
+        state.serialize_field(
+            "identified_in_ms",
+            &(self.0.identified_in.as_secs_f64() * 1000.0),
+        )?;
+
+        state.end()
+    }
+}
+
+impl Serialize for PeerInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let listen_addresses = self
+            .listen_addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let mut state = serializer.serialize_struct("PeerInfo", 17)?;
+        state.serialize_field("peer_id", &self.id.to_base58())?;
+        state.serialize_field("public_key", &SerializablePublicKey(self))?;
+        state.serialize_field("protocol_version", &self.protocol_version)?;
+        state.serialize_field("agent_version", &self.agent_version)?;
+        state.serialize_field("observed_address", &self.observed_address.to_string())?;
+        state.serialize_field("listen_addresses", &listen_addresses)?;
+        state.serialize_field(
+            "listen_address_classes",
+            &SerializableAddressClasses(&self.listen_addresses),
+        )?;
+        state.serialize_field("address_warnings", &self.address_warnings())?;
+        state.serialize_field("protocols", &self.protocols)?;
+        state.serialize_field("protocol_details", &self.parsed_protocols())?;
+        state.serialize_field(
+            "served_genesis_hashes",
+            &self
+                .served_genesis_hashes()
+                .iter()
+                .map(hex_hash)
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("timing", &SerializableTiming(self))?;
+
+        if !self.address_probes.is_empty() {
+            state.serialize_field("address_probes", &self.address_probes)?;
+        }
+
+        if !self.protocol_negotiations.is_empty() {
+            state.serialize_field("protocol_negotiations", &self.protocol_negotiations)?;
+        }
+
+        if let Some(ping) = &self.ping {
+            state.serialize_field("ping", ping)?;
+        }
+
+        if let Some(chain) = &self.chain {
+            state.serialize_field("chain", chain)?;
+        }
+
+        if let Some(block) = &self.block {
+            state.serialize_field("block", block)?;
         }
+
+        state.end()
     }
 }
 
@@ -96,20 +352,144 @@ impl Display for PeerInfo {
             "[Observed Address]\n  \"{}\"",
             &self.observed_address
         )?;
+        writeln!(
+            formatter,
+            "[Connection Timing]\n  - established in {:?}\n  - identified in {:?}",
+            &self.established_in, &self.identified_in
+        )?;
 
         if !self.listen_addresses.is_empty() {
             writeln!(formatter, "[Listen Addresses]")?;
 
             for addr in &self.listen_addresses {
-                writeln!(formatter, "  - \"{addr}\"")?;
+                writeln!(formatter, "  - \"{addr}\" ({})", classify_address(addr))?;
+            }
+        }
+
+        let address_warnings = self.address_warnings();
+
+        if !address_warnings.is_empty() {
+            writeln!(formatter, "[Address Warnings]")?;
+
+            for warning in &address_warnings {
+                writeln!(formatter, "  - {warning}")?;
+
+                if let AddressWarning::InternalNetworkLeak(addresses) = warning {
+                    for addr in addresses {
+                        writeln!(formatter, "    - \"{addr}\"")?;
+                    }
+                }
             }
         }
 
-        if !self.protocols.is_empty() {
+        let parsed_protocols = self.parsed_protocols();
+
+        if !parsed_protocols.is_empty() {
             writeln!(formatter, "[Protocols]")?;
 
-            for protocol in &self.protocols {
-                writeln!(formatter, "  - \"{protocol}\"")?;
+            for category in ProtocolCategory::ALL {
+                let mut category_protocols = parsed_protocols
+                    .iter()
+                    .filter(|protocol| protocol.category == category)
+                    .peekable();
+
+                if category_protocols.peek().is_none() {
+                    continue;
+                }
+
+                writeln!(formatter, "  {category}:")?;
+
+                for protocol in category_protocols {
+                    writeln!(formatter, "    - {protocol}")?;
+                }
+            }
+        }
+
+        let genesis_hashes = served_genesis_hashes(&parsed_protocols);
+        let mut legacy_chain_ids = Vec::new();
+
+        for protocol in &parsed_protocols {
+            if let Some(ProtocolChain::Legacy(chain_id)) = &protocol.chain {
+                if !legacy_chain_ids.contains(&chain_id) {
+                    legacy_chain_ids.push(chain_id);
+                }
+            }
+        }
+
+        if !genesis_hashes.is_empty() || !legacy_chain_ids.is_empty() {
+            writeln!(formatter, "[Served Chains]")?;
+
+            for genesis_hash in &genesis_hashes {
+                writeln!(formatter, "  - genesis {}", hex_hash(genesis_hash))?;
+            }
+
+            for chain_id in &legacy_chain_ids {
+                writeln!(formatter, "  - legacy chain \"{chain_id}\"")?;
+            }
+
+            if genesis_hashes.len() > 1 {
+                writeln!(
+                    formatter,
+                    "  ! protocols of {} different genesis hashes served, multi-chain or misconfigured node",
+                    genesis_hashes.len()
+                )?;
+            }
+        }
+
+        if !self.protocol_negotiations.is_empty() {
+            writeln!(formatter, "[Protocol Support]")?;
+
+            for negotiation in &self.protocol_negotiations {
+                writeln!(
+                    formatter,
+                    "  - \"{}\" ({}): {}{}",
+                    negotiation.protocol,
+                    if negotiation.advertised {
+                        "advertised"
+                    } else {
+                        "extra"
+                    },
+                    negotiation.outcome,
+                    if negotiation.is_misadvertised() {
+                        ", advertised but not served!"
+                    } else {
+                        ""
+                    }
+                )?;
+            }
+        }
+
+        if let Some(ping) = &self.ping {
+            writeln!(formatter, "[Ping]\n  {ping}")?;
+        }
+
+        if let Some(chain) = &self.chain {
+            writeln!(formatter, "[Chain State]\n{chain}")?;
+        }
+
+        if let Some(block) = &self.block {
+            writeln!(formatter, "[Block]\n{block}")?;
+        }
+
+        if !self.address_probes.is_empty() {
+            writeln!(formatter, "[Address Probes]")?;
+
+            for probe in &self.address_probes {
+                match &probe.outcome {
+                    Ok(established_in) => writeln!(
+                        formatter,
+                        "  - \"{}\" ({}): reachable in {established_in:?}",
+                        probe.address,
+                        probe.sources()
+                    )?,
+                    Err(error) => writeln!(
+                        formatter,
+                        "  - \"{}\" ({}): unreachable, {} ({error})",
+                        probe.address,
+                        probe.sources(),
+                        error.kind()
+                    )?,
+                }
             }
         }
 
@@ -117,14 +497,62 @@ impl Display for PeerInfo {
     }
 }
 
-pub(crate) enum PeerLookupClient {
+/// Deadlines of each lookup phase
+#[derive(Debug, Clone, Copy)]
+pub struct LookupTimeouts {
+    /// Deadline of the whole lookup, unbounded (besides each phase) if unset
+    pub total: Option<Duration>,
+    /// Deadline of establishing a connection to the peer
+    pub dial: Duration,
+    /// Deadline of walking the DHT to find the peer
+    pub dht_query: Duration,
+    /// Deadline of receiving the identify message once connected
+    pub identify: Duration,
+}
+
+impl Default for LookupTimeouts {
+    fn default() -> Self {
+        Self {
+            total: None,
+            dial: Duration::from_secs(30),
+            dht_query: Duration::from_secs(60),
+            identify: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Bounds a lookup phase, resolving to `on_expiry` if the phase doesn't finish in time
+pub(crate) async fn within<T>(
+    limit: Duration,
+    on_expiry: PeerLookupError,
+    phase: impl Future<Output = Result<T, PeerLookupError>>,
+) -> Result<T, PeerLookupError> {
+    timeout(limit, phase).await.unwrap_or(Err(on_expiry))
+}
+
+pub enum PeerLookupClient {
     Direct {
         swarm: Swarm<PeerLookupBehaviour>,
         destination: Multiaddr,
+        timeouts: LookupTimeouts,
+        ping_samples: usize,
+        probe_chain: bool,
+        fetch_block: Option<BlockId>,
+        /// Extra protocols to negotiate along with the advertised ones, `None` unless verifying
+        verify_protocols: Option<Vec<String>>,
     },
     Dht {
         swarm: Swarm<PeerLookupBehaviour>,
         peer_id: PeerId,
+        timeouts: LookupTimeouts,
+        trace_query: bool,
+        probe_all_addresses: bool,
+        ping_samples: usize,
+        probe_chain: bool,
+        fetch_block: Option<BlockId>,
+        verify_protocols: Option<Vec<String>>,
+        /// Genesis hash of the DHT network, which the peer's chain is checked against
+        expected_genesis_hash: Option<BlockHash>,
     },
 }
 
@@ -132,7 +560,10 @@ impl PeerLookupClient {
     async fn wait_for_indentication(
         swarm: &mut Swarm<PeerLookupBehaviour>,
         peer: PeerId,
+        established_in: Duration,
     ) -> PeerLookupResult {
+        let identify_started_at = Instant::now();
+
         loop {
             match swarm
                 .next()
@@ -143,18 +574,32 @@ impl PeerLookupClient {
                     IdentityEvent::Received { peer_id, info },
                 )) => {
                     if peer_id == peer {
-                        return Ok(info.into());
+                        return Ok(PeerInfo::new(
+                            info,
+                            established_in,
+                            identify_started_at.elapsed(),
+                        ));
                     }
                 }
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(
+                    IdentityEvent::Error {
+                        peer_id,
+                        error: ConnectionHandlerUpgrErr::Upgrade(UpgradeError::Select(error)),
+                    },
+                )) if peer_id == peer => {
+                    return Err(PeerLookupError::ProtocolMismatch(format!(
+                        "identify protocol can't be negotiated ({error})"
+                    )));
+                }
                 other_event => log_debug!("{other_event:?}"),
             }
         }
     }
 
-    async fn lookup_directly(
+    async fn connect_directly(
         swarm: &mut Swarm<PeerLookupBehaviour>,
         destination_address: Multiaddr,
-    ) -> PeerLookupResult {
+    ) -> Result<(PeerId, Duration), PeerLookupError> {
         swarm.dial(destination_address.clone())?;
 
         loop {
@@ -170,7 +615,9 @@ impl PeerLookupClient {
                     established_in,
                     ..
                 } => {
-                    assert_eq!(Into::<u32>::into(num_established), 1);
+                    if num_established.get() > 1 {
+                        log_debug!("Connection #{num_established} established with \"{peer_id}\"");
+                    }
 
                     match endpoint {
                         ConnectedPoint::Dialer {
@@ -180,7 +627,7 @@ impl PeerLookupClient {
                             if address == destination_address {
                                 log_info!("Connection established in {established_in:?} for \"{address}\" (role override: {role_override:?})");
 
-                                return Self::wait_for_indentication(swarm, peer_id).await;
+                                return Ok((peer_id, established_in));
                             }
                         }
                         ConnectedPoint::Listener {
@@ -207,10 +654,65 @@ impl PeerLookupClient {
         }
     }
 
-    async fn lookup_via_dht(
+    async fn connect_to_peer(
         swarm: &mut Swarm<PeerLookupBehaviour>,
         target_peer_id: PeerId,
-    ) -> PeerLookupResult {
+    ) -> Result<Duration, PeerLookupError> {
+        swarm.dial(target_peer_id)?;
+
+        loop {
+            match swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::ConnectionEstablished {
+                    peer_id,
+                    established_in,
+                    endpoint,
+                    ..
+                } => {
+                    if peer_id == target_peer_id {
+                        Self::log_peer_connection(&peer_id, &endpoint, established_in);
+
+                        return Ok(established_in);
+                    }
+                }
+                SwarmEvent::OutgoingConnectionError {
+                    peer_id: Some(peer_id),
+                    error,
+                } => {
+                    if peer_id == target_peer_id {
+                        return Err(error.into());
+                    }
+                }
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    }
+
+    fn log_peer_connection(peer_id: &PeerId, endpoint: &ConnectedPoint, established_in: Duration) {
+        match endpoint {
+            ConnectedPoint::Dialer { address, .. } => {
+                let address_string = address.to_string().replace(&peer_id.to_string(), "");
+                log_info!("Connection established in {established_in:?} for \"{peer_id}\" (via \"{address_string}\")");
+            }
+            ConnectedPoint::Listener {
+                local_addr,
+                send_back_addr,
+            } => {
+                log_info!("Connection established in {established_in:?} for \"{peer_id}\" (via \"{local_addr}\" with send back address \"{send_back_addr}\")");
+            }
+        }
+    }
+
+    /// Walks the DHT until the target peer is found, returning the connection establishment
+    /// duration if the walk itself already connected to the target peer
+    async fn walk_dht(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        target_peer_id: PeerId,
+        dht_query_timeout: Duration,
+    ) -> Result<Option<Duration>, PeerLookupError> {
         swarm.behaviour_mut().dht.get_closest_peers(target_peer_id);
 
         loop {
@@ -226,51 +728,38 @@ impl PeerLookupClient {
                     endpoint,
                     ..
                 } => {
-                    assert_eq!(Into::<u32>::into(num_established), 1);
+                    if num_established.get() > 1 {
+                        log_debug!("Connection #{num_established} established with \"{peer_id}\"");
+                    }
 
                     if peer_id == target_peer_id {
-                        match endpoint {
-                            ConnectedPoint::Dialer { address, .. } => {
-                                let address_string =
-                                    address.to_string().replace(&peer_id.to_string(), "");
-                                log_info!("Connection established in {established_in:?} for \"{peer_id}\" (via \"{address_string}\")");
-                            }
-                            ConnectedPoint::Listener {
-                                local_addr,
-                                send_back_addr,
-                            } => {
-                                log_info!("Connection established in {established_in:?} for \"{peer_id}\" (via \"{local_addr}\" with send back address \"{send_back_addr}\")");
-                            }
-                        }
+                        Self::log_peer_connection(&peer_id, &endpoint, established_in);
 
-                        return Self::wait_for_indentication(swarm, peer_id).await;
+                        return Ok(Some(established_in));
                     }
                 }
                 SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
                     KademliaEvent::OutboundQueryProgressed {
-                        result: KademliaQueryResult::Bootstrap(_),
-                        ..
-                    },
-                )) => {
-                    panic!("Unexpected bootstrap in Kademlia rooting!");
-                }
-                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
-                    KademliaEvent::OutboundQueryProgressed {
-                        result:
-                            KademliaQueryResult::GetClosestPeers(Ok(KademliatGetClosestPeersOk {
-                                peers,
-                                ..
-                            })),
+                        result: KademliaQueryResult::GetClosestPeers(query_result),
                         step: KademliaProgressStep { count: _, last },
                         ..
                     },
                 )) => {
+                    let (peers, timed_out) = match query_result {
+                        Ok(KademliatGetClosestPeersOk { peers, .. }) => (peers, false),
+                        Err(KademliaGetClosestPeersError::Timeout { peers, .. }) => (peers, true),
+                    };
+
                     if peers.contains(&target_peer_id) {
-                        if !Swarm::is_connected(swarm, &target_peer_id) {
-                            Swarm::dial(swarm, target_peer_id).unwrap();
+                        if Swarm::is_connected(swarm, &target_peer_id) {
+                            return Ok(Some(Duration::ZERO));
                         }
 
-                        return Self::wait_for_indentication(swarm, target_peer_id).await;
+                        return Ok(None);
+                    }
+
+                    if timed_out {
+                        return Err(PeerLookupError::DhtQueryTimeout(dht_query_timeout));
                     }
 
                     if last {
@@ -282,124 +771,537 @@ impl PeerLookupClient {
         }
     }
 
-    pub(crate) fn create_from_options() -> Self {
-        let (destination_address, dht_network) = match Options::from_args() {
-            Options::Dht {
-                peer_id,
-                dht_network,
-            } => (None, Some((dht_network, peer_id))),
-            Options::Direct { address } => (Some(address), None),
-        };
+    /// Walks the DHT like [`Self::walk_dht`], but with single `FIND_NODE` requests so that every
+    /// hop is recorded in the query trace
+    async fn trace_dht(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        target_peer_id: PeerId,
+        query_trace: &mut QueryTrace,
+    ) -> Result<Option<Duration>, PeerLookupError> {
+        let seeds = swarm
+            .behaviour_mut()
+            .dht
+            .kbuckets()
+            .flat_map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|entry| {
+                        (
+                            *entry.node.key.preimage(),
+                            entry.node.value.clone().into_vec(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let mut walk = TracedWalk::new(target_peer_id, seeds);
 
-        let local_key = Keypair::generate_ed25519();
-        let local_peer_id = PeerId::from(local_key.public());
-        log_info!("Local peer id: \"{local_peer_id}\"");
-        let (relay_transport, relay_client) = new_relay_client(local_peer_id);
-        let transport = {
-            let authentication_config = {
-                let noise_keypair_spec = NoiseKeypair::<X25519Spec>::new()
-                    .into_authentic(&local_key)
-                    .expect("Programmatic error: incompatible keypair spec!");
-
-                NoiseConfig::xx(noise_keypair_spec).into_authenticated()
-            };
-            let multiplexing_config = {
-                let mut mplex_config = MplexConfig::new();
-                mplex_config.set_max_buffer_behaviour(MultiplexMaxBufferBehaviour::Block);
-                mplex_config.set_max_buffer_size(usize::MAX);
-                let mut yamux_config = YamuxConfig::default();
-                yamux_config.set_window_update_mode(YamuxWindowUpdateMode::on_read());
-
-                SelectUpgrade::new(yamux_config, mplex_config)
-                    .map_inbound(StreamMuxerBox::new)
-                    .map_outbound(StreamMuxerBox::new)
-            };
-            let tcp_and_relay_transport = TCPOrTransport::new(
-                relay_transport,
-                AsyncTCPTransport::new(TCPConfig::new().port_reuse(true).nodelay(true)),
-            )
-            .upgrade(UpgradeVersion::V1)
-            .authenticate(authentication_config)
-            .multiplex(multiplexing_config)
-            .timeout(Duration::from_secs(10));
-            let quic_transport = {
-                let mut config = QUICConfig::new(&local_key);
-                config.support_draft_29 = true;
-
-                QUICTransport::new(config)
-            };
-
-            block_on(DnsConfig::system(TCPOrTransport::new(
-                quic_transport,
-                tcp_and_relay_transport,
-            )))
-            .expect("Can't decide network to connect! Does the network available?")
-            .map(|either_output, _| match either_output {
-                Either::Left((peer_id, stream_muxer)) => {
-                    (peer_id, StreamMuxerBox::new(stream_muxer))
+        loop {
+            if let Some(target_addresses) = walk.target_addresses() {
+                query_trace.found = true;
+
+                for address in target_addresses {
+                    swarm
+                        .behaviour_mut()
+                        .dht
+                        .add_address(&target_peer_id, address.clone());
                 }
-                Either::Right((peer_id, stream_muxer)) => {
-                    (peer_id, StreamMuxerBox::new(stream_muxer))
+
+                if Swarm::is_connected(swarm, &target_peer_id) {
+                    return Ok(Some(Duration::ZERO));
                 }
+
+                return Ok(None);
+            }
+
+            if walk.is_exhausted() {
+                return Err(PeerLookupError::FailedToFindPeerOnDht);
+            }
+
+            for (peer_id, addresses) in walk.next_requests() {
+                let find_node = &mut swarm.behaviour_mut().find_node;
+
+                for address in addresses {
+                    find_node.add_address(&peer_id, address);
+                }
+
+                let request_id = find_node.send_request(&peer_id, target_peer_id.to_bytes());
+                walk.on_request_sent(request_id, peer_id);
+            }
+
+            match swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::FindNode(
+                    RequestResponseEvent::Message {
+                        message:
+                            RequestResponseMessage::Response {
+                                request_id,
+                                response,
+                            },
+                        ..
+                    },
+                )) => walk.on_response(request_id, Ok(response), query_trace),
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::FindNode(
+                    RequestResponseEvent::OutboundFailure {
+                        request_id, error, ..
+                    },
+                )) => walk.on_response(request_id, Err(error.to_string()), query_trace),
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    }
+
+    pub(crate) async fn lookup_directly(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        destination_address: Multiaddr,
+        timeouts: LookupTimeouts,
+    ) -> PeerLookupResult {
+        let (peer_id, established_in) = within(
+            timeouts.dial,
+            PeerLookupError::DialTimeout(timeouts.dial),
+            Self::connect_directly(swarm, destination_address),
+        )
+        .await?;
+
+        within(
+            timeouts.identify,
+            PeerLookupError::IdentifyTimeout(timeouts.identify),
+            Self::wait_for_indentication(swarm, peer_id, established_in),
+        )
+        .await
+    }
+
+    /// Addresses of the peer known to the local routing table
+    fn dht_addresses(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        target_peer_id: PeerId,
+    ) -> Vec<Multiaddr> {
+        swarm
+            .behaviour_mut()
+            .dht
+            .kbucket(target_peer_id)
+            .and_then(|bucket| {
+                bucket
+                    .iter()
+                    .find(|entry| *entry.node.key.preimage() == target_peer_id)
+                    .map(|entry| entry.node.value.iter().cloned().collect())
             })
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
-            .boxed()
-        };
+            .unwrap_or_default()
+    }
 
-        let behaviour = {
-            let local_peer_id = PeerId::from(local_key.public());
-            let store = MemoryStore::new(local_peer_id);
-            let mut kademlia_config = KademliaConfig::default();
+    /// Dials every address the identified peer announced or is known by in the DHT
+    async fn probe_peer_addresses(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        peer_info: &mut PeerInfo,
+        dial_timeout: Duration,
+    ) {
+        let dht_addresses = Self::dht_addresses(swarm, peer_info.id);
+        let mut addresses: Vec<(Multiaddr, bool, bool)> = Vec::new();
 
-            if let Some(protocol_name) = dht_network
-                .as_ref()
-                .and_then(|(dht_network, _)| dht_network.protocol())
+        for address in peer_info.listen_addresses.iter().chain(&dht_addresses) {
+            if addresses
+                .iter()
+                .any(|(known_address, ..)| known_address == address)
             {
-                kademlia_config.set_protocol_names(vec![protocol_name.into_bytes().into()]);
+                continue;
             }
 
-            let kademlia = Kademlia::with_config(local_peer_id, store, kademlia_config);
-            let ping = PingBehaviour::new(PingConfig::new());
-            let user_agent = "substrate-node/v2.0.0-85dacde-wasm32 (unknown)".to_owned();
-            let proto_version = "/substrate/1.0".to_string();
-            let identify = IdentifyBehaviour::new(
-                IdentifyConfig::new(proto_version, local_key.public())
-                    .with_agent_version(user_agent),
-            );
-
-            PeerLookupBehaviour {
-                dht: kademlia,
-                ping,
-                identify,
-                relay: relay_client,
-                keep_alive: SwarmKeepAliveBehaviour,
+            addresses.push((
+                address.clone(),
+                peer_info.listen_addresses.contains(address),
+                dht_addresses.contains(address),
+            ));
+        }
+
+        let address_probes = probe_addresses(swarm, peer_info.id, addresses, dial_timeout).await;
+        peer_info.set_address_probes(address_probes);
+    }
+
+    pub(crate) async fn lookup_via_dht(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        target_peer_id: PeerId,
+        timeouts: LookupTimeouts,
+        query_trace: Option<&mut QueryTrace>,
+        probe_all_addresses: bool,
+    ) -> PeerLookupResult {
+        let walk_result = within(
+            timeouts.dht_query,
+            PeerLookupError::DhtQueryTimeout(timeouts.dht_query),
+            async {
+                match query_trace {
+                    Some(query_trace) => Self::trace_dht(swarm, target_peer_id, query_trace).await,
+                    None => Self::walk_dht(swarm, target_peer_id, timeouts.dht_query).await,
+                }
+            },
+        )
+        .await?;
+        let established_in = match walk_result {
+            Some(established_in) => established_in,
+            None => {
+                within(
+                    timeouts.dial,
+                    PeerLookupError::DialTimeout(timeouts.dial),
+                    Self::connect_to_peer(swarm, target_peer_id),
+                )
+                .await?
             }
         };
 
-        let mut swarm =
-            SwarmBuilder::with_tokio_executor(transport, behaviour, local_peer_id).build();
+        let mut peer_info = within(
+            timeouts.identify,
+            PeerLookupError::IdentifyTimeout(timeouts.identify),
+            Self::wait_for_indentication(swarm, target_peer_id, established_in),
+        )
+        .await?;
 
-        if let Some((network, peer_id)) = dht_network {
-            for (addr, peer_id) in network.bootnodes() {
-                swarm.behaviour_mut().dht.add_address(&peer_id, addr);
-            }
+        if probe_all_addresses {
+            Self::probe_peer_addresses(swarm, &mut peer_info, timeouts.dial).await;
+        }
 
-            Self::Dht { swarm, peer_id }
-        } else {
-            Self::Direct {
-                swarm,
-                destination: destination_address.expect("Programmatic error: bad logical flow!"),
+        Ok(peer_info)
+    }
+
+    /// Starts configuring a lookup client for the given target
+    pub fn builder(target: LookupTarget) -> PeerLookupClientBuilder {
+        PeerLookupClientBuilder::new(target)
+    }
+
+    /// Runs the lookup until the target peer is identified or the lookup fails
+    pub async fn do_lookup(self) -> PeerLookupResult {
+        self.do_traced_lookup().await.0
+    }
+
+    /// Runs the lookup like [`Self::do_lookup`], also returning the DHT query trace when tracing
+    /// is enabled, even if the lookup failed
+    pub async fn do_traced_lookup(self) -> (PeerLookupResult, Option<QueryTrace>) {
+        let (total_timeout, mut query_trace) = match &self {
+            Self::Direct { timeouts, .. } => (timeouts.total, None),
+            Self::Dht {
+                peer_id,
+                timeouts,
+                trace_query,
+                ..
+            } => (
+                timeouts.total,
+                trace_query.then(|| QueryTrace::new(*peer_id)),
+            ),
+        };
+        let lookup_result = match total_timeout {
+            Some(total_timeout) => {
+                within(
+                    total_timeout,
+                    PeerLookupError::LookupTimeout(total_timeout),
+                    self.run_lookup(&mut query_trace),
+                )
+                .await
             }
+            None => self.run_lookup(&mut query_trace).await,
+        };
+
+        (lookup_result, query_trace)
+    }
+
+    /// Samples the round-trip time of the identified peer, unless no samples are wanted, within
+    /// the sampling interval of every sample plus the dial timeout
+    async fn sample_peer_pings(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        mut peer_info: PeerInfo,
+        ping_samples: usize,
+        dial_timeout: Duration,
+    ) -> PeerInfo {
+        if ping_samples > 0 {
+            let deadline = PING_SAMPLING_INTERVAL * ping_samples as u32 + dial_timeout;
+            let ping = sample_pings(swarm, peer_info.id, ping_samples, deadline).await;
+            peer_info.set_ping(ping);
+        }
+
+        peer_info
+    }
+
+    /// Reads the chain state of the identified peer from its block announces handshake
+    async fn probe_peer_chain(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        mut peer_info: PeerInfo,
+        expected_genesis_hash: Option<BlockHash>,
+        handshake_timeout: Duration,
+    ) -> PeerInfo {
+        let chain = probe_chain(
+            swarm,
+            peer_info.id,
+            &peer_info.protocols,
+            expected_genesis_hash,
+            handshake_timeout,
+        )
+        .await;
+        peer_info.set_chain(chain);
+
+        peer_info
+    }
+
+    /// Requests a single block from the identified peer, unless none is wanted
+    async fn fetch_peer_block(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        mut peer_info: PeerInfo,
+        block_id: Option<BlockId>,
+        request_timeout: Duration,
+    ) -> PeerInfo {
+        if let Some(block_id) = block_id {
+            let block = fetch_block(swarm, peer_info.id, block_id, request_timeout).await;
+            peer_info.set_block(block);
         }
+
+        peer_info
     }
 
-    pub(crate) async fn do_lookup(self) -> PeerLookupResult {
+    /// Negotiates every advertised protocol of the identified peer along with the extra ones,
+    /// unless not verifying
+    async fn verify_peer_protocols(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        mut peer_info: PeerInfo,
+        extra_protocols: Option<Vec<String>>,
+    ) -> PeerInfo {
+        if let Some(extra_protocols) = extra_protocols {
+            let protocol_negotiations =
+                verify_protocols(swarm, peer_info.id, &peer_info.protocols, &extra_protocols).await;
+            peer_info.set_protocol_negotiations(protocol_negotiations);
+        }
+
+        peer_info
+    }
+
+    async fn run_lookup(self, query_trace: &mut Option<QueryTrace>) -> PeerLookupResult {
         match self {
             Self::Direct {
                 mut swarm,
                 destination,
-            } => Self::lookup_directly(&mut swarm, destination).await,
-            Self::Dht { mut swarm, peer_id } => Self::lookup_via_dht(&mut swarm, peer_id).await,
+                timeouts,
+                ping_samples,
+                probe_chain,
+                fetch_block,
+                verify_protocols,
+            } => {
+                let mut peer_info =
+                    Self::lookup_directly(&mut swarm, destination, timeouts).await?;
+
+                if probe_chain {
+                    peer_info =
+                        Self::probe_peer_chain(&mut swarm, peer_info, None, timeouts.identify)
+                            .await;
+                }
+
+                let peer_info =
+                    Self::fetch_peer_block(&mut swarm, peer_info, fetch_block, timeouts.identify)
+                        .await;
+                let peer_info =
+                    Self::verify_peer_protocols(&mut swarm, peer_info, verify_protocols).await;
+
+                Ok(
+                    Self::sample_peer_pings(&mut swarm, peer_info, ping_samples, timeouts.dial)
+                        .await,
+                )
+            }
+            Self::Dht {
+                mut swarm,
+                peer_id,
+                timeouts,
+                probe_all_addresses,
+                ping_samples,
+                probe_chain,
+                fetch_block,
+                verify_protocols,
+                expected_genesis_hash,
+                ..
+            } => {
+                let mut peer_info = Self::lookup_via_dht(
+                    &mut swarm,
+                    peer_id,
+                    timeouts,
+                    query_trace.as_mut(),
+                    probe_all_addresses,
+                )
+                .await?;
+
+                if probe_chain {
+                    peer_info = Self::probe_peer_chain(
+                        &mut swarm,
+                        peer_info,
+                        expected_genesis_hash,
+                        timeouts.identify,
+                    )
+                    .await;
+                }
+
+                let peer_info =
+                    Self::fetch_peer_block(&mut swarm, peer_info, fetch_block, timeouts.identify)
+                        .await;
+                let peer_info =
+                    Self::verify_peer_protocols(&mut swarm, peer_info, verify_protocols).await;
+
+                Ok(
+                    Self::sample_peer_pings(&mut swarm, peer_info, ping_samples, timeouts.dial)
+                        .await,
+                )
+            }
+        }
+    }
+}
+
+/// Peer to be looked up by [`PeerLookupClient`]
+#[derive(Debug, Clone)]
+pub enum LookupTarget {
+    /// Dial the peer on a known address
+    Direct(Multiaddr),
+    /// Find the peer by its ID on a Kademlia DHT
+    Dht {
+        peer_id: PeerId,
+        dht_network: DHTNetwork,
+    },
+}
+
+/// Explicit configuration of a [`PeerLookupClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct PeerLookupClientBuilder {
+    target: LookupTarget,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    trace_query: bool,
+    probe_all_addresses: bool,
+    ping_samples: usize,
+    probe_chain: bool,
+    fetch_block: Option<BlockId>,
+    verify_protocols: bool,
+    extra_protocols: Vec<String>,
+}
+
+impl PeerLookupClientBuilder {
+    pub fn new(target: LookupTarget) -> Self {
+        Self {
+            target,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+            trace_query: false,
+            probe_all_addresses: false,
+            ping_samples: 0,
+            probe_chain: false,
+            fetch_block: None,
+            verify_protocols: false,
+            extra_protocols: Vec::new(),
+        }
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    /// Identity used by the local peer, a fresh Ed25519 keypair is generated if unset
+    pub fn local_key(mut self, local_key: Keypair) -> Self {
+        self.node_config.local_key = Some(local_key);
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    /// Records every hop of the DHT query, ignored when dialing the peer directly
+    pub fn trace_query(mut self, trace_query: bool) -> Self {
+        self.trace_query = trace_query;
+
+        self
+    }
+
+    /// Dials every address of the identified peer on a fresh connection, ignored when dialing the
+    /// peer directly
+    pub fn probe_all_addresses(mut self, probe_all_addresses: bool) -> Self {
+        self.probe_all_addresses = probe_all_addresses;
+
+        self
+    }
+
+    /// Number of pings sent to the identified peer to sample its round-trip time, none if `0`
+    pub fn ping_samples(mut self, ping_samples: usize) -> Self {
+        self.ping_samples = ping_samples;
+
+        self
+    }
+
+    /// Performs the Substrate block announces handshake with the identified peer to read its role,
+    /// best block and genesis hash, the latter being checked against the DHT network's one
+    pub fn probe_chain(mut self, probe_chain: bool) -> Self {
+        self.probe_chain = probe_chain;
+
+        self
+    }
+
+    /// Requests a single block (header, body and justification) from the identified peer through
+    /// the Substrate block request protocol, timing its response
+    pub fn fetch_block(mut self, fetch_block: Option<BlockId>) -> Self {
+        self.fetch_block = fetch_block;
+
+        self
+    }
+
+    /// Proposes every protocol the identified peer advertised on a fresh substream, reporting which
+    /// ones it really accepts
+    pub fn verify_protocols(mut self, verify_protocols: bool) -> Self {
+        self.verify_protocols = verify_protocols;
+
+        self
+    }
+
+    /// Protocols proposed to the identified peer on top of the advertised ones, enabling the
+    /// verification of protocols if not empty
+    pub fn extra_protocols(mut self, extra_protocols: Vec<String>) -> Self {
+        self.extra_protocols = extra_protocols;
+
+        self
+    }
+
+    pub fn build(mut self) -> Result<PeerLookupClient, PeerLookupError> {
+        let verify_protocols = (self.verify_protocols || !self.extra_protocols.is_empty())
+            .then_some(self.extra_protocols);
+
+        if self.ping_samples > 0 {
+            self.node_config.ping_interval = Some(PING_SAMPLING_INTERVAL);
+        }
+
+        match self.target {
+            LookupTarget::Direct(destination) => Ok(PeerLookupClient::Direct {
+                swarm: create_swarm(&self.node_config, None, self.timeouts.dht_query)?,
+                destination,
+                timeouts: self.timeouts,
+                ping_samples: self.ping_samples,
+                probe_chain: self.probe_chain,
+                fetch_block: self.fetch_block,
+                verify_protocols,
+            }),
+            LookupTarget::Dht {
+                peer_id,
+                dht_network,
+            } => Ok(PeerLookupClient::Dht {
+                swarm: create_swarm(
+                    &self.node_config,
+                    Some(&dht_network),
+                    self.timeouts.dht_query,
+                )?,
+                peer_id,
+                timeouts: self.timeouts,
+                trace_query: self.trace_query,
+                probe_all_addresses: self.probe_all_addresses,
+                ping_samples: self.ping_samples,
+                probe_chain: self.probe_chain,
+                fetch_block: self.fetch_block,
+                verify_protocols,
+                expected_genesis_hash: dht_network.genesis_hash(),
+            }),
         }
     }
 }
@@ -0,0 +1,223 @@
+use crate::logging::log_debug;
+use crate::node::{PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use futures::StreamExt;
+use libp2p::ping::{Event as PingEvent, Failure as PingFailure, Success as PingSuccess};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use tokio::time::{timeout, Duration};
+
+/// Interval of the pings sent while sampling round-trip times, libp2p's default being 15 seconds
+pub(crate) const PING_SAMPLING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Round-trip times of the pings sent to a peer
+#[derive(Debug, Clone, Default)]
+pub struct PingStatistics {
+    /// Round-trip times in the order the pings were sent
+    pub rtts: Vec<Duration>,
+    pub failures: usize,
+}
+
+impl PingStatistics {
+    pub fn min(&self) -> Option<Duration> {
+        self.rtts.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.rtts.iter().max().copied()
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        (!self.rtts.is_empty()).then(|| self.rtts.iter().sum::<Duration>() / self.rtts.len() as u32)
+    }
+
+    /// Mean absolute difference between consecutive round-trip times, zero with a single one,
+    /// `None` without any
+    pub fn jitter(&self) -> Option<Duration> {
+        (!self.rtts.is_empty()).then(|| {
+            self.rtts
+                .windows(2)
+                .map(|pair| pair[0].max(pair[1]) - pair[0].min(pair[1]))
+                .sum::<Duration>()
+                / (self.rtts.len() - 1).max(1) as u32
+        })
+    }
+
+    /// Records the outcome of a ping sent to the peer, ignoring pongs answered to the peer
+    pub(crate) fn record(&mut self, event: &PingEvent) {
+        match &event.result {
+            Ok(PingSuccess::Ping { rtt }) => self.rtts.push(*rtt),
+            Ok(PingSuccess::Pong) => {}
+            Err(_) => self.failures += 1,
+        }
+    }
+
+    fn sample_count(&self) -> usize {
+        self.rtts.len() + self.failures
+    }
+}
+
+impl Display for PingStatistics {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        write!(
+            formatter,
+            "{} sample(s), {} failure(s)",
+            self.rtts.len(),
+            self.failures
+        )?;
+
+        if let (Some(min), Some(average), Some(max)) = (self.min(), self.average(), self.max()) {
+            write!(formatter, ", RTT min/avg/max {min:?}/{average:?}/{max:?}")?;
+        }
+
+        if let Some(jitter) = self.jitter() {
+            write!(formatter, ", jitter {jitter:?}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn as_millis_f64(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Serialize for PingStatistics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("PingStatistics", 6)?;
+        state.serialize_field("count", &self.rtts.len())?;
+        state.serialize_field("failures", &self.failures)?;
+        state.serialize_field("min_rtt_ms", &self.min().map(as_millis_f64))?;
+        state.serialize_field("avg_rtt_ms", &self.average().map(as_millis_f64))?;
+        state.serialize_field("max_rtt_ms", &self.max().map(as_millis_f64))?;
+        state.serialize_field("jitter_ms", &self.jitter().map(as_millis_f64))?;
+
+        state.end()
+    }
+}
+
+/// Waits for `samples` pings (successful or not) to be sent to a connected peer, stopping early
+/// if the connection closes, the peer doesn't support ping or the deadline elapses, keeping the
+/// statistics sampled so far
+pub(crate) async fn sample_pings(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    samples: usize,
+    deadline: Duration,
+) -> PingStatistics {
+    let mut statistics = PingStatistics::default();
+    let sampling = async {
+        while statistics.sample_count() < samples {
+            match swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Ping(event))
+                    if event.peer == peer_id =>
+                {
+                    statistics.record(&event);
+
+                    if let Err(PingFailure::Unsupported) = event.result {
+                        log_debug!("\"{peer_id}\" doesn't support ping");
+
+                        break;
+                    }
+                }
+                SwarmEvent::ConnectionClosed {
+                    peer_id: closed_peer_id,
+                    num_established: 0,
+                    ..
+                } if closed_peer_id == peer_id => {
+                    log_debug!("Connection to \"{peer_id}\" closed while sampling pings");
+
+                    break;
+                }
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    };
+
+    if timeout(deadline, sampling).await.is_err() {
+        log_debug!("Sampling pings of \"{peer_id}\" timed out after {deadline:?}");
+    }
+
+    statistics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ping_statistics(rtts_ms: &[u64], failures: usize) -> PingStatistics {
+        PingStatistics {
+            rtts: rtts_ms.iter().copied().map(Duration::from_millis).collect(),
+            failures,
+        }
+    }
+
+    #[test]
+    fn summarizes_no_samples() {
+        let statistics = ping_statistics(&[], 2);
+
+        assert_eq!(statistics.min(), None);
+        assert_eq!(statistics.average(), None);
+        assert_eq!(statistics.max(), None);
+        assert_eq!(statistics.jitter(), None);
+        assert_eq!(statistics.to_string(), "0 sample(s), 2 failure(s)");
+        assert_eq!(
+            serde_json::to_value(&statistics).unwrap(),
+            json!({
+                "count": 0,
+                "failures": 2,
+                "min_rtt_ms": null,
+                "avg_rtt_ms": null,
+                "max_rtt_ms": null,
+                "jitter_ms": null,
+            })
+        );
+    }
+
+    #[test]
+    fn summarizes_a_single_sample() {
+        let statistics = ping_statistics(&[42], 0);
+
+        assert_eq!(statistics.min(), Some(Duration::from_millis(42)));
+        assert_eq!(statistics.average(), Some(Duration::from_millis(42)));
+        assert_eq!(statistics.max(), Some(Duration::from_millis(42)));
+        assert_eq!(statistics.jitter(), Some(Duration::ZERO));
+        assert_eq!(
+            statistics.to_string(),
+            "1 sample(s), 0 failure(s), RTT min/avg/max 42ms/42ms/42ms, jitter 0ns"
+        );
+    }
+
+    #[test]
+    fn summarizes_a_series() {
+        // Consecutive differences of 20, 10 and 0 ms, whatever their sign
+        let statistics = ping_statistics(&[10, 30, 20, 20], 1);
+
+        assert_eq!(statistics.min(), Some(Duration::from_millis(10)));
+        assert_eq!(statistics.average(), Some(Duration::from_millis(20)));
+        assert_eq!(statistics.max(), Some(Duration::from_millis(30)));
+        assert_eq!(statistics.jitter(), Some(Duration::from_millis(10)));
+        assert_eq!(
+            serde_json::to_value(&statistics).unwrap(),
+            json!({
+                "count": 4,
+                "failures": 1,
+                "min_rtt_ms": 10.0,
+                "avg_rtt_ms": 20.0,
+                "max_rtt_ms": 30.0,
+                "jitter_ms": 10.0,
+            })
+        );
+        // Not the standard deviation, which would be about 7.07 ms
+        assert_eq!(
+            ping_statistics(&[10, 30, 10, 30], 0).jitter(),
+            Some(Duration::from_millis(20))
+        );
+    }
+}
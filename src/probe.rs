@@ -0,0 +1,204 @@
+use crate::logging::{log_debug, log_info};
+use crate::node::PeerLookupBehaviour;
+use futures::StreamExt;
+use libp2p::core::transport::TransportError;
+use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::{DialError as SwarmDialError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+use tokio::time::{timeout, Duration};
+
+#[derive(Debug, Error)]
+pub enum AddressProbeError {
+    #[error("No enabled transport supports the address")]
+    UnsupportedAddress,
+    #[error("Reached peer \"{0}\" instead")]
+    WrongPeerId(PeerId),
+    #[error("Transport error: {0}")]
+    Transport(String),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Dial error: {0}")]
+    Dial(String),
+}
+
+impl AddressProbeError {
+    /// Short machine-readable name of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::UnsupportedAddress => "unsupported_address",
+            Self::WrongPeerId(_) => "wrong_peer_id",
+            Self::Transport(_) => "transport",
+            Self::Timeout(_) => "timeout",
+            Self::Dial(_) => "dial",
+        }
+    }
+}
+
+impl From<SwarmDialError> for AddressProbeError {
+    fn from(error: SwarmDialError) -> Self {
+        match error {
+            SwarmDialError::Transport(errors) => match errors.into_iter().next() {
+                Some((_, TransportError::MultiaddrNotSupported(_))) => Self::UnsupportedAddress,
+                Some((_, TransportError::Other(error))) => Self::Transport(error.to_string()),
+                None => Self::Dial("no address dialed".to_owned()),
+            },
+            SwarmDialError::WrongPeerId { obtained, .. } => Self::WrongPeerId(obtained),
+            other_error => Self::Dial(other_error.to_string()),
+        }
+    }
+}
+
+/// Outcome of dialing a single address of a peer on a fresh connection
+pub struct AddressProbe {
+    pub address: Multiaddr,
+    /// Whether the peer listed the address in its identify message
+    pub announced: bool,
+    /// Whether the DHT knows the peer by the address
+    pub in_dht: bool,
+    /// Connection establishment duration (handshake included), or why it failed
+    pub outcome: Result<Duration, AddressProbeError>,
+}
+
+impl AddressProbe {
+    pub(crate) fn sources(&self) -> &'static str {
+        match (self.announced, self.in_dht) {
+            (true, true) => "identify, dht",
+            (true, false) => "identify",
+            (false, _) => "dht",
+        }
+    }
+}
+
+impl Serialize for AddressProbe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AddressProbe", 6)?;
+        state.serialize_field("address", &self.address.to_string())?;
+        state.serialize_field("announced", &self.announced)?;
+        state.serialize_field("in_dht", &self.in_dht)?;
+
+        let (established_in_ms, error) = match &self.outcome {
+            Ok(established_in) => (Some(established_in.as_secs_f64() * 1000.0), None),
+            Err(error) => (None, Some(SerializableProbeError(error))),
+        };
+        state.serialize_field("reachable", &self.outcome.is_ok())?;
+        state.serialize_field("established_in_ms", &established_in_ms)?;
+        state.serialize_field("error", &error)?;
+
+        state.end()
+    }
+}
+
+struct SerializableProbeError<'a>(&'a AddressProbeError);
+
+impl Serialize for SerializableProbeError<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AddressProbeError", 2)?;
+        state.serialize_field("kind", self.0.kind())?;
+        state.serialize_field("message", &self.0.to_string())?;
+
+        state.end()
+    }
+}
+
+/// Address without its trailing `/p2p/<peer ID>`, if any
+fn without_peer_id(address: &Multiaddr) -> Multiaddr {
+    let mut address = address.clone();
+
+    if let Some(MultiaddrProtocol::P2p(_)) = address.iter().last() {
+        address.pop();
+    }
+
+    address
+}
+
+async fn wait_for_connection(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    target_peer_id: PeerId,
+    address: &Multiaddr,
+) -> Result<Duration, AddressProbeError> {
+    loop {
+        match swarm
+            .next()
+            .await
+            .expect("Programmatic error: infinite streams!")
+        {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint:
+                    ConnectedPoint::Dialer {
+                        address: dialed_address,
+                        ..
+                    },
+                established_in,
+                ..
+            } if peer_id == target_peer_id && without_peer_id(&dialed_address) == *address => {
+                return Ok(established_in);
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+            } if peer_id == target_peer_id => {
+                // Failures of dials which timed out before are told apart by their address
+                let is_other_dial = match &error {
+                    SwarmDialError::Transport(errors) => errors
+                        .iter()
+                        .all(|(failed_address, _)| without_peer_id(failed_address) != *address),
+                    SwarmDialError::WrongPeerId { endpoint, .. } => {
+                        without_peer_id(endpoint.get_remote_address()) != *address
+                    }
+                    _ => false,
+                };
+
+                if !is_other_dial {
+                    return Err(error.into());
+                }
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+    }
+}
+
+/// Dials every address of an already identified peer one after the other, each on a fresh
+/// connection, addresses being given with whether they were announced and/or found in the DHT
+pub(crate) async fn probe_addresses(
+    swarm: &mut Swarm<PeerLookupBehaviour>,
+    peer_id: PeerId,
+    addresses: Vec<(Multiaddr, bool, bool)>,
+    dial_timeout: Duration,
+) -> Vec<AddressProbe> {
+    let mut probes = Vec::new();
+
+    for (address, announced, in_dht) in addresses {
+        let address = without_peer_id(&address);
+        let dial_opts = DialOpts::peer_id(peer_id)
+            .addresses(vec![address.clone()])
+            .condition(PeerCondition::Always)
+            .build();
+        let outcome = match swarm.dial(dial_opts) {
+            Ok(()) => timeout(dial_timeout, wait_for_connection(swarm, peer_id, &address))
+                .await
+                .unwrap_or(Err(AddressProbeError::Timeout(dial_timeout))),
+            Err(error) => Err(error.into()),
+        };
+
+        match &outcome {
+            Ok(established_in) => {
+                log_info!("Address \"{address}\" reachable in {established_in:?}")
+            }
+            Err(error) => log_info!("Address \"{address}\" unreachable: {error}"),
+        }
+
+        probes.push(AddressProbe {
+            address,
+            announced,
+            in_dht,
+            outcome,
+        });
+    }
+
+    probes
+}
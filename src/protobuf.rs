@@ -0,0 +1,120 @@
+//! Minimal protobuf wire format helpers, for the few messages hand-encoded by the inspector
+
+/// Value of a single protobuf field
+pub(crate) enum FieldValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+pub(crate) fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+pub(crate) fn put_varint_field(out: &mut Vec<u8>, field: u32, value: u64) {
+    put_varint(out, u64::from(field) << 3);
+    put_varint(out, value);
+}
+
+pub(crate) fn put_bytes_field(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    put_varint(out, (u64::from(field) << 3) | 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub(crate) fn take_varint(input: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| "Truncated varint".to_owned())?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err("Varint overflow".to_owned())
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+    if input.len() < length {
+        return Err(format!(
+            "Expected {length} bytes of field data, got {}",
+            input.len()
+        ));
+    }
+
+    let (bytes, rest) = input.split_at(length);
+    *input = rest;
+
+    Ok(bytes)
+}
+
+/// Iterates over the fields of an encoded message, skipping fixed size ones
+pub(crate) struct FieldReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> FieldReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input }
+    }
+
+    fn read_field(&mut self) -> Result<Option<(u32, FieldValue<'a>)>, String> {
+        loop {
+            if self.input.is_empty() {
+                return Ok(None);
+            }
+
+            let key = take_varint(&mut self.input)?;
+            let field = (key >> 3) as u32;
+
+            match key & 0x07 {
+                0 => {
+                    return Ok(Some((
+                        field,
+                        FieldValue::Varint(take_varint(&mut self.input)?),
+                    )))
+                }
+                1 => {
+                    take_bytes(&mut self.input, 8)?;
+                }
+                2 => {
+                    let length = take_varint(&mut self.input)? as usize;
+
+                    return Ok(Some((
+                        field,
+                        FieldValue::Bytes(take_bytes(&mut self.input, length)?),
+                    )));
+                }
+                5 => {
+                    take_bytes(&mut self.input, 4)?;
+                }
+                wire_type => return Err(format!("Unsupported wire type {wire_type}")),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for FieldReader<'a> {
+    type Item = Result<(u32, FieldValue<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_field() {
+            Ok(field) => field.map(Ok),
+            Err(error) => {
+                self.input = &[];
+
+                Some(Err(error))
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::chain::{hex_hash, protocol_genesis_hash, BlockHash};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+
+/// Notification protocol families of Substrate, opened once and kept for streaming messages
+const NOTIFICATION_FAMILIES: &[&str] = &[
+    "block-announces",
+    "transactions",
+    "grandpa",
+    "beefy",
+    "statement",
+];
+/// Request-response protocol families of Substrate
+const REQUEST_RESPONSE_FAMILIES: &[&str] = &[
+    "sync",
+    "sync/warp",
+    "state",
+    "light",
+    "beefy/justifications",
+];
+const KAD_FAMILY: &str = "kad";
+/// Prefix of the legacy protocol names of GRANDPA and BEEFY, which aren't bound to a chain
+const PARITYTECH_PREFIX: &str = "paritytech";
+/// First segments of libp2p's own protocols, the Kademlia one aside
+const LIBP2P_PREFIXES: &[&str] = &[
+    "ipfs",
+    "libp2p",
+    "meshsub",
+    "floodsub",
+    "noise",
+    "yamux",
+    "mplex",
+    "multistream",
+];
+
+/// Kind of a protocol, as grouped in reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolCategory {
+    Notifications,
+    RequestResponse,
+    Kad,
+    Libp2pCore,
+    Other,
+}
+
+impl ProtocolCategory {
+    /// Every category, in report order
+    pub const ALL: [Self; 5] = [
+        Self::Notifications,
+        Self::RequestResponse,
+        Self::Kad,
+        Self::Libp2pCore,
+        Self::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Notifications => "notifications",
+            Self::RequestResponse => "request-response",
+            Self::Kad => "kad",
+            Self::Libp2pCore => "libp2p-core",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl Display for ProtocolCategory {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        formatter.write_str(self.as_str())
+    }
+}
+
+/// Chain a Substrate protocol name is bound to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolChain {
+    /// `/<genesis hash>/...` name
+    Genesis(BlockHash),
+    /// Legacy `/<chain ID>/...` name, like `/sup/kad`
+    Legacy(String),
+}
+
+impl Display for ProtocolChain {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Genesis(genesis_hash) => {
+                let hex_genesis_hash = hex::encode(genesis_hash);
+
+                write!(
+                    formatter,
+                    "genesis 0x{}…{}",
+                    &hex_genesis_hash[..8],
+                    &hex_genesis_hash[hex_genesis_hash.len() - 4..]
+                )
+            }
+            Self::Legacy(chain_id) => write!(formatter, "legacy chain \"{chain_id}\""),
+        }
+    }
+}
+
+/// Protocol name split into its chain, family and version
+#[derive(Debug, Clone)]
+pub struct ParsedProtocol {
+    pub name: String,
+    pub category: ProtocolCategory,
+    /// Chain of Substrate protocols, `None` for chain-agnostic ones
+    pub chain: Option<ProtocolChain>,
+    /// Protocol name without its chain nor version, like `block-announces` or `ipfs/id`
+    pub family: String,
+    pub version: Option<String>,
+}
+
+/// Category of a Substrate protocol family, `None` if unknown
+fn substrate_category(family: &str) -> Option<ProtocolCategory> {
+    if family == KAD_FAMILY {
+        Some(ProtocolCategory::Kad)
+    } else if NOTIFICATION_FAMILIES.contains(&family) {
+        Some(ProtocolCategory::Notifications)
+    } else if REQUEST_RESPONSE_FAMILIES.contains(&family) {
+        Some(ProtocolCategory::RequestResponse)
+    } else {
+        None
+    }
+}
+
+impl ParsedProtocol {
+    /// Parses `/<genesis hash>/<family>/<version>` names, their legacy `/<chain ID>/...` and
+    /// `/paritytech/...` forms, and libp2p's own names, the version being an optional last
+    /// segment starting with a digit
+    pub fn parse(name: &str) -> Self {
+        let mut segments = name.trim_start_matches('/').split('/').collect::<Vec<_>>();
+        let has_version = segments.len() > 1
+            && segments[segments.len() - 1]
+                .starts_with(|character: char| character.is_ascii_digit());
+        let version = if has_version {
+            segments.pop().map(ToOwned::to_owned)
+        } else {
+            None
+        };
+        let first_segment = segments[0];
+        let chain_family = segments[1..].join("/");
+        let (chain, family, category) = if let Some(genesis_hash) = protocol_genesis_hash(name) {
+            let category = substrate_category(&chain_family);
+
+            (
+                Some(ProtocolChain::Genesis(genesis_hash)),
+                chain_family,
+                category,
+            )
+        } else if LIBP2P_PREFIXES.contains(&first_segment) {
+            let family = segments.join("/");
+            let category = if family == "ipfs/kad" {
+                ProtocolCategory::Kad
+            } else {
+                ProtocolCategory::Libp2pCore
+            };
+
+            (None, family, Some(category))
+        } else if first_segment == PARITYTECH_PREFIX {
+            let category = substrate_category(&chain_family);
+
+            (None, chain_family, category)
+        } else if let Some(category) = substrate_category(&chain_family) {
+            let chain = ProtocolChain::Legacy(first_segment.to_owned());
+
+            (Some(chain), chain_family, Some(category))
+        } else {
+            (None, segments.join("/"), None)
+        };
+
+        Self {
+            name: name.to_owned(),
+            category: category.unwrap_or(ProtocolCategory::Other),
+            chain,
+            family,
+            version,
+        }
+    }
+}
+
+impl Display for ParsedProtocol {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        write!(formatter, "\"{}\"", self.name)?;
+
+        if let Some(chain) = &self.chain {
+            write!(formatter, " ({}", self.family)?;
+
+            if let Some(version) = &self.version {
+                write!(formatter, " v{version}")?;
+            }
+
+            write!(formatter, ", {chain})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Serialize for ParsedProtocol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (genesis_hash, legacy_chain_id) = match &self.chain {
+            Some(ProtocolChain::Genesis(genesis_hash)) => (Some(hex_hash(genesis_hash)), None),
+            Some(ProtocolChain::Legacy(chain_id)) => (None, Some(chain_id)),
+            None => (None, None),
+        };
+        let mut state = serializer.serialize_struct("ParsedProtocol", 6)?;
+        state.serialize_field("protocol", &self.name)?;
+        state.serialize_field("category", self.category.as_str())?;
+        state.serialize_field("genesis_hash", &genesis_hash)?;
+        state.serialize_field("legacy_chain_id", &legacy_chain_id)?;
+        state.serialize_field("family", &self.family)?;
+        state.serialize_field("version", &self.version)?;
+
+        state.end()
+    }
+}
+
+/// Distinct genesis hashes the protocols are bound to, in order of appearance
+pub fn served_genesis_hashes(protocols: &[ParsedProtocol]) -> Vec<BlockHash> {
+    let mut genesis_hashes = Vec::new();
+
+    for protocol in protocols {
+        if let Some(ProtocolChain::Genesis(genesis_hash)) = &protocol.chain {
+            if !genesis_hashes.contains(genesis_hash) {
+                genesis_hashes.push(*genesis_hash);
+            }
+        }
+    }
+
+    genesis_hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HASH: &str = "c2f2d8da3e2d6b63970b6de8e365b84393ee0c69a9aec3a0bc750029a3552d07";
+
+    fn genesis_chain() -> Option<ProtocolChain> {
+        Some(ProtocolChain::Genesis(
+            hex::decode(GENESIS_HASH).unwrap().try_into().unwrap(),
+        ))
+    }
+
+    fn legacy_chain(chain_id: &str) -> Option<ProtocolChain> {
+        Some(ProtocolChain::Legacy(chain_id.to_owned()))
+    }
+
+    fn assert_parsed(
+        name: &str,
+        category: ProtocolCategory,
+        chain: Option<ProtocolChain>,
+        family: &str,
+        version: Option<&str>,
+    ) {
+        let protocol = ParsedProtocol::parse(name);
+
+        assert_eq!(protocol.name, name);
+        assert_eq!(protocol.category, category, "{name}");
+        assert_eq!(protocol.chain, chain, "{name}");
+        assert_eq!(protocol.family, family, "{name}");
+        assert_eq!(protocol.version.as_deref(), version, "{name}");
+    }
+
+    #[test]
+    fn parses_substrate_protocols() {
+        for (family, version, category) in [
+            (
+                "block-announces",
+                Some("1"),
+                ProtocolCategory::Notifications,
+            ),
+            ("transactions", Some("1"), ProtocolCategory::Notifications),
+            ("grandpa", Some("1"), ProtocolCategory::Notifications),
+            ("state", Some("2"), ProtocolCategory::RequestResponse),
+            ("sync/warp", None, ProtocolCategory::RequestResponse),
+            ("sync", Some("2"), ProtocolCategory::RequestResponse),
+            ("light", Some("2"), ProtocolCategory::RequestResponse),
+            ("kad", None, ProtocolCategory::Kad),
+        ] {
+            let suffix = match version {
+                Some(version) => format!("{family}/{version}"),
+                None => family.to_owned(),
+            };
+
+            assert_parsed(
+                &format!("/{GENESIS_HASH}/{suffix}"),
+                category,
+                genesis_chain(),
+                family,
+                version,
+            );
+
+            assert_parsed(
+                &format!("/sup/{suffix}"),
+                category,
+                legacy_chain("sup"),
+                family,
+                version,
+            );
+        }
+
+        assert_parsed(
+            "/paritytech/grandpa/1",
+            ProtocolCategory::Notifications,
+            None,
+            "grandpa",
+            Some("1"),
+        );
+    }
+
+    #[test]
+    fn parses_libp2p_protocols() {
+        assert_parsed(
+            "/ipfs/ping/1.0.0",
+            ProtocolCategory::Libp2pCore,
+            None,
+            "ipfs/ping",
+            Some("1.0.0"),
+        );
+        assert_parsed(
+            "/ipfs/id/1.0.0",
+            ProtocolCategory::Libp2pCore,
+            None,
+            "ipfs/id",
+            Some("1.0.0"),
+        );
+        assert_parsed(
+            "/ipfs/id/push/1.0.0",
+            ProtocolCategory::Libp2pCore,
+            None,
+            "ipfs/id/push",
+            Some("1.0.0"),
+        );
+        assert_parsed(
+            "/ipfs/kad/1.0.0",
+            ProtocolCategory::Kad,
+            None,
+            "ipfs/kad",
+            Some("1.0.0"),
+        );
+        assert_parsed(
+            "/meshsub/1.1.0",
+            ProtocolCategory::Libp2pCore,
+            None,
+            "meshsub",
+            Some("1.1.0"),
+        );
+    }
+
+    #[test]
+    fn parses_unknown_and_malformed_protocols() {
+        assert_parsed(
+            "/my-app/chat/1",
+            ProtocolCategory::Other,
+            None,
+            "my-app/chat",
+            Some("1"),
+        );
+        assert_parsed(
+            &format!("/{GENESIS_HASH}/my-app/1"),
+            ProtocolCategory::Other,
+            genesis_chain(),
+            "my-app",
+            Some("1"),
+        );
+        assert_parsed("/sup/1", ProtocolCategory::Other, None, "sup", Some("1"));
+        assert_parsed("", ProtocolCategory::Other, None, "", None);
+        assert_parsed("/", ProtocolCategory::Other, None, "", None);
+    }
+
+    #[test]
+    fn displays_protocols() {
+        assert_eq!(
+            ParsedProtocol::parse(&format!("/{GENESIS_HASH}/block-announces/1")).to_string(),
+            format!("\"/{GENESIS_HASH}/block-announces/1\" (block-announces v1, genesis 0xc2f2d8da…2d07)")
+        );
+        assert_eq!(
+            ParsedProtocol::parse("/sup/sync/warp").to_string(),
+            "\"/sup/sync/warp\" (sync/warp, legacy chain \"sup\")"
+        );
+        assert_eq!(
+            ParsedProtocol::parse("/ipfs/ping/1.0.0").to_string(),
+            "\"/ipfs/ping/1.0.0\""
+        );
+    }
+
+    #[test]
+    fn lists_served_genesis_hashes() {
+        let protocols = [
+            format!("/{GENESIS_HASH}/block-announces/1"),
+            "/sup/kad".to_owned(),
+            format!("/{GENESIS_HASH}/kad"),
+            format!("/{}/kad", "00".repeat(32)),
+        ]
+        .iter()
+        .map(|name| ParsedProtocol::parse(name))
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            served_genesis_hashes(&protocols)
+                .iter()
+                .map(hex::encode)
+                .collect::<Vec<_>>(),
+            vec![GENESIS_HASH.to_owned(), "00".repeat(32)]
+        );
+    }
+}
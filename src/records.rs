@@ -0,0 +1,621 @@
+use crate::logging::{log_debug, log_info};
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{LookupTimeouts, PeerLookupError};
+use crate::protobuf::take_varint;
+use futures::StreamExt;
+use libp2p::kad::{
+    GetProvidersError as KademliaGetProvidersError, GetProvidersOk as KademliaGetProvidersOk,
+    GetRecordError as KademliaGetRecordError, GetRecordOk as KademliaGetRecordOk, KademliaEvent,
+    PeerRecord as KademliaPeerRecord, QueryId as KademliaQueryId,
+    QueryResult as KademliaQueryResult, Record, RecordKey,
+};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use multibase::Base as MultibaseBase;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use thiserror::Error;
+use tokio::time::{timeout, Duration, Instant};
+
+/// CIDv1 version prefix
+const CID_VERSION_1: u64 = 1;
+
+/// Parses a DHT key, either:
+/// - a CID, keyed by its multihash as IPFS does (e.g. "Qm..." or "bafy...")
+/// - an IPFS record of a peer, as `/ipns/<peer ID>` or `/pk/<peer ID>`
+/// - raw bytes, hex-encoded with a `0x` prefix
+pub fn parse_record_key(source: &str) -> Result<RecordKey, String> {
+    let source = source.trim();
+
+    if let Some(hex_key) = source.strip_prefix("0x") {
+        return hex::decode(hex_key)
+            .map(RecordKey::from)
+            .map_err(|error| format!("Bad hex key \"{source}\": {error}"));
+    }
+
+    for namespace in ["/ipns/", "/pk/"] {
+        if let Some(peer_id) = source.strip_prefix(namespace) {
+            let peer_id = peer_id
+                .parse::<PeerId>()
+                .map_err(|error| format!("Bad peer ID in key \"{source}\": {error}"))?;
+            let mut key = namespace.as_bytes().to_vec();
+            key.extend(peer_id.to_bytes());
+
+            return Ok(RecordKey::from(key));
+        }
+    }
+
+    parse_cid_multihash(source)
+        .map(RecordKey::from)
+        .map_err(|error| format!("Bad key \"{source}\": {error}"))
+}
+
+/// Multihash of a CID, CIDv0 being a bare base58 SHA-256 multihash
+fn parse_cid_multihash(source: &str) -> Result<Vec<u8>, String> {
+    if source.len() == 46 && source.starts_with("Qm") {
+        return MultibaseBase::Base58Btc
+            .decode(source)
+            .map_err(|error| error.to_string());
+    }
+
+    let (_, cid) = multibase::decode(source).map_err(|error| {
+        format!("not a CID, \"/ipns/<peer ID>\", \"/pk/<peer ID>\" or 0x-prefixed hex ({error})")
+    })?;
+    let mut cid = cid.as_slice();
+    let version = take_varint(&mut cid)?;
+
+    if version != CID_VERSION_1 {
+        return Err(format!("unsupported CID version {version}"));
+    }
+
+    // Content type codec, irrelevant to the DHT key
+    take_varint(&mut cid)?;
+
+    if cid.is_empty() {
+        return Err("CID without multihash".to_owned());
+    }
+
+    Ok(cid.to_vec())
+}
+
+/// Kind of DHT query run by a [`RecordQueryClient`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordQuery {
+    /// Peers providing the content of the key (`GET_PROVIDERS`)
+    Providers,
+    /// Value records stored under the key (`GET_VALUE`)
+    Record,
+}
+
+impl RecordQuery {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Providers => "providers",
+            Self::Record => "record",
+        }
+    }
+}
+
+/// Why a provider or value record query ended before converging
+#[derive(Debug, Error)]
+pub enum RecordQueryError {
+    /// DHT query or whole lookup timed out
+    #[error(transparent)]
+    Timeout(PeerLookupError),
+    #[error(transparent)]
+    Record(#[from] KademliaGetRecordError),
+}
+
+impl RecordQueryError {
+    /// Process exit code reported when nothing was found because of this error, `None` when the
+    /// query converged without finding anything
+    pub fn exit_code(&self) -> Option<u8> {
+        match self {
+            Self::Timeout(error) => Some(error.exit_code()),
+            Self::Record(_) => None,
+        }
+    }
+}
+
+/// Provider of a key, as found on the DHT
+///
+/// The peers which returned the provider aren't known, kad's `GetProvidersOk` not naming them
+pub struct FoundProvider {
+    pub peer_id: PeerId,
+    /// Time from the start of the query to the first response naming this provider
+    pub found_in: Duration,
+}
+
+/// Value record of a key, as returned by one or more peers
+pub struct FoundRecord {
+    pub value: Vec<u8>,
+    /// Original publisher of the record, if the record names one
+    pub publisher: Option<PeerId>,
+    /// Time left until the record expires, none if it doesn't
+    pub expires_in: Option<Duration>,
+    /// Peers which returned this same record, empty if it came from the local store only
+    pub returned_by: Vec<PeerId>,
+}
+
+impl FoundRecord {
+    fn is_same(&self, record: &Record) -> bool {
+        self.value == record.value && self.publisher == record.publisher
+    }
+}
+
+/// Outcome of a provider or value record query
+pub struct RecordQueryReport {
+    pub query: RecordQuery,
+    pub key: RecordKey,
+    /// Providers found, sorted by discovery
+    pub providers: Vec<FoundProvider>,
+    /// Distinct records found, sorted by discovery
+    pub records: Vec<FoundRecord>,
+    /// Why the query ended before converging, if it did
+    pub error: Option<RecordQueryError>,
+    pub elapsed: Duration,
+}
+
+impl RecordQueryReport {
+    fn new(query: RecordQuery, key: RecordKey) -> Self {
+        Self {
+            query,
+            key,
+            providers: Vec::new(),
+            records: Vec::new(),
+            error: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Whether any provider or record was found
+    pub fn is_found(&self) -> bool {
+        !(self.providers.is_empty() && self.records.is_empty())
+    }
+
+    fn add_provider(&mut self, peer_id: PeerId, found_in: Duration) {
+        if self
+            .providers
+            .iter()
+            .all(|provider| provider.peer_id != peer_id)
+        {
+            log_info!("Found provider \"{peer_id}\" in {found_in:?}");
+            self.providers.push(FoundProvider { peer_id, found_in });
+        }
+    }
+
+    fn add_record(&mut self, peer_record: KademliaPeerRecord) {
+        let KademliaPeerRecord { peer, record } = peer_record;
+        let found_record = match self
+            .records
+            .iter()
+            .position(|found_record| found_record.is_same(&record))
+        {
+            Some(index) => &mut self.records[index],
+            None => {
+                self.records.push(FoundRecord {
+                    expires_in: record.expires.map(|expires| {
+                        expires.saturating_duration_since(std::time::Instant::now())
+                    }),
+                    value: record.value,
+                    publisher: record.publisher,
+                    returned_by: Vec::new(),
+                });
+
+                self.records
+                    .last_mut()
+                    .expect("Programmatic error: no record after pushing one!")
+            }
+        };
+
+        if let Some(peer_id) = peer {
+            log_info!("Record returned by \"{peer_id}\"");
+            found_record.returned_by.push(peer_id);
+        }
+    }
+}
+
+impl Display for RecordQueryReport {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        writeln!(
+            formatter,
+            "[Key]\n  \"0x{}\"",
+            hex::encode(self.key.as_ref())
+        )?;
+        writeln!(
+            formatter,
+            "[Query]\n  {} in {:?}",
+            self.query.as_str(),
+            self.elapsed
+        )?;
+
+        if let Some(error) = &self.error {
+            writeln!(formatter, "[Error]\n  {error}")?;
+        }
+
+        if !self.providers.is_empty() {
+            writeln!(formatter, "[Providers]")?;
+
+            for provider in &self.providers {
+                writeln!(
+                    formatter,
+                    "  - \"{}\" (found in {:?})",
+                    provider.peer_id, provider.found_in
+                )?;
+            }
+        }
+
+        for record in &self.records {
+            writeln!(formatter, "[Record]")?;
+            writeln!(formatter, "  - value: \"0x{}\"", hex::encode(&record.value))?;
+
+            if let Some(publisher) = &record.publisher {
+                writeln!(formatter, "  - published by \"{publisher}\"")?;
+            }
+
+            if let Some(expires_in) = &record.expires_in {
+                writeln!(formatter, "  - expires in {expires_in:?}")?;
+            }
+
+            for peer_id in &record.returned_by {
+                writeln!(formatter, "  - returned by \"{peer_id}\"")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct SerializableProvider<'a>(&'a FoundProvider);
+
+impl Serialize for SerializableProvider<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FoundProvider", 2)?;
+        state.serialize_field("peer_id", &self.0.peer_id.to_string())?;
+        state.serialize_field("found_in_ms", &(self.0.found_in.as_secs_f64() * 1000.0))?;
+
+        state.end()
+    }
+}
+
+struct SerializableRecord<'a>(&'a FoundRecord);
+
+impl Serialize for SerializableRecord<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let record = self.0;
+        let mut state = serializer.serialize_struct("FoundRecord", 4)?;
+        state.serialize_field("value", &hex::encode(&record.value))?;
+        state.serialize_field(
+            "publisher",
+            &record.publisher.map(|publisher| publisher.to_string()),
+        )?;
+        state.serialize_field(
+            "expires_in_s",
+            &record.expires_in.map(|expires_in| expires_in.as_secs()),
+        )?;
+        state.serialize_field(
+            "returned_by",
+            &record
+                .returned_by
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )?;
+
+        state.end()
+    }
+}
+
+impl Serialize for RecordQueryReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RecordQueryReport", 5)?;
+        state.serialize_field("query", self.query.as_str())?;
+        state.serialize_field("key", &hex::encode(self.key.as_ref()))?;
+        state.serialize_field("elapsed_ms", &(self.elapsed.as_secs_f64() * 1000.0))?;
+        state.serialize_field("error", &self.error.as_ref().map(ToString::to_string))?;
+
+        match self.query {
+            RecordQuery::Providers => state.serialize_field(
+                "providers",
+                &self
+                    .providers
+                    .iter()
+                    .map(SerializableProvider)
+                    .collect::<Vec<_>>(),
+            )?,
+            RecordQuery::Record => state.serialize_field(
+                "records",
+                &self
+                    .records
+                    .iter()
+                    .map(SerializableRecord)
+                    .collect::<Vec<_>>(),
+            )?,
+        }
+
+        state.end()
+    }
+}
+
+/// Runs a single provider or value record query on a DHT network
+pub struct RecordQueryClient {
+    swarm: Swarm<PeerLookupBehaviour>,
+    query: RecordQuery,
+    key: RecordKey,
+    timeouts: LookupTimeouts,
+}
+
+impl RecordQueryClient {
+    /// Starts configuring a query of the given key on the given DHT network
+    pub fn builder(
+        query: RecordQuery,
+        key: RecordKey,
+        dht_network: DHTNetwork,
+    ) -> RecordQueryClientBuilder {
+        RecordQueryClientBuilder::new(query, key, dht_network)
+    }
+
+    fn on_query_progressed(
+        report: &mut RecordQueryReport,
+        query_result: KademliaQueryResult,
+        started_at: Instant,
+        dht_query_timeout: Duration,
+    ) {
+        match query_result {
+            KademliaQueryResult::GetProviders(Ok(KademliaGetProvidersOk::FoundProviders {
+                providers,
+                ..
+            })) => {
+                for peer_id in providers {
+                    report.add_provider(peer_id, started_at.elapsed());
+                }
+            }
+            KademliaQueryResult::GetProviders(Err(KademliaGetProvidersError::Timeout {
+                ..
+            }))
+            | KademliaQueryResult::GetRecord(Err(KademliaGetRecordError::Timeout { .. })) => {
+                report.error = Some(RecordQueryError::Timeout(PeerLookupError::DhtQueryTimeout(
+                    dht_query_timeout,
+                )));
+            }
+            KademliaQueryResult::GetRecord(Ok(KademliaGetRecordOk::FoundRecord(peer_record))) => {
+                report.add_record(peer_record);
+            }
+            // Converging without any record isn't an error, nothing is stored under the key
+            KademliaQueryResult::GetRecord(Err(KademliaGetRecordError::NotFound { .. })) => {
+                log_debug!("No record found");
+            }
+            KademliaQueryResult::GetRecord(Err(error)) => {
+                report.error = Some(error.into());
+            }
+            _ => {}
+        }
+    }
+
+    async fn run_query(
+        swarm: &mut Swarm<PeerLookupBehaviour>,
+        query_id: KademliaQueryId,
+        report: &mut RecordQueryReport,
+        dht_query_timeout: Duration,
+    ) {
+        let started_at = Instant::now();
+
+        loop {
+            match swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                    KademliaEvent::OutboundQueryProgressed {
+                        id, result, step, ..
+                    },
+                )) if id == query_id => {
+                    Self::on_query_progressed(report, result, started_at, dht_query_timeout);
+
+                    if step.last {
+                        return;
+                    }
+                }
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    }
+
+    /// Runs the query until it converges, or until the total deadline
+    pub async fn do_query(mut self) -> RecordQueryReport {
+        let started_at = Instant::now();
+        let mut report = RecordQueryReport::new(self.query, self.key.clone());
+        let dht = &mut self.swarm.behaviour_mut().dht;
+        let query_id = match self.query {
+            RecordQuery::Providers => dht.get_providers(self.key),
+            RecordQuery::Record => dht.get_record(self.key),
+        };
+        let query = Self::run_query(
+            &mut self.swarm,
+            query_id,
+            &mut report,
+            self.timeouts.dht_query,
+        );
+
+        match self.timeouts.total {
+            Some(total_timeout) => {
+                if timeout(total_timeout, query).await.is_err() {
+                    report.error = Some(RecordQueryError::Timeout(PeerLookupError::LookupTimeout(
+                        total_timeout,
+                    )));
+                }
+            }
+            None => query.await,
+        }
+
+        report.elapsed = started_at.elapsed();
+
+        report
+    }
+}
+
+/// Explicit configuration of a [`RecordQueryClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct RecordQueryClientBuilder {
+    query: RecordQuery,
+    key: RecordKey,
+    dht_network: DHTNetwork,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+}
+
+impl RecordQueryClientBuilder {
+    pub fn new(query: RecordQuery, key: RecordKey, dht_network: DHTNetwork) -> Self {
+        Self {
+            query,
+            key,
+            dht_network,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+        }
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    pub fn build(self) -> Result<RecordQueryClient, PeerLookupError> {
+        Ok(RecordQueryClient {
+            swarm: create_swarm(
+                &self.node_config,
+                Some(&self.dht_network),
+                self.timeouts.dht_query,
+            )?,
+            query: self.query,
+            key: self.key,
+            timeouts: self.timeouts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER_ID: &str = "12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2";
+    /// Multihash of [`PEER_ID`], an identity multihash of its Ed25519 public key
+    const PEER_ID_BYTES: &str =
+        "0024080112201ce5f00ef6e89374afb625f1ae4c1546d31234e87e3c3f51a62b91dd6bfa57df";
+
+    fn parsed_key(source: &str) -> String {
+        hex::encode(parse_record_key(source).unwrap().as_ref())
+    }
+
+    #[test]
+    fn parses_cid_v0() {
+        assert_eq!(
+            parsed_key("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"),
+            "12209d6c2be50f706953479ab9df2ce3edca90b68053c00b3004b7f0accbe1e8eedf"
+        );
+    }
+
+    #[test]
+    fn parses_cid_v1() {
+        assert_eq!(
+            parsed_key("bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"),
+            "1220c3c4733ec8affd06cf9e9ff50ffc6bcd2ec85a6170004bb709669c31de94391a"
+        );
+        // Same CID, base16-encoded
+        assert_eq!(
+            parsed_key("f01701220c3c4733ec8affd06cf9e9ff50ffc6bcd2ec85a6170004bb709669c31de94391a"),
+            "1220c3c4733ec8affd06cf9e9ff50ffc6bcd2ec85a6170004bb709669c31de94391a"
+        );
+    }
+
+    #[test]
+    fn parses_peer_record_keys() {
+        assert_eq!(
+            parsed_key(&format!("/ipns/{PEER_ID}")),
+            format!("{}{PEER_ID_BYTES}", hex::encode("/ipns/"))
+        );
+        assert_eq!(
+            parsed_key(&format!("/pk/{PEER_ID}")),
+            format!("{}{PEER_ID_BYTES}", hex::encode("/pk/"))
+        );
+    }
+
+    #[test]
+    fn parses_hex_keys() {
+        assert_eq!(parsed_key("0xdeadbeef"), "deadbeef");
+        assert_eq!(parsed_key("  0xdeadbeef\n"), "deadbeef");
+        assert_eq!(parsed_key("0x"), "");
+    }
+
+    #[test]
+    fn rejects_bad_hex_keys() {
+        assert!(parse_record_key("0xdeadbee")
+            .unwrap_err()
+            .starts_with("Bad hex key \"0xdeadbee\""));
+        assert!(parse_record_key("0xzz").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_peer_record_keys() {
+        assert!(parse_record_key("/ipns/not-a-peer-id")
+            .unwrap_err()
+            .starts_with("Bad peer ID in key \"/ipns/not-a-peer-id\""));
+        assert!(parse_record_key("/pk/").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_cids() {
+        assert!(parse_record_key("!not-a-key")
+            .unwrap_err()
+            .starts_with("Bad key \"!not-a-key\": not a CID"));
+        assert_eq!(
+            parse_record_key("f0270").unwrap_err(),
+            "Bad key \"f0270\": unsupported CID version 2"
+        );
+        assert_eq!(
+            parse_record_key("f0170").unwrap_err(),
+            "Bad key \"f0170\": CID without multihash"
+        );
+        assert!(parse_record_key("f01").is_err());
+        assert!(parse_record_key("").is_err());
+    }
+
+    #[test]
+    fn reports_unfound_records_without_error() {
+        let key = RecordKey::new(&"/ipns/unpublished");
+        let mut report = RecordQueryReport::new(RecordQuery::Record, key.clone());
+
+        RecordQueryClient::on_query_progressed(
+            &mut report,
+            KademliaQueryResult::GetRecord(Err(KademliaGetRecordError::NotFound {
+                key: key.clone(),
+                closest_peers: vec![PEER_ID.parse().unwrap()],
+            })),
+            Instant::now(),
+            Duration::from_secs(60),
+        );
+        assert!(!report.is_found());
+        assert!(report.error.is_none());
+
+        RecordQueryClient::on_query_progressed(
+            &mut report,
+            KademliaQueryResult::GetRecord(Err(KademliaGetRecordError::Timeout { key })),
+            Instant::now(),
+            Duration::from_secs(60),
+        );
+        assert!(matches!(
+            report.error,
+            Some(RecordQueryError::Timeout(PeerLookupError::DhtQueryTimeout(timeout)))
+                if timeout == Duration::from_secs(60)
+        ));
+    }
+}
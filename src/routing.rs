@@ -0,0 +1,383 @@
+use crate::logging::{log_debug, log_info};
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{LookupTimeouts, PeerLookupError};
+use futures::StreamExt;
+use libp2p::kad::kbucket::NodeStatus as KBucketNodeStatus;
+use libp2p::kad::{
+    BootstrapError as KademliaBootstrapError, BootstrapOk as KademliaBootstrapOk, KademliaEvent,
+    QueryId as KademliaQueryId, QueryResult as KademliaQueryResult,
+};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use tokio::time::{timeout, Duration, Instant};
+
+/// Peer of a k-bucket
+pub struct RoutingTableEntry {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    /// Whether Kademlia considers the peer connected
+    pub connected: bool,
+    /// Time since the peer was last connected, none if it never was during the bootstrap
+    pub last_seen: Option<Duration>,
+    /// Whether the peer is one of the network's bootnodes
+    pub bootnode: bool,
+}
+
+/// Non-empty k-bucket, holding the peers at a XOR distance in [2^index, 2^(index + 1))
+pub struct RoutingTableBucket {
+    pub index: u32,
+    pub entries: Vec<RoutingTableEntry>,
+}
+
+/// Routing table of the local node once bootstrapped
+pub struct RoutingTableReport {
+    pub network: String,
+    pub local_peer_id: PeerId,
+    /// Non-empty k-buckets, farthest first
+    pub buckets: Vec<RoutingTableBucket>,
+    /// Bootnodes missing from the routing table, Kademlia drops peers it fails to dial
+    pub missing_bootnodes: Vec<PeerId>,
+    /// Why the bootstrap didn't complete, if it didn't
+    pub error: Option<String>,
+    pub elapsed: Duration,
+}
+
+impl RoutingTableReport {
+    /// Number of peers in the routing table
+    pub fn peer_count(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.entries.len()).sum()
+    }
+
+    /// Number of peers in the routing table Kademlia considers connected
+    pub fn connected_count(&self) -> usize {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| &bucket.entries)
+            .filter(|entry| entry.connected)
+            .count()
+    }
+}
+
+impl Display for RoutingTableReport {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        writeln!(formatter, "[Local Peer ID]\n  \"{}\"", &self.local_peer_id)?;
+        writeln!(
+            formatter,
+            "[Bootstrap]\n  \"{}\" in {:?}, {} peers in {} buckets ({} connected)",
+            self.network,
+            self.elapsed,
+            self.peer_count(),
+            self.buckets.len(),
+            self.connected_count()
+        )?;
+
+        if let Some(error_message) = &self.error {
+            writeln!(formatter, "  - failed: {error_message}")?;
+        }
+
+        for bucket in &self.buckets {
+            writeln!(formatter, "[Bucket {}]", bucket.index)?;
+
+            for entry in &bucket.entries {
+                let status = if entry.connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                };
+                let last_seen = entry.last_seen.map_or_else(
+                    || "never seen".to_owned(),
+                    |last_seen| format!("last seen {last_seen:?} ago"),
+                );
+                let bootnode = if entry.bootnode { ", bootnode" } else { "" };
+                writeln!(
+                    formatter,
+                    "  - \"{}\" ({status}, {last_seen}{bootnode})",
+                    entry.peer_id
+                )?;
+
+                for address in &entry.addresses {
+                    writeln!(formatter, "    - \"{address}\"")?;
+                }
+            }
+        }
+
+        if !self.missing_bootnodes.is_empty() {
+            writeln!(formatter, "[Missing Bootnodes]")?;
+
+            for peer_id in &self.missing_bootnodes {
+                writeln!(formatter, "  - \"{peer_id}\"")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct SerializableEntry<'a>(&'a RoutingTableEntry);
+
+impl Serialize for SerializableEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entry = self.0;
+        let mut state = serializer.serialize_struct("RoutingTableEntry", 5)?;
+        state.serialize_field("peer_id", &entry.peer_id.to_string())?;
+        state.serialize_field(
+            "addresses",
+            &entry
+                .addresses
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("connected", &entry.connected)?;
+        state.serialize_field(
+            "last_seen_ms",
+            &entry
+                .last_seen
+                .map(|last_seen| last_seen.as_secs_f64() * 1000.0),
+        )?;
+        state.serialize_field("bootnode", &entry.bootnode)?;
+
+        state.end()
+    }
+}
+
+struct SerializableBucket<'a>(&'a RoutingTableBucket);
+
+impl Serialize for SerializableBucket<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RoutingTableBucket", 2)?;
+        state.serialize_field("index", &self.0.index)?;
+        state.serialize_field(
+            "entries",
+            &self
+                .0
+                .entries
+                .iter()
+                .map(SerializableEntry)
+                .collect::<Vec<_>>(),
+        )?;
+
+        state.end()
+    }
+}
+
+impl Serialize for RoutingTableReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("RoutingTableReport", 8)?;
+        state.serialize_field("network", &self.network)?;
+        state.serialize_field("local_peer_id", &self.local_peer_id.to_string())?;
+        state.serialize_field("elapsed_ms", &(self.elapsed.as_secs_f64() * 1000.0))?;
+        state.serialize_field("error", &self.error)?;
+        state.serialize_field("peers", &self.peer_count())?;
+        state.serialize_field("connected", &self.connected_count())?;
+        state.serialize_field(
+            "buckets",
+            &self
+                .buckets
+                .iter()
+                .map(SerializableBucket)
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field(
+            "missing_bootnodes",
+            &self
+                .missing_bootnodes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )?;
+
+        state.end()
+    }
+}
+
+/// Bootstraps the local node against a DHT network, then dumps its k-buckets
+pub struct RoutingTableClient {
+    network: String,
+    bootnodes: HashSet<PeerId>,
+    swarm: Swarm<PeerLookupBehaviour>,
+    /// Last time each peer was connected
+    last_seen: HashMap<PeerId, Instant>,
+    timeouts: LookupTimeouts,
+}
+
+impl RoutingTableClient {
+    /// Starts configuring a bootstrap against the given DHT network
+    pub fn builder(dht_network: DHTNetwork) -> RoutingTableClientBuilder {
+        RoutingTableClientBuilder::new(dht_network)
+    }
+
+    async fn bootstrap(&mut self, query_id: KademliaQueryId) -> Result<(), PeerLookupError> {
+        loop {
+            match self
+                .swarm
+                .next()
+                .await
+                .expect("Programmatic error: infinite streams!")
+            {
+                SwarmEvent::ConnectionEstablished { peer_id, .. }
+                | SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                    self.last_seen.insert(peer_id, Instant::now());
+                }
+                SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                    KademliaEvent::OutboundQueryProgressed {
+                        id,
+                        result: KademliaQueryResult::Bootstrap(bootstrap_result),
+                        step,
+                        ..
+                    },
+                )) if id == query_id => match bootstrap_result {
+                    Ok(KademliaBootstrapOk { num_remaining, .. }) => {
+                        log_info!(
+                            "Bootstrap step {} done, {num_remaining} buckets left to refresh",
+                            step.count
+                        );
+
+                        if step.last {
+                            return Ok(());
+                        }
+                    }
+                    Err(KademliaBootstrapError::Timeout { .. }) => {
+                        return Err(PeerLookupError::DhtQueryTimeout(self.timeouts.dht_query));
+                    }
+                },
+                other_event => log_debug!("{other_event:?}"),
+            }
+        }
+    }
+
+    fn into_report(mut self, error: Option<String>, elapsed: Duration) -> RoutingTableReport {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let connected_peers = self.swarm.connected_peers().copied().collect::<Vec<_>>();
+        let mut buckets = Vec::new();
+
+        for bucket in self.swarm.behaviour_mut().dht.kbuckets() {
+            let index = bucket
+                .range()
+                .0
+                .ilog2()
+                .expect("Programmatic error: bucket of the local key!");
+            let entries = bucket
+                .iter()
+                .map(|entry| {
+                    let peer_id = *entry.node.key.preimage();
+                    let last_seen = if connected_peers.contains(&peer_id) {
+                        Some(Duration::ZERO)
+                    } else {
+                        self.last_seen.get(&peer_id).map(Instant::elapsed)
+                    };
+
+                    RoutingTableEntry {
+                        peer_id,
+                        addresses: entry.node.value.iter().cloned().collect(),
+                        connected: matches!(entry.status, KBucketNodeStatus::Connected),
+                        last_seen,
+                        bootnode: self.bootnodes.contains(&peer_id),
+                    }
+                })
+                .collect();
+
+            buckets.push(RoutingTableBucket { index, entries });
+        }
+
+        buckets.sort_by_key(|bucket| std::cmp::Reverse(bucket.index));
+        let mut missing_bootnodes = self
+            .bootnodes
+            .iter()
+            .filter(|bootnode| {
+                !buckets
+                    .iter()
+                    .flat_map(|bucket| &bucket.entries)
+                    .any(|entry| &entry.peer_id == *bootnode)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        missing_bootnodes.sort_by_key(PeerId::to_base58);
+
+        RoutingTableReport {
+            network: self.network,
+            local_peer_id,
+            buckets,
+            missing_bootnodes,
+            error,
+            elapsed,
+        }
+    }
+
+    /// Bootstraps until every bucket is refreshed, or until the total deadline, then dumps the
+    /// routing table whatever the bootstrap outcome
+    pub async fn do_bootstrap(mut self) -> RoutingTableReport {
+        let started_at = Instant::now();
+        let query_id = match self.swarm.behaviour_mut().dht.bootstrap() {
+            Ok(query_id) => query_id,
+            Err(_) => {
+                return self.into_report(
+                    Some("No bootnode to bootstrap from".to_owned()),
+                    started_at.elapsed(),
+                )
+            }
+        };
+        let bootstrap_result = match self.timeouts.total {
+            Some(total_timeout) => timeout(total_timeout, self.bootstrap(query_id))
+                .await
+                .unwrap_or(Err(PeerLookupError::LookupTimeout(total_timeout))),
+            None => self.bootstrap(query_id).await,
+        };
+        let error = bootstrap_result.err().map(|error| error.to_string());
+
+        self.into_report(error, started_at.elapsed())
+    }
+}
+
+/// Explicit configuration of a [`RoutingTableClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct RoutingTableClientBuilder {
+    dht_network: DHTNetwork,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+}
+
+impl RoutingTableClientBuilder {
+    pub fn new(dht_network: DHTNetwork) -> Self {
+        Self {
+            dht_network,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+        }
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    pub fn build(self) -> Result<RoutingTableClient, PeerLookupError> {
+        Ok(RoutingTableClient {
+            network: self.dht_network.name().to_owned(),
+            bootnodes: self
+                .dht_network
+                .bootnodes()
+                .iter()
+                .map(|(_, peer_id)| *peer_id)
+                .collect(),
+            swarm: create_swarm(
+                &self.node_config,
+                Some(&self.dht_network),
+                self.timeouts.dht_query,
+            )?,
+            last_seen: HashMap::new(),
+            timeouts: self.timeouts,
+        })
+    }
+}
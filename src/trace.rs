@@ -0,0 +1,573 @@
+use crate::protobuf::{put_bytes_field, put_varint_field, FieldReader, FieldValue};
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::kad::kbucket::{Distance as KBucketDistance, Key as KBucketKey};
+use libp2p::request_response::{Codec as RequestResponseCodec, ProtocolName, RequestId};
+use libp2p::{Multiaddr, PeerId};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FormatterResult};
+use tokio::time::{Duration, Instant};
+
+/// Kademlia protocol name used when the DHT network doesn't define any
+pub(crate) const DEFAULT_KAD_PROTOCOL: &str = "/ipfs/kad/1.0.0";
+/// `FIND_NODE` value of the Kademlia `MessageType` enum
+const KAD_FIND_NODE: u64 = 4;
+const KAD_MESSAGE_TYPE_FIELD: u32 = 1;
+const KAD_MESSAGE_KEY_FIELD: u32 = 2;
+const KAD_MESSAGE_CLOSER_PEERS_FIELD: u32 = 8;
+const KAD_PEER_ID_FIELD: u32 = 1;
+const KAD_PEER_ADDRESSES_FIELD: u32 = 2;
+const KAD_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// Concurrent requests of a traced walk, as Kademlia's α
+const WALK_PARALLELISM: usize = 3;
+/// Closest peers a traced walk converges on, as Kademlia's k
+const WALK_WIDTH: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct FindNodeProtocol(pub(crate) String);
+
+impl ProtocolName for FindNodeProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Sends single Kademlia `FIND_NODE` requests, answering inbound ones isn't supported
+#[derive(Debug, Clone, Default)]
+pub struct FindNodeCodec;
+
+#[async_trait]
+impl RequestResponseCodec for FindNodeCodec {
+    type Protocol = FindNodeProtocol;
+    /// Key to find the closest peers of
+    type Request = Vec<u8>;
+    /// Closer peers known by the remote peer, with their addresses
+    type Response = Vec<(PeerId, Vec<Multiaddr>)>;
+
+    async fn read_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        _io: &mut T,
+    ) -> std::io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Inbound FIND_NODE requests aren't supported",
+        ))
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> std::io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let message = read_length_prefixed(io, KAD_MAX_MESSAGE_SIZE).await?;
+
+        decode_closer_peers(&message)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        io: &mut T,
+        key: Self::Request,
+    ) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, encode_find_node_request(&key)).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _protocol: &Self::Protocol,
+        _io: &mut T,
+        _response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Inbound FIND_NODE requests aren't supported",
+        ))
+    }
+}
+
+/// Encodes a Kademlia `FIND_NODE` request of the closest peers of the key
+fn encode_find_node_request(key: &[u8]) -> Vec<u8> {
+    let mut message = Vec::new();
+    put_varint_field(&mut message, KAD_MESSAGE_TYPE_FIELD, KAD_FIND_NODE);
+    put_bytes_field(&mut message, KAD_MESSAGE_KEY_FIELD, key);
+
+    message
+}
+
+fn decode_closer_peers(message: &[u8]) -> Result<Vec<(PeerId, Vec<Multiaddr>)>, String> {
+    let mut closer_peers = Vec::new();
+
+    for field in FieldReader::new(message) {
+        let (KAD_MESSAGE_CLOSER_PEERS_FIELD, FieldValue::Bytes(peer)) = field? else {
+            continue;
+        };
+        let mut peer_id = None;
+        let mut addresses = Vec::new();
+
+        for peer_field in FieldReader::new(peer) {
+            match peer_field? {
+                (KAD_PEER_ID_FIELD, FieldValue::Bytes(bytes)) => {
+                    peer_id = Some(PeerId::from_bytes(bytes).map_err(|error| error.to_string())?)
+                }
+                (KAD_PEER_ADDRESSES_FIELD, FieldValue::Bytes(bytes)) => {
+                    // Unparsable addresses are ignored, as libp2p's Kademlia does
+                    if let Ok(address) = Multiaddr::try_from(bytes.to_vec()) {
+                        addresses.push(address);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(peer_id) = peer_id {
+            closer_peers.push((peer_id, addresses));
+        }
+    }
+
+    Ok(closer_peers)
+}
+
+/// Single `FIND_NODE` request of a traced DHT query
+pub struct QueryHop {
+    pub peer_id: PeerId,
+    /// Peer whose response led to this one, none for the peers known beforehand
+    pub parent: Option<PeerId>,
+    /// Base 2 logarithm of the XOR distance to the target, none if it's the target itself
+    pub distance: Option<u32>,
+    pub latency: Duration,
+    /// Closer peers from the response, empty on failure
+    pub closer_peers: Vec<PeerId>,
+    /// Why the request failed, if it did
+    pub error: Option<String>,
+}
+
+/// Every request of a traced DHT query towards a target peer
+pub struct QueryTrace {
+    pub target: PeerId,
+    pub hops: Vec<QueryHop>,
+    /// Whether the target appeared among the closer peers of a response
+    pub found: bool,
+}
+
+impl QueryTrace {
+    pub(crate) fn new(target: PeerId) -> Self {
+        Self {
+            target,
+            hops: Vec::new(),
+            found: false,
+        }
+    }
+
+    fn format_hops(
+        &self,
+        formatter: &mut Formatter,
+        parent: Option<&PeerId>,
+        depth: usize,
+    ) -> FormatterResult {
+        for hop in self.hops.iter().filter(|hop| hop.parent.as_ref() == parent) {
+            let indentation = "  ".repeat(depth + 1);
+            let distance = hop.distance.map_or_else(
+                || "target".to_owned(),
+                |distance| format!("distance 2^{distance}"),
+            );
+
+            match &hop.error {
+                Some(error_message) => writeln!(
+                    formatter,
+                    "{indentation}- \"{}\" ({distance}) failed after {:?}: {error_message}",
+                    hop.peer_id, hop.latency
+                )?,
+                None => writeln!(
+                    formatter,
+                    "{indentation}- \"{}\" ({distance}) answered in {:?} with {} closer peers",
+                    hop.peer_id,
+                    hop.latency,
+                    hop.closer_peers.len()
+                )?,
+            }
+
+            // Every peer is queried at most once, so the hops can't loop
+            self.format_hops(formatter, Some(&hop.peer_id), depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for QueryTrace {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        writeln!(formatter, "[Target]\n  \"{}\"", &self.target)?;
+        writeln!(
+            formatter,
+            "[Outcome]\n  {} after {} requests",
+            if self.found { "found" } else { "not found" },
+            self.hops.len()
+        )?;
+        writeln!(formatter, "[Hops]")?;
+
+        self.format_hops(formatter, None, 0)
+    }
+}
+
+struct SerializableQueryHop<'a>(&'a QueryHop);
+
+impl Serialize for SerializableQueryHop<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let hop = self.0;
+        let mut state = serializer.serialize_struct("QueryHop", 6)?;
+        state.serialize_field("peer_id", &hop.peer_id.to_string())?;
+        state.serialize_field("parent", &hop.parent.map(|parent| parent.to_string()))?;
+        state.serialize_field("log2_distance", &hop.distance)?;
+        state.serialize_field("latency_ms", &(hop.latency.as_secs_f64() * 1000.0))?;
+        state.serialize_field(
+            "closer_peers",
+            &hop.closer_peers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("error", &hop.error)?;
+
+        state.end()
+    }
+}
+
+impl Serialize for QueryTrace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("QueryTrace", 3)?;
+        state.serialize_field("target", &self.target.to_string())?;
+        state.serialize_field("found", &self.found)?;
+        state.serialize_field(
+            "hops",
+            &self
+                .hops
+                .iter()
+                .map(SerializableQueryHop)
+                .collect::<Vec<_>>(),
+        )?;
+
+        state.end()
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum CandidateState {
+    NotQueried,
+    Waiting,
+    Answered,
+    Failed,
+}
+
+struct Candidate {
+    addresses: Vec<Multiaddr>,
+    parent: Option<PeerId>,
+    distance: KBucketDistance,
+    state: CandidateState,
+}
+
+/// Iterative `FIND_NODE` walk towards a target peer, one request at a time so that every hop
+/// can be traced
+pub(crate) struct TracedWalk {
+    target_key: KBucketKey<PeerId>,
+    candidates: HashMap<PeerId, Candidate>,
+    in_flight: HashMap<RequestId, (PeerId, Instant)>,
+    target_addresses: Option<Vec<Multiaddr>>,
+}
+
+impl TracedWalk {
+    pub(crate) fn new(target: PeerId, seeds: Vec<(PeerId, Vec<Multiaddr>)>) -> Self {
+        let mut walk = Self {
+            target_key: KBucketKey::from(target),
+            candidates: HashMap::new(),
+            in_flight: HashMap::new(),
+            target_addresses: None,
+        };
+
+        for (peer_id, addresses) in seeds {
+            walk.add_candidate(peer_id, addresses, None);
+        }
+
+        walk
+    }
+
+    fn add_candidate(
+        &mut self,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        parent: Option<PeerId>,
+    ) {
+        if peer_id == *self.target_key.preimage() {
+            self.target_addresses
+                .get_or_insert_with(Vec::new)
+                .extend(addresses);
+
+            return;
+        }
+
+        let distance = KBucketKey::from(peer_id).distance(&self.target_key);
+        let candidate = self.candidates.entry(peer_id).or_insert(Candidate {
+            addresses: Vec::new(),
+            parent,
+            distance,
+            state: CandidateState::NotQueried,
+        });
+
+        for address in addresses {
+            if !candidate.addresses.contains(&address) {
+                candidate.addresses.push(address);
+            }
+        }
+    }
+
+    /// Closest candidates still worth converging on, nearest first
+    fn closest(&self) -> Vec<(&PeerId, &Candidate)> {
+        let mut closest = self
+            .candidates
+            .iter()
+            .filter(|(_, candidate)| candidate.state != CandidateState::Failed)
+            .collect::<Vec<_>>();
+        closest.sort_by_key(|(_, candidate)| candidate.distance);
+        closest.truncate(WALK_WIDTH);
+
+        closest
+    }
+
+    /// Peers to send a request to next, with their known addresses
+    pub(crate) fn next_requests(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        self.closest()
+            .into_iter()
+            .filter(|(_, candidate)| candidate.state == CandidateState::NotQueried)
+            .take(WALK_PARALLELISM.saturating_sub(self.in_flight.len()))
+            .map(|(peer_id, candidate)| (*peer_id, candidate.addresses.clone()))
+            .collect()
+    }
+
+    pub(crate) fn on_request_sent(&mut self, request_id: RequestId, peer_id: PeerId) {
+        if let Some(candidate) = self.candidates.get_mut(&peer_id) {
+            candidate.state = CandidateState::Waiting;
+        }
+
+        self.in_flight.insert(request_id, (peer_id, Instant::now()));
+    }
+
+    pub(crate) fn on_response(
+        &mut self,
+        request_id: RequestId,
+        response: Result<Vec<(PeerId, Vec<Multiaddr>)>, String>,
+        query_trace: &mut QueryTrace,
+    ) {
+        let Some((peer_id, sent_at)) = self.in_flight.remove(&request_id) else {
+            return;
+        };
+
+        self.on_peer_response(peer_id, sent_at.elapsed(), response, query_trace);
+    }
+
+    /// Records the hop of an answered or failed request, adding the closer peers as candidates
+    fn on_peer_response(
+        &mut self,
+        peer_id: PeerId,
+        latency: Duration,
+        response: Result<Vec<(PeerId, Vec<Multiaddr>)>, String>,
+        query_trace: &mut QueryTrace,
+    ) {
+        let candidate = self
+            .candidates
+            .get_mut(&peer_id)
+            .expect("Programmatic error: request to unknown candidate!");
+        let mut hop = QueryHop {
+            peer_id,
+            parent: candidate.parent,
+            distance: candidate.distance.ilog2(),
+            latency,
+            closer_peers: Vec::new(),
+            error: None,
+        };
+
+        match response {
+            Ok(closer_peers) => {
+                candidate.state = CandidateState::Answered;
+                hop.closer_peers = closer_peers.iter().map(|(peer_id, _)| *peer_id).collect();
+
+                for (closer_peer_id, addresses) in closer_peers {
+                    self.add_candidate(closer_peer_id, addresses, Some(peer_id));
+                }
+            }
+            Err(error_message) => {
+                candidate.state = CandidateState::Failed;
+                hop.error = Some(error_message);
+            }
+        }
+
+        query_trace.hops.push(hop);
+    }
+
+    /// Addresses of the target, once a response mentioned it
+    pub(crate) fn target_addresses(&self) -> Option<&[Multiaddr]> {
+        self.target_addresses.as_deref()
+    }
+
+    /// Whether the walk converged without finding the target
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.in_flight.is_empty() && self.next_requests().is_empty()
+    }
+}
+
+/// Kademlia protocol names of the `FIND_NODE` requests
+pub(crate) fn find_node_protocols(kad_protocols: &[String]) -> Vec<FindNodeProtocol> {
+    if kad_protocols.is_empty() {
+        return vec![FindNodeProtocol(DEFAULT_KAD_PROTOCOL.to_owned())];
+    }
+
+    kad_protocols
+        .iter()
+        .cloned()
+        .map(FindNodeProtocol)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `FIND_NODE` response with the message type, then a single closer peer with an address and
+    /// an unparsable one
+    const FIND_NODE_RESPONSE: &str = "080442360a260024080112201ce5f00ef6e89374afb625f1ae4c1546d3\
+                                      1234e87e3c3f51a62b91dd6bfa57df1208046c8821cb06767d1202ffff";
+
+    fn peer_id(source: &str) -> PeerId {
+        source.parse().unwrap()
+    }
+
+    fn target() -> PeerId {
+        peer_id("12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2")
+    }
+
+    fn seed() -> PeerId {
+        peer_id("12D3KooWPHzn5X8uGTSZKTHBgfAyMViJZGwpKaej26gXNsLuGKua")
+    }
+
+    fn closer_peer() -> PeerId {
+        peer_id("QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ")
+    }
+
+    fn address() -> Multiaddr {
+        "/ip4/108.136.33.203/tcp/30333".parse().unwrap()
+    }
+
+    #[test]
+    fn encodes_find_node_requests() {
+        let mut expected_request = hex::decode("08041226").unwrap();
+        expected_request.extend(target().to_bytes());
+
+        assert_eq!(
+            encode_find_node_request(&target().to_bytes()),
+            expected_request
+        );
+        assert_eq!(encode_find_node_request(&[]), vec![0x08, 0x04, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn decodes_find_node_responses() {
+        assert_eq!(
+            decode_closer_peers(&hex::decode(FIND_NODE_RESPONSE).unwrap()).unwrap(),
+            vec![(target(), vec![address()])]
+        );
+        assert!(decode_closer_peers(&[]).unwrap().is_empty());
+        assert!(decode_closer_peers(&[0x08, 0x04]).unwrap().is_empty());
+        // Closer peers without a peer ID are skipped
+        assert!(decode_closer_peers(&[0x42, 0x02, 0x12, 0x00])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_find_node_responses() {
+        let response = hex::decode(FIND_NODE_RESPONSE).unwrap();
+
+        // The first 2 bytes are the whole message type field, every longer prefix cuts a field
+        for length in 3..response.len() {
+            assert!(
+                decode_closer_peers(&response[..length]).is_err(),
+                "Truncated to {length} bytes"
+            );
+        }
+
+        // Bad peer ID
+        assert!(decode_closer_peers(&[0x42, 0x04, 0x0a, 0x02, 0xff, 0xff]).is_err());
+        // Unsupported wire type
+        assert!(decode_closer_peers(&[0x43]).is_err());
+        // Unterminated and overflowing varints
+        assert!(decode_closer_peers(&[0x08]).is_err());
+        assert!(decode_closer_peers(&[0x08, 0xff]).is_err());
+        assert!(decode_closer_peers(&[0xff; 11]).is_err());
+    }
+
+    #[test]
+    fn traces_walk_hops() {
+        let mut query_trace = QueryTrace::new(target());
+        let mut walk = TracedWalk::new(target(), vec![(seed(), vec![address()])]);
+
+        assert_eq!(walk.next_requests(), vec![(seed(), vec![address()])]);
+        assert!(walk.target_addresses().is_none());
+
+        walk.on_peer_response(
+            seed(),
+            Duration::from_millis(10),
+            Ok(vec![
+                (closer_peer(), vec![address()]),
+                (target(), vec![address()]),
+            ]),
+            &mut query_trace,
+        );
+
+        let hop = &query_trace.hops[0];
+        assert_eq!(hop.peer_id, seed());
+        assert_eq!(hop.parent, None);
+        assert_eq!(hop.distance, Some(253));
+        assert_eq!(hop.latency, Duration::from_millis(10));
+        assert_eq!(hop.closer_peers, vec![closer_peer(), target()]);
+        assert_eq!(hop.error, None);
+        assert_eq!(walk.target_addresses(), Some(&[address()][..]));
+        // The target itself is never a candidate
+        assert_eq!(walk.next_requests(), vec![(closer_peer(), vec![address()])]);
+
+        walk.on_peer_response(
+            closer_peer(),
+            Duration::from_millis(20),
+            Err("Timeout".to_owned()),
+            &mut query_trace,
+        );
+
+        let hop = &query_trace.hops[1];
+        assert_eq!(hop.peer_id, closer_peer());
+        assert_eq!(hop.parent, Some(seed()));
+        assert_eq!(hop.distance, Some(254));
+        assert!(hop.closer_peers.is_empty());
+        assert_eq!(hop.error.as_deref(), Some("Timeout"));
+        assert!(walk.is_exhausted());
+        assert!(query_trace.to_string().contains(&format!(
+            "  - \"{}\" (distance 2^253) answered in 10ms with 2 closer peers\n    - \"{}\" \
+             (distance 2^254) failed after 20ms: Timeout\n",
+            seed(),
+            closer_peer()
+        )));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::peer::PeerLookupError;
+use futures::executor::block_on;
+use futures::future::Either;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{
+    Boxed as BoxedTransport, OptionalTransport, OrTransport as TCPOrTransport,
+};
+use libp2p::core::upgrade::{SelectUpgrade, Version as UpgradeVersion};
+use libp2p::dns::DnsConfig;
+use libp2p::identity::Keypair;
+use libp2p::mplex::{MaxBufferBehaviour as MultiplexMaxBufferBehaviour, MplexConfig};
+use libp2p::noise::{Keypair as NoiseKeypair, NoiseConfig, X25519Spec};
+use libp2p::quic::tokio::Transport as QUICTransport;
+use libp2p::quic::Config as QUICConfig;
+use libp2p::relay::client::Transport as RelayClientTransport;
+use libp2p::tcp::async_io::Transport as AsyncTCPTransport;
+use libp2p::tcp::Config as TCPConfig;
+use libp2p::websocket::WsConfig;
+use libp2p::yamux::{WindowUpdateMode as YamuxWindowUpdateMode, YamuxConfig};
+use libp2p::{InboundUpgradeExt, OutboundUpgradeExt, PeerId, Transport};
+use std::str::FromStr;
+use tokio::time::Duration;
+
+/// Stream multiplexers negotiated over TCP, WebSocket and relayed connections
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Multiplexing {
+    /// Yamux, falling back to Mplex
+    #[default]
+    Any,
+    Yamux,
+    Mplex,
+}
+
+impl FromStr for Multiplexing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "any" => Ok(Self::Any),
+            "yamux" => Ok(Self::Yamux),
+            "mplex" => Ok(Self::Mplex),
+            _ => Err("Supported multiplexers are any, yamux and mplex.".to_string()),
+        }
+    }
+}
+
+/// Transports of the local node, everything is enabled by default
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    pub tcp: bool,
+    pub quic: bool,
+    /// Dialing through circuit relays (`/p2p-circuit`)
+    pub relay: bool,
+    /// Dialing `/ws` and `/wss` addresses
+    pub websocket: bool,
+    /// Supporting the QUIC draft-29 version besides QUIC v1
+    pub quic_draft_29: bool,
+    pub multiplexing: Multiplexing,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            tcp: true,
+            quic: true,
+            relay: true,
+            websocket: true,
+            quic_draft_29: true,
+            multiplexing: Multiplexing::default(),
+        }
+    }
+}
+
+fn yamux_config() -> YamuxConfig {
+    let mut yamux_config = YamuxConfig::default();
+    yamux_config.set_window_update_mode(YamuxWindowUpdateMode::on_read());
+
+    yamux_config
+}
+
+fn mplex_config() -> MplexConfig {
+    let mut mplex_config = MplexConfig::new();
+    mplex_config.set_max_buffer_behaviour(MultiplexMaxBufferBehaviour::Block);
+    mplex_config.set_max_buffer_size(usize::MAX);
+
+    mplex_config
+}
+
+fn optional_transport<T>(transport: Option<T>) -> OptionalTransport<T> {
+    transport.map_or_else(OptionalTransport::none, OptionalTransport::some)
+}
+
+/// Authenticates the connections of a stream transport with Noise, then multiplexes them
+fn upgrade_stream_transport<T>(
+    transport: T,
+    local_key: &Keypair,
+    multiplexing: Multiplexing,
+) -> BoxedTransport<(PeerId, StreamMuxerBox)>
+where
+    T: Transport + Send + Unpin + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    let authentication_config = {
+        let noise_keypair_spec = NoiseKeypair::<X25519Spec>::new()
+            .into_authentic(local_key)
+            .expect("Programmatic error: incompatible keypair spec!");
+
+        NoiseConfig::xx(noise_keypair_spec).into_authenticated()
+    };
+    let transport = transport
+        .upgrade(UpgradeVersion::V1)
+        .authenticate(authentication_config);
+
+    match multiplexing {
+        Multiplexing::Any => transport
+            .multiplex(
+                SelectUpgrade::new(yamux_config(), mplex_config())
+                    .map_inbound(StreamMuxerBox::new)
+                    .map_outbound(StreamMuxerBox::new),
+            )
+            .timeout(Duration::from_secs(10))
+            .boxed(),
+        Multiplexing::Yamux => transport
+            .multiplex(yamux_config())
+            .timeout(Duration::from_secs(10))
+            .map(|(peer_id, stream_muxer), _| (peer_id, StreamMuxerBox::new(stream_muxer)))
+            .boxed(),
+        Multiplexing::Mplex => transport
+            .multiplex(mplex_config())
+            .timeout(Duration::from_secs(10))
+            .map(|(peer_id, stream_muxer), _| (peer_id, StreamMuxerBox::new(stream_muxer)))
+            .boxed(),
+    }
+}
+
+pub(crate) fn create_transport(
+    config: &TransportConfig,
+    local_key: &Keypair,
+    relay_transport: RelayClientTransport,
+) -> Result<BoxedTransport<(PeerId, StreamMuxerBox)>, PeerLookupError> {
+    if !(config.tcp || config.quic || config.relay || config.websocket) {
+        return Err(PeerLookupError::FailedToCreateTransport(
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Every transport is disabled",
+            ),
+        ));
+    }
+
+    let tcp_config = || TCPConfig::new().port_reuse(true).nodelay(true);
+    // WebSocket resolves its own DNS names, keeping them for the TLS server name of "/wss", and
+    // comes first as it rejects other addresses right away, unlike the DNS layer
+    let websocket_transport = config
+        .websocket
+        .then(|| block_on(DnsConfig::system(AsyncTCPTransport::new(tcp_config()))))
+        .transpose()
+        .map_err(PeerLookupError::FailedToCreateTransport)?
+        .map(|transport| {
+            upgrade_stream_transport(WsConfig::new(transport), local_key, config.multiplexing)
+        });
+    let relay_and_tcp_transport = (config.relay || config.tcp).then(|| {
+        upgrade_stream_transport(
+            TCPOrTransport::new(
+                optional_transport(config.relay.then_some(relay_transport)),
+                optional_transport(config.tcp.then(|| AsyncTCPTransport::new(tcp_config()))),
+            ),
+            local_key,
+            config.multiplexing,
+        )
+    });
+    let quic_transport = config.quic.then(|| {
+        let mut quic_config = QUICConfig::new(local_key);
+        quic_config.support_draft_29 = config.quic_draft_29;
+
+        QUICTransport::new(quic_config)
+    });
+    // A single DNS layer, so that relayed "/dns*/.../p2p-circuit" addresses are resolved too
+    let resolving_transport = block_on(DnsConfig::system(TCPOrTransport::new(
+        optional_transport(quic_transport),
+        optional_transport(relay_and_tcp_transport),
+    )))
+    .map_err(PeerLookupError::FailedToCreateTransport)?
+    .map(|either_output, _| match either_output {
+        Either::Left((peer_id, stream_muxer)) => (peer_id, StreamMuxerBox::new(stream_muxer)),
+        Either::Right((peer_id, stream_muxer)) => (peer_id, stream_muxer),
+    });
+
+    Ok(
+        TCPOrTransport::new(optional_transport(websocket_transport), resolving_transport)
+            .map(|either_output, _| match either_output {
+                Either::Left(output) | Either::Right(output) => output,
+            })
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+            .boxed(),
+    )
+}
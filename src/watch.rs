@@ -0,0 +1,740 @@
+use crate::batch::BatchTarget;
+use crate::logging::log_debug;
+use crate::metrics::WatchMetrics;
+use crate::networks::DHTNetwork;
+use crate::node::{create_swarm, NodeConfig, PeerLookupBehaviour, PeerLookupBehaviourEvent};
+use crate::peer::{LookupTimeouts, PeerInfo, PeerLookupError};
+use crate::ping::PingStatistics;
+use futures::StreamExt;
+use libp2p::core::ConnectedPoint;
+use libp2p::identify::{Event as IdentityEvent, Info as IdentityInfo};
+use libp2p::kad::{
+    GetClosestPeersError as KademliaGetClosestPeersError,
+    GetClosestPeersOk as KademliaGetClosestPeersOk, KademliaEvent, QueryId as KademliaQueryId,
+    QueryResult as KademliaQueryResult,
+};
+use libp2p::ping::Success as PingSuccess;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::{DialError as SwarmDialError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt::{Debug, Display, Formatter, Result as FormatterResult};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+
+/// Periodic summary of a watched peer
+pub struct WatchStatus {
+    /// Age of the current connection, `None` while disconnected
+    pub connected_for: Option<Duration>,
+    /// Share of the watch duration the peer was connected
+    pub availability: f64,
+    pub ping: PingStatistics,
+}
+
+/// Change or observation about a watched peer
+pub enum WatchEvent {
+    /// Connection established, initially or after a disconnection (e.g. a restart)
+    Connected {
+        established_in: Duration,
+    },
+    /// First identification of the peer
+    Identified(PeerInfo),
+    AgentVersionChanged {
+        previous: String,
+        current: String,
+    },
+    ProtocolsChanged {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    ListenAddressesChanged {
+        added: Vec<Multiaddr>,
+        removed: Vec<Multiaddr>,
+    },
+    /// Last connection closed, a new lookup starts on the next interval
+    Disconnected {
+        cause: Option<String>,
+    },
+    /// Lookup (DHT walk or dial) of a disconnected peer failed
+    LookupFailed(PeerLookupError),
+    Status(WatchStatus),
+}
+
+impl WatchEvent {
+    /// Short machine-readable name of the event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Connected { .. } => "connected",
+            Self::Identified(_) => "identified",
+            Self::AgentVersionChanged { .. } => "agent_version_changed",
+            Self::ProtocolsChanged { .. } => "protocols_changed",
+            Self::ListenAddressesChanged { .. } => "listen_addresses_changed",
+            Self::Disconnected { .. } => "disconnected",
+            Self::LookupFailed(_) => "lookup_failed",
+            Self::Status(_) => "status",
+        }
+    }
+}
+
+fn write_changes<T: Display>(
+    formatter: &mut Formatter,
+    added: &[T],
+    removed: &[T],
+) -> FormatterResult {
+    for item in added {
+        write!(formatter, "\n  + \"{item}\"")?;
+    }
+
+    for item in removed {
+        write!(formatter, "\n  - \"{item}\"")?;
+    }
+
+    Ok(())
+}
+
+impl Display for WatchEvent {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatterResult {
+        match self {
+            Self::Connected { established_in } => {
+                write!(formatter, "connected in {established_in:?}")
+            }
+            Self::Identified(peer_info) => {
+                write!(formatter, "identified\n********\n{peer_info}********")
+            }
+            Self::AgentVersionChanged { previous, current } => {
+                write!(
+                    formatter,
+                    "agent version changed from \"{previous}\" to \"{current}\""
+                )
+            }
+            Self::ProtocolsChanged { added, removed } => {
+                write!(formatter, "protocols changed")?;
+                write_changes(formatter, added, removed)
+            }
+            Self::ListenAddressesChanged { added, removed } => {
+                write!(formatter, "listen addresses changed")?;
+                write_changes(formatter, added, removed)
+            }
+            Self::Disconnected { cause: Some(cause) } => {
+                write!(formatter, "disconnected ({cause})")
+            }
+            Self::Disconnected { cause: None } => write!(formatter, "disconnected"),
+            Self::LookupFailed(error) => write!(formatter, "lookup failed: {error}"),
+            Self::Status(status) => {
+                match status.connected_for {
+                    Some(connected_for) => write!(formatter, "up for {connected_for:?}")?,
+                    None => write!(formatter, "down")?,
+                }
+
+                write!(
+                    formatter,
+                    ", available {:.1}% of the time",
+                    status.availability * 100.0
+                )?;
+
+                if let (Some(min), Some(average), Some(max)) =
+                    (status.ping.min(), status.ping.average(), status.ping.max())
+                {
+                    write!(
+                        formatter,
+                        ", ping RTT min/avg/max {min:?}/{average:?}/{max:?}"
+                    )?;
+
+                    if let Some(jitter) = status.ping.jitter() {
+                        write!(formatter, " (jitter {jitter:?})")?;
+                    }
+                }
+
+                if status.ping.failures > 0 {
+                    write!(formatter, ", {} ping failure(s)", status.ping.failures)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Event about a single watch target
+pub struct WatchRecord {
+    pub target: BatchTarget,
+    pub at: SystemTime,
+    pub event: WatchEvent,
+}
+
+fn as_millis_f64(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Serialize for WatchRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let timestamp_ms = self
+            .at
+            .duration_since(UNIX_EPOCH)
+            .map(as_millis_f64)
+            .unwrap_or_default();
+        let mut state = serializer.serialize_struct("WatchRecord", 8)?;
+        state.serialize_field("target", &self.target.to_string())?;
+        state.serialize_field("timestamp_ms", &timestamp_ms)?;
+        state.serialize_field("event", self.event.kind())?;
+
+        match &self.event {
+            WatchEvent::Connected { established_in } => {
+                state.serialize_field("established_in_ms", &as_millis_f64(*established_in))?;
+            }
+            WatchEvent::Identified(peer_info) => {
+                state.serialize_field("peer", peer_info)?;
+            }
+            WatchEvent::AgentVersionChanged { previous, current } => {
+                state.serialize_field("previous", previous)?;
+                state.serialize_field("current", current)?;
+            }
+            WatchEvent::ProtocolsChanged { added, removed } => {
+                state.serialize_field("added", added)?;
+                state.serialize_field("removed", removed)?;
+            }
+            WatchEvent::ListenAddressesChanged { added, removed } => {
+                let to_strings = |addresses: &[Multiaddr]| {
+                    addresses
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                };
+                state.serialize_field("added", &to_strings(added))?;
+                state.serialize_field("removed", &to_strings(removed))?;
+            }
+            WatchEvent::Disconnected { cause } => {
+                state.serialize_field("cause", cause)?;
+            }
+            WatchEvent::LookupFailed(error) => {
+                state.serialize_field("error", &error.to_string())?;
+            }
+            WatchEvent::Status(status) => {
+                state.serialize_field(
+                    "connected_for_ms",
+                    &status.connected_for.map(as_millis_f64),
+                )?;
+                state.serialize_field("availability", &status.availability)?;
+                state.serialize_field("ping", &status.ping)?;
+            }
+        }
+
+        state.end()
+    }
+}
+
+/// Items of `current` missing from `previous`, then items of `previous` missing from `current`
+fn diff<T: Clone + PartialEq>(previous: &[T], current: &[T]) -> (Vec<T>, Vec<T>) {
+    let added = current
+        .iter()
+        .filter(|item| !previous.contains(item))
+        .cloned()
+        .collect();
+    let removed = previous
+        .iter()
+        .filter(|item| !current.contains(item))
+        .cloned()
+        .collect();
+
+    (added, removed)
+}
+
+enum WatchPhase {
+    Idle,
+    WalkingDht(KademliaQueryId),
+    Dialing,
+    Connected {
+        since: Instant,
+        established_in: Duration,
+    },
+}
+
+struct WatchedPeer {
+    target: BatchTarget,
+    peer_id: Option<PeerId>,
+    phase: WatchPhase,
+    phase_started_at: Instant,
+    identity: Option<IdentityInfo>,
+    /// Whether the current connection already carried an identify message
+    identified_on_connection: bool,
+    watched_since: Instant,
+    connected_for: Duration,
+    ping: PingStatistics,
+}
+
+impl WatchedPeer {
+    fn new(target: BatchTarget) -> Self {
+        let now = Instant::now();
+
+        Self {
+            peer_id: target.peer_id(),
+            target,
+            phase: WatchPhase::Idle,
+            phase_started_at: now,
+            identity: None,
+            identified_on_connection: false,
+            watched_since: now,
+            connected_for: Duration::ZERO,
+            ping: PingStatistics::default(),
+        }
+    }
+
+    fn enter(&mut self, phase: WatchPhase) {
+        if let WatchPhase::Connected { since, .. } = self.phase {
+            self.connected_for += since.elapsed();
+        }
+
+        self.phase = phase;
+        self.phase_started_at = Instant::now();
+        self.identified_on_connection = false;
+    }
+
+    fn is_peer(&self, peer_id: &PeerId) -> bool {
+        self.peer_id.as_ref() == Some(peer_id)
+    }
+
+    fn is_dialed_by(&self, peer_id: &PeerId, endpoint: &ConnectedPoint) -> bool {
+        match (&self.phase, self.peer_id, &self.target) {
+            (WatchPhase::Connected { .. }, ..) => false,
+            (_, Some(target_peer_id), _) => target_peer_id == *peer_id,
+            (WatchPhase::Dialing, None, BatchTarget::Address(address)) => {
+                matches!(endpoint, ConnectedPoint::Dialer { address: dialed, .. } if dialed == address)
+            }
+            _ => false,
+        }
+    }
+
+    fn is_failed_by(&self, peer_id: Option<&PeerId>, error: &SwarmDialError) -> bool {
+        if !matches!(self.phase, WatchPhase::Dialing) {
+            return false;
+        }
+
+        match (peer_id, &self.target) {
+            (Some(peer_id), _) => self.is_peer(peer_id),
+            (None, BatchTarget::Address(address)) => match error {
+                SwarmDialError::Transport(errors) => errors
+                    .iter()
+                    .any(|(failed_address, _)| failed_address == address),
+                _ => false,
+            },
+            (None, BatchTarget::PeerId(_)) => false,
+        }
+    }
+
+    /// Status over the past interval, starting a new one
+    fn take_status(&mut self) -> WatchStatus {
+        let connected_for = match self.phase {
+            WatchPhase::Connected { since, .. } => Some(since.elapsed()),
+            _ => None,
+        };
+        let watched_for = self.watched_since.elapsed().as_secs_f64();
+        let availability = if watched_for > 0.0 {
+            (self.connected_for + connected_for.unwrap_or_default()).as_secs_f64() / watched_for
+        } else {
+            0.0
+        };
+
+        WatchStatus {
+            connected_for,
+            availability,
+            ping: std::mem::take(&mut self.ping),
+        }
+    }
+
+    /// Events telling how the peer changed since its last identification
+    fn on_identified(&mut self, info: IdentityInfo) -> Vec<WatchEvent> {
+        let Some(previous) = self.identity.replace(info.clone()) else {
+            let (established_in, identified_in) = match self.phase {
+                WatchPhase::Connected {
+                    since,
+                    established_in,
+                } => (established_in, since.elapsed()),
+                _ => (Duration::ZERO, Duration::ZERO),
+            };
+
+            return vec![WatchEvent::Identified(PeerInfo::new(
+                info,
+                established_in,
+                identified_in,
+            ))];
+        };
+        let mut events = Vec::new();
+
+        if previous.agent_version != info.agent_version {
+            events.push(WatchEvent::AgentVersionChanged {
+                previous: previous.agent_version,
+                current: info.agent_version,
+            });
+        }
+
+        let (added, removed) = diff(&previous.protocols, &info.protocols);
+
+        if !added.is_empty() || !removed.is_empty() {
+            events.push(WatchEvent::ProtocolsChanged { added, removed });
+        }
+
+        let (added, removed) = diff(&previous.listen_addrs, &info.listen_addrs);
+
+        if !added.is_empty() || !removed.is_empty() {
+            events.push(WatchEvent::ListenAddressesChanged { added, removed });
+        }
+
+        events
+    }
+}
+
+/// Keeps connections to many peers alive over a single swarm, re-identifying them periodically
+/// and looking them up again once disconnected
+pub struct WatchClient {
+    swarm: Swarm<PeerLookupBehaviour>,
+    peers: Vec<WatchedPeer>,
+    interval: Duration,
+    timeouts: LookupTimeouts,
+    metrics: Option<Arc<WatchMetrics>>,
+}
+
+impl WatchClient {
+    /// Starts configuring a watch client for the given targets
+    pub fn builder(targets: Vec<BatchTarget>) -> WatchClientBuilder {
+        WatchClientBuilder::new(targets)
+    }
+
+    fn start_lookup(&mut self, index: usize) -> Option<PeerLookupError> {
+        let peer = &mut self.peers[index];
+        let dial_opts = match (&peer.target, peer.peer_id) {
+            (BatchTarget::Address(address), Some(peer_id)) => DialOpts::peer_id(peer_id)
+                .addresses(vec![address.clone()])
+                .condition(PeerCondition::Always)
+                .build(),
+            (BatchTarget::Address(address), None) => {
+                DialOpts::unknown_peer_id().address(address.clone()).build()
+            }
+            (BatchTarget::PeerId(peer_id), _) => {
+                let query_id = self.swarm.behaviour_mut().dht.get_closest_peers(*peer_id);
+                peer.enter(WatchPhase::WalkingDht(query_id));
+
+                return None;
+            }
+        };
+
+        match self.swarm.dial(dial_opts) {
+            Ok(()) => {
+                peer.enter(WatchPhase::Dialing);
+
+                None
+            }
+            Err(error) => Some(error.into()),
+        }
+    }
+
+    fn on_dht_walked(
+        &mut self,
+        index: usize,
+        query_result: Result<KademliaGetClosestPeersOk, KademliaGetClosestPeersError>,
+    ) -> Option<PeerLookupError> {
+        let target_peer_id = self.peers[index]
+            .peer_id
+            .expect("Programmatic error: DHT lookup without peer ID!");
+        let (peers, timed_out) = match query_result {
+            Ok(KademliaGetClosestPeersOk { peers, .. }) => (peers, false),
+            Err(KademliaGetClosestPeersError::Timeout { peers, .. }) => (peers, true),
+        };
+
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_dht_query_duration(
+                &self.peers[index].target.to_string(),
+                self.peers[index].phase_started_at.elapsed(),
+            );
+        }
+
+        if !peers.contains(&target_peer_id) {
+            self.peers[index].enter(WatchPhase::Idle);
+
+            return Some(if timed_out {
+                PeerLookupError::DhtQueryTimeout(self.timeouts.dht_query)
+            } else {
+                PeerLookupError::FailedToFindPeerOnDht
+            });
+        }
+
+        if let Err(error) = self.swarm.dial(target_peer_id) {
+            self.peers[index].enter(WatchPhase::Idle);
+
+            return Some(error.into());
+        }
+
+        self.peers[index].enter(WatchPhase::Dialing);
+
+        None
+    }
+
+    fn handle_event<THandlerErr: Debug>(
+        &mut self,
+        event: SwarmEvent<PeerLookupBehaviourEvent, THandlerErr>,
+        on_record: &mut impl FnMut(WatchRecord),
+    ) {
+        let mut events = Vec::new();
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_swarm_event(&event);
+        }
+
+        match event {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                established_in,
+                ..
+            } => {
+                for (index, peer) in self.peers.iter_mut().enumerate() {
+                    if peer.is_dialed_by(&peer_id, &endpoint) {
+                        peer.peer_id = Some(peer_id);
+                        peer.enter(WatchPhase::Connected {
+                            since: Instant::now(),
+                            established_in,
+                        });
+                        events.push((index, WatchEvent::Connected { established_in }));
+                    }
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                cause,
+                ..
+            } => {
+                for (index, peer) in self.peers.iter_mut().enumerate() {
+                    if peer.is_peer(&peer_id) && matches!(peer.phase, WatchPhase::Connected { .. })
+                    {
+                        peer.enter(WatchPhase::Idle);
+                        events.push((
+                            index,
+                            WatchEvent::Disconnected {
+                                cause: cause.as_ref().map(|cause| format!("{cause:?}")),
+                            },
+                        ));
+                    }
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error } => {
+                if let Some(index) = self
+                    .peers
+                    .iter()
+                    .position(|peer| peer.is_failed_by(peer_id.as_ref(), &error))
+                {
+                    self.peers[index].enter(WatchPhase::Idle);
+                    events.push((index, WatchEvent::LookupFailed(error.into())));
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Dht(
+                KademliaEvent::OutboundQueryProgressed {
+                    id,
+                    result: KademliaQueryResult::GetClosestPeers(query_result),
+                    ..
+                },
+            )) => {
+                if let Some(index) = self.peers.iter().position(
+                    |peer| matches!(peer.phase, WatchPhase::WalkingDht(query_id) if query_id == id),
+                ) {
+                    if let Some(error) = self.on_dht_walked(index, query_result) {
+                        events.push((index, WatchEvent::LookupFailed(error)));
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Identify(
+                IdentityEvent::Received { peer_id, info },
+            )) => {
+                for (index, peer) in self.peers.iter_mut().enumerate() {
+                    if peer.is_peer(&peer_id) {
+                        if let (Some(metrics), WatchPhase::Connected { since, .. }, false) =
+                            (&self.metrics, &peer.phase, peer.identified_on_connection)
+                        {
+                            metrics.observe_identify_latency(
+                                &peer.target.to_string(),
+                                since.elapsed(),
+                            );
+                        }
+
+                        peer.identified_on_connection = true;
+                        events.extend(
+                            peer.on_identified(info.clone())
+                                .into_iter()
+                                .map(|event| (index, event)),
+                        );
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(PeerLookupBehaviourEvent::Ping(event)) => {
+                for peer in self
+                    .peers
+                    .iter_mut()
+                    .filter(|peer| peer.is_peer(&event.peer))
+                {
+                    if let (Some(metrics), Ok(PingSuccess::Ping { rtt })) =
+                        (&self.metrics, &event.result)
+                    {
+                        metrics.observe_ping_rtt(&peer.target.to_string(), *rtt);
+                    }
+
+                    peer.ping.record(&event);
+                }
+            }
+            other_event => log_debug!("{other_event:?}"),
+        }
+
+        self.emit(events, on_record);
+    }
+
+    fn emit(&self, events: Vec<(usize, WatchEvent)>, on_record: &mut impl FnMut(WatchRecord)) {
+        for (index, event) in events {
+            if let Some(metrics) = &self.metrics {
+                metrics.record_watch_event(&self.peers[index].target.to_string(), &event);
+            }
+
+            on_record(WatchRecord {
+                target: self.peers[index].target.clone(),
+                at: SystemTime::now(),
+                event,
+            });
+        }
+    }
+
+    fn expire_dials(&mut self, on_record: &mut impl FnMut(WatchRecord)) {
+        let dial_timeout = self.timeouts.dial;
+        let mut events = Vec::new();
+
+        for (index, peer) in self.peers.iter_mut().enumerate() {
+            if matches!(peer.phase, WatchPhase::Dialing)
+                && peer.phase_started_at.elapsed() >= dial_timeout
+            {
+                peer.enter(WatchPhase::Idle);
+                events.push((
+                    index,
+                    WatchEvent::LookupFailed(PeerLookupError::DialTimeout(dial_timeout)),
+                ));
+            }
+        }
+
+        self.emit(events, on_record);
+    }
+
+    /// Reports the status of every peer over the past interval, then looks up the disconnected
+    /// ones again
+    fn on_interval(&mut self, report_status: bool, on_record: &mut impl FnMut(WatchRecord)) {
+        let mut events = Vec::new();
+
+        for index in 0..self.peers.len() {
+            if report_status {
+                events.push((index, WatchEvent::Status(self.peers[index].take_status())));
+            }
+
+            if matches!(self.peers[index].phase, WatchPhase::Idle) {
+                if let Some(error) = self.start_lookup(index) {
+                    events.push((index, WatchEvent::LookupFailed(error)));
+                }
+            }
+        }
+
+        self.emit(events, on_record);
+    }
+
+    /// Watches the peers until the future is dropped, reporting every event through `on_record`
+    pub async fn do_watch(mut self, mut on_record: impl FnMut(WatchRecord)) {
+        let mut watch_interval = interval(self.interval);
+        watch_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut deadline_check = interval(Duration::from_millis(250));
+        deadline_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut first_interval = true;
+
+        if let Some(metrics) = &self.metrics {
+            for peer in &self.peers {
+                metrics.add_target(&peer.target.to_string());
+            }
+        }
+
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_event(event, &mut on_record),
+                _ = watch_interval.tick() => {
+                    self.on_interval(!first_interval, &mut on_record);
+                    first_interval = false;
+                }
+                _ = deadline_check.tick() => self.expire_dials(&mut on_record),
+            }
+        }
+    }
+}
+
+/// Explicit configuration of a [`WatchClient`], independent of any CLI parsing
+#[derive(Debug, Clone)]
+pub struct WatchClientBuilder {
+    targets: Vec<BatchTarget>,
+    dht_network: Option<DHTNetwork>,
+    node_config: NodeConfig,
+    timeouts: LookupTimeouts,
+    interval: Duration,
+    metrics: Option<Arc<WatchMetrics>>,
+}
+
+impl WatchClientBuilder {
+    pub fn new(targets: Vec<BatchTarget>) -> Self {
+        Self {
+            targets,
+            dht_network: None,
+            node_config: NodeConfig::default(),
+            timeouts: LookupTimeouts::default(),
+            interval: Duration::from_secs(60),
+            metrics: None,
+        }
+    }
+
+    /// DHT network used to find the targets given by peer ID
+    pub fn dht_network(mut self, dht_network: DHTNetwork) -> Self {
+        self.dht_network = Some(dht_network);
+
+        self
+    }
+
+    pub fn node_config(mut self, node_config: NodeConfig) -> Self {
+        self.node_config = node_config;
+
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: LookupTimeouts) -> Self {
+        self.timeouts = timeouts;
+
+        self
+    }
+
+    /// Interval of re-identifying the peers and reporting their status
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_secs(1));
+
+        self
+    }
+
+    /// Records the watch into Prometheus metrics
+    pub fn metrics(mut self, metrics: Arc<WatchMetrics>) -> Self {
+        self.metrics = Some(metrics);
+
+        self
+    }
+
+    pub fn build(mut self) -> Result<WatchClient, PeerLookupError> {
+        self.node_config.identify_interval = Some(self.interval);
+
+        Ok(WatchClient {
+            swarm: create_swarm(
+                &self.node_config,
+                self.dht_network.as_ref(),
+                self.timeouts.dht_query,
+            )?,
+            peers: self.targets.into_iter().map(WatchedPeer::new).collect(),
+            interval: self.interval,
+            timeouts: self.timeouts,
+            metrics: self.metrics,
+        })
+    }
+}